@@ -8,7 +8,7 @@ use solver::simulation_environment::SimulationEnvironment;
 use solver::simulator_bb::{BitBoards, SimulatorBB};
 use solver::solution_explorer::SolutionExplorer;
 use solver::trace_mode::TraceMode;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 fn setup_input() -> (
     SimulationEnvironment,
@@ -30,9 +30,13 @@ fn setup_input() -> (
         is_chance_mode: false,
         minimum_puyo_num_for_popping: 3,
         max_trace_num: 5,
+        min_trace_num: 0,
         trace_mode: TraceMode::Normal,
         popping_leverage: 1.0,
         chain_leverage: 7.0,
+        popping_coefficient: None,
+        chain_coefficient_table: None,
+        special_puyo_strengths: HashMap::new(),
     };
     let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
     let mut id_counter = 0;
@@ -76,6 +80,18 @@ fn setup_input() -> (
         sub_attr: None,
         main_sub_ratio: None,
         counting_bonus: None,
+        next_expectation: None,
+        team: None,
+        enemy: None,
+        pareto_objectives: None,
+        scoring: None,
+        constraints: Vec::new(),
+        required_coords: HashSet::new(),
+        forbidden_coords: HashSet::new(),
+        drawable_path_only: false,
+        trace_modes: None,
+        board_transforms: Vec::new(),
+        preference_bucket_widths: HashMap::new(),
     };
 
     return (
@@ -114,6 +130,19 @@ fn solve_all_traces() {
     explorer.solve_all_traces();
 }
 
+fn solve_all_traces_in_parallel() {
+    let (environment, boost_area_coord_set, field, next_puyos, _trace_coords, exploration_target) =
+        setup_input();
+    let explorer = SolutionExplorer::new(
+        &exploration_target,
+        &environment,
+        &boost_area_coord_set,
+        &field,
+        &next_puyos,
+    );
+    explorer.solve_all_traces_in_parallel();
+}
+
 fn do_chains_benchmark(c: &mut Criterion) {
     let (environment, boost_area_coord_set, field, next_puyos, trace_coords, _exploration_target) =
         setup_input();
@@ -150,6 +179,9 @@ fn solve_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("explorer");
     group.sample_size(30);
     group.bench_function("solve_all_traces", |b| b.iter(|| solve_all_traces()));
+    group.bench_function("solve_all_traces_in_parallel", |b| {
+        b.iter(solve_all_traces_in_parallel)
+    });
 }
 
 criterion_group!(benches, do_chains_benchmark, solve_benchmark);
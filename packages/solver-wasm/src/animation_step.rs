@@ -0,0 +1,38 @@
+use num_derive::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+use crate::{puyo_coord::PuyoCoord, puyo_type::PuyoType};
+
+/** アニメーションステップの種類 */
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, FromPrimitive, ToPrimitive, Serialize_repr, Deserialize_repr,
+)]
+#[repr(u8)]
+pub enum AnimationStepKind {
+    /** なぞる前の初期状態 */
+    Initial = 0,
+    /** なぞり消し(あるいは塗り替えとそれによる消去)の直後 */
+    Traced = 1,
+    /** 繋がったぷよが消えた直後 */
+    Popped = 2,
+    /** フィールド内でぷよが落ちた直後 */
+    DroppedInField = 3,
+    /** ネクストぷよがフィールドに落ちた直後 */
+    NextDropped = 4,
+}
+
+/** アニメーションのステップ情報 */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnimationStep {
+    /** ステップの種類 */
+    pub kind: AnimationStepKind,
+    /** このステップまでに発生した連鎖数 */
+    pub chain_num: u32,
+    /** このステップで消えたぷよの座標リスト */
+    pub popped_coords: Vec<PuyoCoord>,
+    /** フィールドぷよ8x6個分 */
+    pub field: [[Option<PuyoType>; PuyoCoord::X_NUM as usize]; PuyoCoord::Y_NUM as usize],
+    /** ネクストぷよ8個分 */
+    pub next_puyos: [Option<PuyoType>; PuyoCoord::X_NUM as usize],
+}
@@ -0,0 +1,544 @@
+use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+use crate::{
+    puyo::{FieldAndNext, Puyo},
+    puyo_attr::{PuyoAttr, POPPABLE_ATTRS},
+    puyo_coord::PuyoCoord,
+    puyo_type::{convert_type, get_attr, is_colored_type, is_plus_type, PuyoType},
+    random::SeededRandom,
+};
+
+/** 盤面変換 (なぞる前に盤面を変えるスキル効果) の種類 */
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, FromPrimitive, ToPrimitive, Serialize_repr, Deserialize_repr,
+)]
+#[repr(u8)]
+pub enum BoardTransformKind {
+    /** 縦一列 (index の列) のぷよを to_attr に変える */
+    ColumnToAttr = 1,
+    /** 横一列 (index の行) のぷよを to_attr に変える */
+    RowToAttr = 2,
+    /** from_attr のぷよを全て to_attr に変える (ハートを色ぷよに変えるなど) */
+    AttrToAttr = 3,
+    /** coords のぷよを to_attr に変える */
+    CoordsToAttr = 4,
+    /** to_attr 以外の色ぷよから乱数で count 個選んで to_attr に変える */
+    RandomToAttr = 5,
+    /** coords の色ぷよをプラスぷよにする */
+    CoordsToPlus = 6,
+    /** プラスでない色ぷよから乱数で count 個選んでプラスぷよにする */
+    RandomToPlus = 7,
+    /** おじゃまぷよを全て消す。消えたマスには上のぷよが落ち、その列のネクストも落ちてくる。 */
+    RemoveOjama = 8,
+}
+
+/** 盤面変換 */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoardTransform {
+    /** 盤面変換の種類 */
+    pub kind: BoardTransformKind,
+    /** 列 (X座標) か行 (Y座標) */
+    #[serde(default)]
+    pub index: Option<u8>, // ::ColumnToAttr, ::RowToAttr
+    /** 変える前の属性 */
+    #[serde(default)]
+    pub from_attr: Option<PuyoAttr>, // ::AttrToAttr
+    /** 変えた後の属性 */
+    #[serde(default)]
+    pub to_attr: Option<PuyoAttr>, // ::ColumnToAttr, ::RowToAttr, ::AttrToAttr, ::CoordsToAttr, ::RandomToAttr
+    /** 対象の座標のリスト */
+    #[serde(default)]
+    pub coords: Vec<PuyoCoord>, // ::CoordsToAttr, ::CoordsToPlus
+    /** 乱数で選ぶぷよの数。候補がそれより少なければ候補を全て変える。 */
+    #[serde(default)]
+    pub count: u32, // ::RandomToAttr, ::RandomToPlus
+    /** 乱数のシード */
+    #[serde(default)]
+    pub seed: u64, // ::RandomToAttr, ::RandomToPlus
+}
+
+impl BoardTransform {
+    /// 探索を始める前に盤面変換の設定が正しいか検証する。
+    pub fn validate(&self) -> Result<(), String> {
+        let needs_to_attr = matches!(
+            self.kind,
+            BoardTransformKind::ColumnToAttr
+                | BoardTransformKind::RowToAttr
+                | BoardTransformKind::AttrToAttr
+                | BoardTransformKind::CoordsToAttr
+                | BoardTransformKind::RandomToAttr
+        );
+        if needs_to_attr && self.poppable_to_attr().is_none() {
+            return Err(format!("{:?} requires a poppable to_attr", self.kind));
+        }
+        match self.kind {
+            BoardTransformKind::ColumnToAttr => match self.index {
+                Some(x) if x < PuyoCoord::X_NUM => {}
+                _ => return Err(format!("{:?} requires index less than 8", self.kind)),
+            },
+            BoardTransformKind::RowToAttr => match self.index {
+                Some(y) if y < PuyoCoord::Y_NUM => {}
+                _ => return Err(format!("{:?} requires index less than 6", self.kind)),
+            },
+            BoardTransformKind::AttrToAttr => {
+                if self.from_attr.is_none() {
+                    return Err(format!("{:?} requires from_attr", self.kind));
+                }
+            }
+            BoardTransformKind::CoordsToAttr | BoardTransformKind::CoordsToPlus => {
+                if let Some(c) = self
+                    .coords
+                    .iter()
+                    .find(|c| !PuyoCoord::is_valid_xy(c.x, c.y))
+                {
+                    return Err(format!("({}, {}) is out of the field", c.x, c.y));
+                }
+            }
+            BoardTransformKind::RandomToAttr
+            | BoardTransformKind::RandomToPlus
+            | BoardTransformKind::RemoveOjama => {}
+        }
+        Ok(())
+    }
+
+    /// 盤面を変換する。ぷよのIDは変えない。
+    /// 必要な設定が無かったり、弾けない属性や盤面の外を指していたりすれば、その変換 (座標のリストならその座標) は無視する。
+    /// 設定の誤りは validate で検出する。
+    pub fn apply(&self, board: &mut FieldAndNext) {
+        match self.kind {
+            BoardTransformKind::ColumnToAttr => {
+                let (Some(x), Some(to_attr)) = (self.index, self.poppable_to_attr()) else {
+                    return;
+                };
+                for row in board.field.iter_mut() {
+                    if let Some(cell) = row.get_mut(x as usize) {
+                        convert_puyo(cell, to_attr);
+                    }
+                }
+            }
+            BoardTransformKind::RowToAttr => {
+                let (Some(y), Some(to_attr)) = (self.index, self.poppable_to_attr()) else {
+                    return;
+                };
+                let Some(row) = board.field.get_mut(y as usize) else {
+                    return;
+                };
+                for cell in row.iter_mut() {
+                    convert_puyo(cell, to_attr);
+                }
+            }
+            BoardTransformKind::AttrToAttr => {
+                let (Some(from_attr), Some(to_attr)) = (self.from_attr, self.poppable_to_attr())
+                else {
+                    return;
+                };
+                for cell in board.field.iter_mut().flatten() {
+                    if cell.is_some_and(|p| get_attr(p.puyo_type) == from_attr) {
+                        convert_puyo(cell, to_attr);
+                    }
+                }
+            }
+            BoardTransformKind::CoordsToAttr => {
+                let Some(to_attr) = self.poppable_to_attr() else {
+                    return;
+                };
+                for c in self.coords.iter() {
+                    if let Some(cell) = get_cell_mut(board, c) {
+                        convert_puyo(cell, to_attr);
+                    }
+                }
+            }
+            BoardTransformKind::RandomToAttr => {
+                let Some(to_attr) = self.poppable_to_attr() else {
+                    return;
+                };
+                for c in
+                    self.choose_randomly(board, |t| is_colored_type(t) && get_attr(t) != to_attr)
+                {
+                    convert_puyo(&mut board.field[c.y as usize][c.x as usize], to_attr);
+                }
+            }
+            BoardTransformKind::CoordsToPlus => {
+                for c in self.coords.iter() {
+                    if let Some(cell) = get_cell_mut(board, c) {
+                        make_plus(cell);
+                    }
+                }
+            }
+            BoardTransformKind::RandomToPlus => {
+                for c in self.choose_randomly(board, |t| is_colored_type(t) && !is_plus_type(t)) {
+                    make_plus(&mut board.field[c.y as usize][c.x as usize]);
+                }
+            }
+            BoardTransformKind::RemoveOjama => remove_ojama(board),
+        }
+    }
+
+    /// 変えた後の属性が弾ける属性なら返す。
+    fn poppable_to_attr(&self) -> Option<PuyoAttr> {
+        self.to_attr.filter(|attr| POPPABLE_ATTRS.contains(attr))
+    }
+
+    /// 条件を満たすぷよのある座標から、乱数で count 個を選ぶ。
+    /// 候補は上の行から、各行は左の列から順に並べるので、同じシードからは同じ座標が選ばれる。
+    fn choose_randomly<F>(&self, board: &FieldAndNext, is_target: F) -> Vec<PuyoCoord>
+    where
+        F: Fn(PuyoType) -> bool,
+    {
+        let mut candidates: Vec<PuyoCoord> = (0..PuyoCoord::Y_NUM)
+            .flat_map(|y| (0..PuyoCoord::X_NUM).map(move |x| PuyoCoord { x, y }))
+            .filter(|c| {
+                board.field[c.y as usize][c.x as usize].is_some_and(|p| is_target(p.puyo_type))
+            })
+            .collect();
+        let count = (self.count as usize).min(candidates.len());
+        let mut random = SeededRandom::new(self.seed);
+        // 先頭から count 個だけ Fisher-Yates で選ぶ
+        for i in 0..count {
+            let j = i + (random.next_u64() % (candidates.len() - i) as u64) as usize;
+            candidates.swap(i, j);
+        }
+        candidates.truncate(count);
+        candidates
+    }
+}
+
+/// 盤面変換を順に適用する。
+pub fn apply_board_transforms(transforms: &[BoardTransform], board: &mut FieldAndNext) {
+    for transform in transforms {
+        transform.apply(board);
+    }
+}
+
+/// 座標のマスを返す。盤面の外なら None。
+fn get_cell_mut<'a>(board: &'a mut FieldAndNext, c: &PuyoCoord) -> Option<&'a mut Option<Puyo>> {
+    board.field.get_mut(c.y as usize)?.get_mut(c.x as usize)
+}
+
+/// ぷよを指定の属性に変える。色ぷよ同士ならプラスやチャンスはそのまま残る。
+fn convert_puyo(cell: &mut Option<Puyo>, to_attr: PuyoAttr) {
+    if let Some(p) = cell {
+        p.puyo_type = convert_type(p.puyo_type, to_attr);
+    }
+}
+
+/// 色ぷよをプラスぷよにする。色ぷよでなければ何もしない。
+fn make_plus(cell: &mut Option<Puyo>) {
+    if let Some(p) = cell {
+        if is_colored_type(p.puyo_type) && !is_plus_type(p.puyo_type) {
+            p.puyo_type = PuyoType::from_u8(p.puyo_type.to_u8().unwrap() + 1).unwrap();
+        }
+    }
+}
+
+/// おじゃまぷよを消し、各列で上のぷよを下に詰めてから、おじゃまが消えた列にはその列のネクストを落とす。
+fn remove_ojama(board: &mut FieldAndNext) {
+    for x in 0..PuyoCoord::X_NUM as usize {
+        let puyos: Vec<Puyo> = (0..PuyoCoord::Y_NUM as usize)
+            .rev()
+            .filter_map(|y| board.field[y][x])
+            .collect();
+        let mut column: Vec<Puyo> = puyos
+            .iter()
+            .filter(|p| p.puyo_type != PuyoType::Ojama)
+            .cloned()
+            .collect();
+        if column.len() == puyos.len() {
+            continue;
+        }
+        if let Some(next) = board.next_puyos[x].take() {
+            column.push(next);
+        }
+        for y in (0..PuyoCoord::Y_NUM as usize).rev() {
+            let from_bottom = PuyoCoord::Y_NUM as usize - 1 - y;
+            board.field[y][x] = column.get(from_bottom).cloned();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puyo::Field;
+
+    fn board(types: [[Option<PuyoType>; 8]; 6], next: [Option<PuyoType>; 8]) -> FieldAndNext {
+        let mut id_counter = 0;
+        let mut to_puyo = |option: Option<PuyoType>| {
+            option.map(|puyo_type| {
+                id_counter += 1;
+                Puyo {
+                    id: id_counter,
+                    puyo_type,
+                }
+            })
+        };
+        let field: Field = types.map(|row| row.map(&mut to_puyo));
+        let next_puyos = next.map(&mut to_puyo);
+        FieldAndNext { field, next_puyos }
+    }
+
+    fn types(board: &FieldAndNext) -> [[Option<PuyoType>; 8]; 6] {
+        board.field.map(|row| row.map(|c| c.map(|p| p.puyo_type)))
+    }
+
+    fn transform(kind: BoardTransformKind) -> BoardTransform {
+        BoardTransform {
+            kind,
+            index: None,
+            from_attr: None,
+            to_attr: None,
+            coords: Vec::new(),
+            count: 0,
+            seed: 0,
+        }
+    }
+
+    const R: Option<PuyoType> = Some(PuyoType::Red);
+    const RP: Option<PuyoType> = Some(PuyoType::RedPlus);
+    const BC: Option<PuyoType> = Some(PuyoType::BlueChance);
+    const G: Option<PuyoType> = Some(PuyoType::Green);
+    const H: Option<PuyoType> = Some(PuyoType::Heart);
+    const O: Option<PuyoType> = Some(PuyoType::Ojama);
+    const E: Option<PuyoType> = None;
+
+    fn sample_board() -> FieldAndNext {
+        board(
+            [
+                [E, E, E, E, E, E, E, E],
+                [G, E, E, E, E, E, E, E],
+                [H, O, E, E, E, E, E, E],
+                [R, G, E, E, E, E, E, E],
+                [O, BC, G, G, G, G, G, G],
+                [G, H, R, R, R, R, R, R],
+            ],
+            [R, G, E, E, E, E, E, E],
+        )
+    }
+
+    #[test]
+    fn test_column_and_row_to_attr() {
+        // Arrange
+        let mut b = sample_board();
+        let column = BoardTransform {
+            index: Some(1),
+            to_attr: Some(PuyoAttr::Red),
+            ..transform(BoardTransformKind::ColumnToAttr)
+        };
+        let row = BoardTransform {
+            index: Some(5),
+            to_attr: Some(PuyoAttr::Green),
+            ..transform(BoardTransformKind::RowToAttr)
+        };
+
+        // Act
+        apply_board_transforms(&[column, row], &mut b);
+
+        // Assert
+        let t = types(&b);
+        // 列: おじゃまも含めて赤になり、チャンスはそのまま残る
+        assert_eq!(t[2][1], R);
+        assert_eq!(t[3][1], R);
+        assert_eq!(t[4][1], Some(PuyoType::RedChance));
+        // 行: 列の変換の後に適用される
+        assert_eq!(t[5], [G; 8]);
+        // ぷよのIDは変わらない
+        assert_eq!(
+            b.field[5][1].unwrap().id,
+            sample_board().field[5][1].unwrap().id
+        );
+    }
+
+    #[test]
+    fn test_attr_to_attr_and_coords() {
+        // Arrange
+        let mut b = sample_board();
+        let hearts = BoardTransform {
+            from_attr: Some(PuyoAttr::Heart),
+            to_attr: Some(PuyoAttr::Blue),
+            ..transform(BoardTransformKind::AttrToAttr)
+        };
+        let plus = BoardTransform {
+            coords: Vec::from([PuyoCoord { x: 0, y: 3 }, PuyoCoord { x: 0, y: 4 }]),
+            ..transform(BoardTransformKind::CoordsToPlus)
+        };
+        let prism = BoardTransform {
+            coords: Vec::from([PuyoCoord { x: 7, y: 5 }]),
+            to_attr: Some(PuyoAttr::Prism),
+            ..transform(BoardTransformKind::CoordsToAttr)
+        };
+
+        // Act
+        apply_board_transforms(&[hearts, plus, prism], &mut b);
+
+        // Assert
+        let t = types(&b);
+        assert_eq!(t[2][0], Some(PuyoType::Blue));
+        assert_eq!(t[5][1], Some(PuyoType::Blue));
+        assert_eq!(t[3][0], RP);
+        // おじゃまはプラスにならない
+        assert_eq!(t[4][0], O);
+        assert_eq!(t[5][7], Some(PuyoType::Prism));
+    }
+
+    #[test]
+    fn test_random_to_plus_and_attr() {
+        // Arrange
+        let plus = BoardTransform {
+            count: 3,
+            seed: 42,
+            ..transform(BoardTransformKind::RandomToPlus)
+        };
+        let purple = BoardTransform {
+            count: 100,
+            to_attr: Some(PuyoAttr::Purple),
+            ..transform(BoardTransformKind::RandomToAttr)
+        };
+
+        // Act
+        let mut b1 = sample_board();
+        plus.apply(&mut b1);
+        let mut b2 = sample_board();
+        plus.apply(&mut b2);
+        let mut b3 = sample_board();
+        purple.apply(&mut b3);
+
+        // Assert
+        // 同じシードなら同じ結果になる
+        assert_eq!(b1, b2);
+        let plus_num = types(&b1)
+            .iter()
+            .flatten()
+            .filter(|t| t.is_some_and(is_plus_type))
+            .count();
+        assert_eq!(plus_num, 3);
+        // 候補より多ければ全ての色ぷよが変わり、ハートとおじゃまは変わらない
+        let t = types(&b3);
+        assert!(t.iter().flatten().flatten().all(|t| matches!(
+            get_attr(*t),
+            PuyoAttr::Purple | PuyoAttr::Heart | PuyoAttr::Ojama
+        )));
+        assert_eq!(t[4][1], Some(PuyoType::PurpleChance));
+    }
+
+    #[test]
+    fn test_remove_ojama() {
+        // Arrange
+        let mut b = sample_board();
+
+        // Act
+        transform(BoardTransformKind::RemoveOjama).apply(&mut b);
+
+        // Assert
+        let t = types(&b);
+        assert_eq!(
+            t.map(|row| [row[0], row[1]]),
+            [[E, E], [R, E], [G, G], [H, G], [R, BC], [G, H],]
+        );
+        // ネクストは落ちて空になる
+        assert_eq!(b.next_puyos[0], None);
+        assert_eq!(b.next_puyos[1], None);
+        // おじゃまの無い列はネクストが残る
+        let mut full = sample_board();
+        full.next_puyos[2] = Some(Puyo {
+            id: 100,
+            puyo_type: PuyoType::Red,
+        });
+        transform(BoardTransformKind::RemoveOjama).apply(&mut full);
+        assert!(full.next_puyos[2].is_some());
+    }
+
+    #[test]
+    fn test_apply_ignores_invalid_settings() {
+        // Arrange
+        let transforms = [
+            transform(BoardTransformKind::ColumnToAttr),
+            BoardTransform {
+                index: Some(8),
+                to_attr: Some(PuyoAttr::Red),
+                ..transform(BoardTransformKind::ColumnToAttr)
+            },
+            BoardTransform {
+                index: Some(6),
+                to_attr: Some(PuyoAttr::Red),
+                ..transform(BoardTransformKind::RowToAttr)
+            },
+            BoardTransform {
+                index: Some(5),
+                to_attr: Some(PuyoAttr::Padding),
+                ..transform(BoardTransformKind::RowToAttr)
+            },
+            BoardTransform {
+                to_attr: Some(PuyoAttr::Red),
+                ..transform(BoardTransformKind::AttrToAttr)
+            },
+            BoardTransform {
+                coords: Vec::from([PuyoCoord { x: 8, y: 0 }, PuyoCoord { x: 0, y: 6 }]),
+                ..transform(BoardTransformKind::CoordsToAttr)
+            },
+            BoardTransform {
+                count: 3,
+                ..transform(BoardTransformKind::RandomToAttr)
+            },
+            BoardTransform {
+                coords: Vec::from([PuyoCoord { x: 8, y: 0 }, PuyoCoord { x: 0, y: 6 }]),
+                ..transform(BoardTransformKind::CoordsToPlus)
+            },
+        ];
+
+        for transform in transforms.iter() {
+            // Act
+            let mut b = sample_board();
+            transform.apply(&mut b);
+
+            // Assert
+            // パニックせず、盤面も変わらない
+            assert!(transform.validate().is_err());
+            assert_eq!(b, sample_board());
+        }
+
+        // 盤面の外の座標だけを無視し、残りの座標は変える
+        let mut b = sample_board();
+        BoardTransform {
+            coords: Vec::from([PuyoCoord { x: 8, y: 0 }, PuyoCoord { x: 0, y: 3 }]),
+            ..transform(BoardTransformKind::CoordsToPlus)
+        }
+        .apply(&mut b);
+        assert_eq!(types(&b)[3][0], RP);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(BoardTransform {
+            index: Some(7),
+            to_attr: Some(PuyoAttr::Red),
+            ..transform(BoardTransformKind::ColumnToAttr)
+        }
+        .validate()
+        .is_ok());
+        assert!(BoardTransform {
+            index: Some(6),
+            to_attr: Some(PuyoAttr::Red),
+            ..transform(BoardTransformKind::RowToAttr)
+        }
+        .validate()
+        .is_err());
+        assert!(BoardTransform {
+            from_attr: Some(PuyoAttr::Heart),
+            ..transform(BoardTransformKind::AttrToAttr)
+        }
+        .validate()
+        .is_err());
+        assert!(BoardTransform {
+            coords: Vec::from([PuyoCoord { x: 8, y: 0 }]),
+            ..transform(BoardTransformKind::CoordsToPlus)
+        }
+        .validate()
+        .is_err());
+        assert!(transform(BoardTransformKind::RemoveOjama)
+            .validate()
+            .is_ok());
+    }
+}
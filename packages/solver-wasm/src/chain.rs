@@ -30,4 +30,7 @@ pub struct Chain {
     pub popped_chance_num: u32,
     /// この連鎖で全消しを達成したかどうか
     pub is_all_cleared: bool,
+    /// 対象の色属性が指定された特殊ぷよの強さの、対象の色属性ごとの合計。その色属性が消えていなくても加わる。
+    #[serde(default)]
+    pub targeted_special_strengths: HashMap<PuyoAttr, f64>,
 }
@@ -2,7 +2,7 @@ use std::cmp;
 
 use crate::{
     chain::Chain,
-    puyo_attr::{is_colored_attr, PuyoAttr, COLOR_ATTRS},
+    puyo_attr::{is_colored_attr, PuyoAttr, COLOR_ATTRS, SPECIAL_ATTRS},
 };
 
 /** 連鎖情報から対象属性のポップカウント総数を求める。 */
@@ -31,8 +31,23 @@ pub fn sum_puyo_tsukai_count(chains: &Vec<Chain>) -> u32 {
     chains.iter().fold(0, |acc, c| acc + c.puyo_tsukai_count)
 }
 
+/** 連鎖数を求める。 */
+pub fn count_chains(chains: &[Chain]) -> u32 {
+    chains.iter().map(|c| c.chain_num).max().unwrap_or(0)
+}
+
+/** 連鎖情報から同時消し数の最大値を求める。 */
+pub fn max_simultaneous_num(chains: &[Chain]) -> u32 {
+    chains.iter().map(|c| c.simultaneous_num).max().unwrap_or(0)
+}
+
+/** 連鎖情報から同時消し数の総数 (全ての連鎖で消えたぷよ数) を求める。 */
+pub fn sum_simultaneous_num(chains: &[Chain]) -> u32 {
+    chains.iter().map(|c| c.simultaneous_num).sum()
+}
+
 /** 対象属性による純粋なダメージを集計する。(プリズムのダメージやブーストカウントによる倍率は考慮に**入れない**) */
-fn sum_pure_attr_damage(chains: &Vec<Chain>, attr: PuyoAttr) -> f64 {
+pub fn sum_pure_attr_damage(chains: &Vec<Chain>, attr: PuyoAttr) -> f64 {
     chains.iter().fold(0.0, |acc, c| {
         acc + match c.attributes.get(&attr) {
             Some(attr_chain) => attr_chain.strength,
@@ -46,27 +61,59 @@ pub fn sum_prism_damage(chains: &Vec<Chain>) -> f64 {
     sum_pure_attr_damage(chains, PuyoAttr::Prism)
 }
 
-/** 対象の色属性におけるダメージを集計する。(プリズムのダメージやブーストカウントによる倍率も考慮に**入れる**) */
+/** 特殊ぷよ (プリズム、ハート、おじゃま、固ぷよ) による、全ての色属性に加わるダメージを集計する。 */
+pub fn sum_special_damage(chains: &Vec<Chain>) -> f64 {
+    SPECIAL_ATTRS
+        .iter()
+        .fold(0.0, |acc, attr| acc + sum_pure_attr_damage(chains, *attr))
+}
+
+/** 特殊ぷよによる、対象の色属性だけに加わるダメージを集計する。 */
+pub fn sum_targeted_special_damage(chains: &[Chain], attr: PuyoAttr) -> f64 {
+    chains.iter().fold(0.0, |acc, c| {
+        acc + c.targeted_special_strengths.get(&attr).unwrap_or(&0.0)
+    })
+}
+
+/** 特殊ぷよによる、対象の色属性に加わるダメージを集計する。(全ての色属性に加わるものと、その色属性だけに加わるものの合計) */
+pub fn sum_special_attr_damage(chains: &Vec<Chain>, attr: PuyoAttr) -> f64 {
+    sum_special_damage(chains) + sum_targeted_special_damage(chains, attr)
+}
+
+/** 対象の色属性におけるダメージを集計する。(プリズムなど特殊ぷよのダメージやブーストカウントによる倍率も考慮に**入れる**) */
 pub fn sum_colored_attr_damage(chains: &Vec<Chain>, attr: PuyoAttr) -> f64 {
+    sum_colored_attr_damage_with_affinity(chains, attr, 1.0)
+}
+
+/** 対象の色属性におけるダメージを、色属性による部分にだけ敵との相性の倍率 affinity を掛けて集計する。(特殊ぷよのダメージには掛けない) */
+pub fn sum_colored_attr_damage_with_affinity(
+    chains: &Vec<Chain>,
+    attr: PuyoAttr,
+    affinity: f64,
+) -> f64 {
     if !is_colored_attr(attr) {
         return 0.0;
     }
     let attr_damage = sum_pure_attr_damage(chains, attr);
-    let prism_damage = sum_prism_damage(chains);
+    let special_damage = sum_special_attr_damage(chains, attr);
     let boost_ratio = calc_boost_ratio(sum_boost_count(chains));
 
-    return (attr_damage + prism_damage) * boost_ratio;
+    return (attr_damage * affinity + special_damage) * boost_ratio;
 }
 
-/** ワイルドによるダメージを計算する。(プリズムのダメージやブーストカウントによる倍率も考慮に**入れる**) */
+/** ワイルドによるダメージを計算する。(プリズムなど特殊ぷよのダメージやブーストカウントによる倍率も考慮に**入れる**) */
 pub fn sum_wild_damage(chains: &Vec<Chain>) -> f64 {
     let wild_pure_damage = COLOR_ATTRS
         .iter()
         .fold(0.0, |acc, attr| acc + sum_pure_attr_damage(chains, *attr));
-    let prism_damage = sum_prism_damage(chains);
+    let special_damage = COLOR_ATTRS
+        .iter()
+        .fold(sum_special_damage(chains), |acc, attr| {
+            acc + sum_targeted_special_damage(chains, *attr)
+        });
     let boost_ratio = calc_boost_ratio(sum_boost_count(chains));
 
-    return (wild_pure_damage + prism_damage) * boost_ratio;
+    return (wild_pure_damage + special_damage) * boost_ratio;
 }
 
 #[cfg(test)]
@@ -104,6 +151,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
             Chain {
                 chain_num: 2,
@@ -130,6 +178,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
             Chain {
                 chain_num: 3,
@@ -156,6 +205,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
         ]);
 
@@ -204,6 +254,7 @@ mod tests {
                 ]),
                 popped_chance_num: 1,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
             Chain {
                 chain_num: 2,
@@ -230,6 +281,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
         ]);
 
@@ -283,6 +335,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
             Chain {
                 chain_num: 2,
@@ -309,6 +362,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
         ]);
 
@@ -316,6 +370,82 @@ mod tests {
         assert_eq!(sum_puyo_tsukai_count(&chains), 37);
     }
 
+    #[test]
+    fn test_chain_scale() {
+        // Arrange
+        let chains: Vec<Chain> = Vec::from([
+            Chain {
+                chain_num: 1,
+                simultaneous_num: 9,
+                boost_count: 4,
+                puyo_tsukai_count: 19,
+                attributes: HashMap::from([
+                    (
+                        PuyoAttr::Green,
+                        AttributeChain {
+                            strength: 1.75,
+                            popped_count: 5,
+                            separated_blocks_num: 1,
+                        },
+                    ),
+                    (
+                        PuyoAttr::Purple,
+                        AttributeChain {
+                            strength: 1.75,
+                            popped_count: 4,
+                            separated_blocks_num: 1,
+                        },
+                    ),
+                    (
+                        PuyoAttr::Heart,
+                        AttributeChain {
+                            strength: 0.0,
+                            popped_count: 2,
+                            separated_blocks_num: 0,
+                        },
+                    ),
+                ]),
+                popped_chance_num: 0,
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
+            },
+            Chain {
+                chain_num: 2,
+                simultaneous_num: 10,
+                boost_count: 4,
+                puyo_tsukai_count: 18,
+                attributes: HashMap::from([
+                    (
+                        PuyoAttr::Red,
+                        AttributeChain {
+                            strength: 2.66,
+                            popped_count: 5,
+                            separated_blocks_num: 1,
+                        },
+                    ),
+                    (
+                        PuyoAttr::Yellow,
+                        AttributeChain {
+                            strength: 2.66,
+                            popped_count: 5,
+                            separated_blocks_num: 1,
+                        },
+                    ),
+                ]),
+                popped_chance_num: 0,
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
+            },
+        ]);
+
+        // Act & Assert
+        assert_eq!(count_chains(&chains), 2);
+        assert_eq!(max_simultaneous_num(&chains), 10);
+        assert_eq!(sum_simultaneous_num(&chains), 19);
+        assert_eq!(count_chains(&[]), 0);
+        assert_eq!(max_simultaneous_num(&[]), 0);
+    }
+
     #[test]
     fn test_sum_prism_damage() {
         // Arrange
@@ -345,6 +475,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
             Chain {
                 chain_num: 2,
@@ -371,6 +502,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
             Chain {
                 chain_num: 3,
@@ -387,6 +519,7 @@ mod tests {
                 )]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
         ]);
 
@@ -394,6 +527,100 @@ mod tests {
         assert_eq!(sum_prism_damage(&chains), 9.0);
     }
 
+    #[test]
+    fn test_sum_special_damage() {
+        // Arrange
+        let chains: Vec<Chain> = Vec::from([Chain {
+            chain_num: 1,
+            simultaneous_num: 7,
+            boost_count: 0,
+            puyo_tsukai_count: 7,
+            attributes: HashMap::from([
+                (
+                    PuyoAttr::Red,
+                    AttributeChain {
+                        strength: 1.0,
+                        popped_count: 4,
+                        separated_blocks_num: 1,
+                    },
+                ),
+                (
+                    PuyoAttr::Prism,
+                    AttributeChain {
+                        strength: 3.0,
+                        popped_count: 1,
+                        separated_blocks_num: 0,
+                    },
+                ),
+                (
+                    PuyoAttr::Heart,
+                    AttributeChain {
+                        strength: 1.5,
+                        popped_count: 1,
+                        separated_blocks_num: 0,
+                    },
+                ),
+                (
+                    PuyoAttr::Ojama,
+                    AttributeChain {
+                        strength: 0.5,
+                        popped_count: 1,
+                        separated_blocks_num: 0,
+                    },
+                ),
+            ]),
+            popped_chance_num: 0,
+            is_all_cleared: false,
+            targeted_special_strengths: HashMap::new(),
+        }]);
+
+        // Act & Assert
+        assert_eq!(sum_special_damage(&chains), 5.0);
+        assert_eq!(sum_colored_attr_damage(&chains, PuyoAttr::Red), 6.0);
+        assert_eq!(sum_colored_attr_damage(&chains, PuyoAttr::Blue), 5.0);
+    }
+
+    #[test]
+    fn test_sum_targeted_special_damage() {
+        // Arrange
+        // ハートの強さ 2.0 が青だけに加わる (青は消えていない)
+        let chains: Vec<Chain> = Vec::from([Chain {
+            chain_num: 1,
+            simultaneous_num: 5,
+            boost_count: 0,
+            puyo_tsukai_count: 5,
+            attributes: HashMap::from([
+                (
+                    PuyoAttr::Red,
+                    AttributeChain {
+                        strength: 1.0,
+                        popped_count: 4,
+                        separated_blocks_num: 1,
+                    },
+                ),
+                (
+                    PuyoAttr::Heart,
+                    AttributeChain {
+                        strength: 0.0,
+                        popped_count: 1,
+                        separated_blocks_num: 0,
+                    },
+                ),
+            ]),
+            popped_chance_num: 0,
+            is_all_cleared: false,
+            targeted_special_strengths: HashMap::from([(PuyoAttr::Blue, 2.0)]),
+        }]);
+
+        // Act & Assert
+        assert_eq!(sum_targeted_special_damage(&chains, PuyoAttr::Blue), 2.0);
+        assert_eq!(sum_targeted_special_damage(&chains, PuyoAttr::Red), 0.0);
+        assert_eq!(sum_pure_attr_damage(&chains, PuyoAttr::Blue), 0.0);
+        assert_eq!(sum_colored_attr_damage(&chains, PuyoAttr::Red), 1.0);
+        assert_eq!(sum_colored_attr_damage(&chains, PuyoAttr::Blue), 2.0);
+        assert_eq!(sum_wild_damage(&chains), 3.0);
+    }
+
     #[test]
     fn test_sum_colored_attr_damage() {
         // Arrange
@@ -423,6 +650,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
             Chain {
                 chain_num: 2,
@@ -449,6 +677,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
             Chain {
                 chain_num: 3,
@@ -465,6 +694,7 @@ mod tests {
                 )]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
         ]);
 
@@ -534,6 +764,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
             Chain {
                 chain_num: 2,
@@ -560,6 +791,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
             Chain {
                 chain_num: 3,
@@ -594,6 +826,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             },
         ]);
 
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{puyo_attr::PuyoAttr, scoring::ScoringMetric};
+
+/**
+ * 解が満たすべき条件。指標の値が min 以上 max 以下の解だけを残す。
+ * 例えば「全消し必須」は (AllClear, min: 1)、「6連鎖以上」は (ChainCount, min: 6)、
+ * 「ハートを消さない」は (HeartPop, max: 0) で表す。
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolutionConstraint {
+    /** 指標 */
+    pub metric: ScoringMetric,
+    /** 対象属性。属性ごとの指標のときだけ指定する。 */
+    #[serde(default)]
+    pub attr: Option<PuyoAttr>,
+    /** 下限 (この値を含む) */
+    #[serde(default)]
+    pub min: Option<f64>,
+    /** 上限 (この値を含む) */
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+impl SolutionConstraint {
+    /// 探索を始める前に条件が正しいか検証する。
+    pub fn validate(&self) -> Result<(), String> {
+        self.metric.validate_attr(self.attr)?;
+        match (self.min, self.max) {
+            (None, None) => Err(format!("{:?}: min or max is required", self.metric)),
+            (Some(min), Some(max)) if min > max => Err(format!(
+                "{:?}: min must not be greater than max",
+                self.metric
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// 指標の値が条件を満たすかどうか。
+    pub fn is_satisfied(&self, value: f64) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constraint(metric: ScoringMetric, min: Option<f64>, max: Option<f64>) -> SolutionConstraint {
+        SolutionConstraint {
+            metric,
+            attr: None,
+            min,
+            max,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(constraint(ScoringMetric::ChainCount, Some(6.0), None)
+            .validate()
+            .is_ok());
+        assert!(constraint(ScoringMetric::ChainCount, Some(2.0), Some(2.0))
+            .validate()
+            .is_ok());
+        assert!(constraint(ScoringMetric::ChainCount, None, None)
+            .validate()
+            .is_err());
+        assert!(constraint(ScoringMetric::ChainCount, Some(3.0), Some(2.0))
+            .validate()
+            .is_err());
+        assert!(constraint(ScoringMetric::AttrPoppedCount, Some(1.0), None)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_is_satisfied() {
+        let at_least = constraint(ScoringMetric::ChainCount, Some(6.0), None);
+        assert!(at_least.is_satisfied(6.0));
+        assert!(!at_least.is_satisfied(5.0));
+
+        let none = constraint(ScoringMetric::HeartPop, None, Some(0.0));
+        assert!(none.is_satisfied(0.0));
+        assert!(!none.is_satisfied(1.0));
+
+        let range = constraint(ScoringMetric::ChancePop, Some(2.0), Some(3.0));
+        assert!(!range.is_satisfied(1.0));
+        assert!(range.is_satisfied(2.0));
+        assert!(range.is_satisfied(3.0));
+        assert!(!range.is_satisfied(4.0));
+    }
+}
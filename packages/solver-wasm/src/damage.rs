@@ -1,3 +1,5 @@
+use std::cmp;
+
 pub fn calc_damage_term(card_attack: f64, popping_factor: f64, chain_factor: f64) -> f64 {
     card_attack * popping_factor * chain_factor
 }
@@ -22,14 +24,25 @@ const CHAIN_COEFFICIENT_TABLE: [f64; 3] = [
          // 4連鎖は 1.0 でそれ以降 0.2 ずつ上がる
 ];
 
-pub fn calc_chain_factor(chain_num: u32, chain_leverage: Option<f64>) -> Option<f64> {
+/// 連鎖倍率を求める。chain_coefficient_table を指定すると既定の係数の代わりに使う。
+/// 指定した表より長い連鎖では表の最後の係数を使う。
+pub fn calc_chain_factor(
+    chain_num: u32,
+    chain_coefficient_table: Option<&[f64]>,
+    chain_leverage: Option<f64>,
+) -> Option<f64> {
     if chain_num < 1 {
         return None;
     }
-    let base_coeffcient = if chain_num >= 4 {
-        1.0 + ((chain_num - 4) as f64) * 0.2
-    } else {
-        CHAIN_COEFFICIENT_TABLE[(chain_num - 1) as usize]
+    let base_coeffcient = match chain_coefficient_table {
+        Some(table) if !table.is_empty() => table[cmp::min(chain_num as usize, table.len()) - 1],
+        _ => {
+            if chain_num >= 4 {
+                1.0 + ((chain_num - 4) as f64) * 0.2
+            } else {
+                CHAIN_COEFFICIENT_TABLE[(chain_num - 1) as usize]
+            }
+        }
     };
     Some(base_coeffcient * chain_leverage.unwrap_or(1.0) + 1.0)
 }
@@ -66,21 +79,32 @@ mod tests {
 
     #[test]
     fn test_calc_chain_factor() {
-        assert_eq!(calc_chain_factor(0, None), None);
-        assert_relative_eq!(calc_chain_factor(1, None).unwrap(), 1.0);
-        assert_relative_eq!(calc_chain_factor(1, Some(1.0)).unwrap(), 1.0);
-        assert_relative_eq!(calc_chain_factor(2, Some(1.0)).unwrap(), 1.4);
-        assert_relative_eq!(calc_chain_factor(3, Some(1.0)).unwrap(), 1.7);
-        assert_relative_eq!(calc_chain_factor(4, Some(1.0)).unwrap(), 2.0);
-        assert_relative_eq!(calc_chain_factor(5, Some(1.0)).unwrap(), 2.2);
-        assert_relative_eq!(calc_chain_factor(10, Some(1.0)).unwrap(), 3.2);
-        assert_relative_eq!(calc_chain_factor(18, Some(1.0)).unwrap(), 4.8);
-        assert_relative_eq!(calc_chain_factor(1, Some(7.0)).unwrap(), 1.0);
-        assert_relative_eq!(calc_chain_factor(2, Some(7.0)).unwrap(), 3.8);
-        assert_relative_eq!(calc_chain_factor(3, Some(7.0)).unwrap(), 5.9);
-        assert_relative_eq!(calc_chain_factor(4, Some(7.0)).unwrap(), 8.0);
-        assert_relative_eq!(calc_chain_factor(5, Some(7.0)).unwrap(), 9.4);
-        assert_relative_eq!(calc_chain_factor(10, Some(7.0)).unwrap(), 16.4);
-        assert_relative_eq!(calc_chain_factor(18, Some(7.0)).unwrap(), 27.6);
+        assert_eq!(calc_chain_factor(0, None, None), None);
+        assert_relative_eq!(calc_chain_factor(1, None, None).unwrap(), 1.0);
+        assert_relative_eq!(calc_chain_factor(1, None, Some(1.0)).unwrap(), 1.0);
+        assert_relative_eq!(calc_chain_factor(2, None, Some(1.0)).unwrap(), 1.4);
+        assert_relative_eq!(calc_chain_factor(3, None, Some(1.0)).unwrap(), 1.7);
+        assert_relative_eq!(calc_chain_factor(4, None, Some(1.0)).unwrap(), 2.0);
+        assert_relative_eq!(calc_chain_factor(5, None, Some(1.0)).unwrap(), 2.2);
+        assert_relative_eq!(calc_chain_factor(10, None, Some(1.0)).unwrap(), 3.2);
+        assert_relative_eq!(calc_chain_factor(18, None, Some(1.0)).unwrap(), 4.8);
+        assert_relative_eq!(calc_chain_factor(1, None, Some(7.0)).unwrap(), 1.0);
+        assert_relative_eq!(calc_chain_factor(2, None, Some(7.0)).unwrap(), 3.8);
+        assert_relative_eq!(calc_chain_factor(3, None, Some(7.0)).unwrap(), 5.9);
+        assert_relative_eq!(calc_chain_factor(4, None, Some(7.0)).unwrap(), 8.0);
+        assert_relative_eq!(calc_chain_factor(5, None, Some(7.0)).unwrap(), 9.4);
+        assert_relative_eq!(calc_chain_factor(10, None, Some(7.0)).unwrap(), 16.4);
+        assert_relative_eq!(calc_chain_factor(18, None, Some(7.0)).unwrap(), 27.6);
+    }
+
+    #[test]
+    fn test_calc_chain_factor_with_table() {
+        let table = [0.0, 0.5, 1.5];
+        assert_eq!(calc_chain_factor(0, Some(&table), None), None);
+        assert_relative_eq!(calc_chain_factor(1, Some(&table), None).unwrap(), 1.0);
+        assert_relative_eq!(calc_chain_factor(2, Some(&table), Some(1.0)).unwrap(), 1.5);
+        assert_relative_eq!(calc_chain_factor(3, Some(&table), Some(2.0)).unwrap(), 4.0);
+        assert_relative_eq!(calc_chain_factor(10, Some(&table), Some(1.0)).unwrap(), 2.5);
+        assert_relative_eq!(calc_chain_factor(5, Some(&[]), Some(1.0)).unwrap(), 2.2);
     }
 }
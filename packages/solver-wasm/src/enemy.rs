@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chain::Chain,
+    chain_helper::{
+        calc_boost_ratio, sum_boost_count, sum_colored_attr_damage_with_affinity,
+        sum_pure_attr_damage, sum_special_damage, sum_targeted_special_damage,
+    },
+    puyo_attr::{is_colored_attr, PuyoAttr, COLOR_ATTRS},
+};
+
+/**
+ * 敵の情報。
+ * 敵との相性は、攻撃のうち色属性による部分にだけ掛ける。
+ * プリズムなど特殊ぷよのダメージは、色属性の攻撃に加わるものも含めて相性の影響を受けない。
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Enemy {
+    /** 属性 (5色のいずれか) */
+    pub attr: PuyoAttr,
+    /** 体力。指定するとそれを超えるダメージは区別しない。 */
+    pub hp: Option<f64>,
+    /** ダメージ軽減率 (0.0〜1.0) */
+    #[serde(default)]
+    pub damage_reduction: f64,
+    /** 1ターンに受けるダメージの上限 */
+    pub damage_cap: Option<f64>,
+}
+
+/// 攻撃の属性と敵の属性の相性による倍率を返す。
+/// 赤は緑に、緑は青に、青は赤に強く、黄と紫は互いに強い。強いと2倍、弱いと0.5倍になる。
+pub fn calc_attr_affinity(attack_attr: PuyoAttr, enemy_attr: PuyoAttr) -> f64 {
+    match (attack_attr, enemy_attr) {
+        (PuyoAttr::Red, PuyoAttr::Green)
+        | (PuyoAttr::Green, PuyoAttr::Blue)
+        | (PuyoAttr::Blue, PuyoAttr::Red)
+        | (PuyoAttr::Yellow, PuyoAttr::Purple)
+        | (PuyoAttr::Purple, PuyoAttr::Yellow) => 2.0,
+        (PuyoAttr::Green, PuyoAttr::Red)
+        | (PuyoAttr::Blue, PuyoAttr::Green)
+        | (PuyoAttr::Red, PuyoAttr::Blue) => 0.5,
+        _ => 1.0,
+    }
+}
+
+impl Enemy {
+    /// 設定が正しいか検証する。
+    pub fn validate(&self) -> Result<(), String> {
+        if !is_colored_attr(self.attr) {
+            return Err("attr of enemy must be a colored attribute".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.damage_reduction) {
+            return Err("damage_reduction of enemy must be between 0.0 and 1.0".to_string());
+        }
+        let is_valid = |v: &Option<f64>| v.is_none_or(|v| v >= 0.0 && v.is_finite());
+        if !is_valid(&self.hp) || !is_valid(&self.damage_cap) {
+            return Err("hp and damage_cap of enemy must be non-negative and finite".to_string());
+        }
+        Ok(())
+    }
+
+    /// 攻撃の属性とこの敵の属性の相性による倍率を返す。
+    pub fn calc_affinity(&self, attack_attr: PuyoAttr) -> f64 {
+        calc_attr_affinity(attack_attr, self.attr)
+    }
+
+    /// 対象の色属性で攻撃したときのダメージを、相性を考慮して求める。軽減率や上限は考慮しない。
+    pub fn calc_attr_damage(&self, chains: &Vec<Chain>, attr: PuyoAttr) -> f64 {
+        sum_colored_attr_damage_with_affinity(chains, attr, self.calc_affinity(attr))
+    }
+
+    /// ワイルドで攻撃したときのダメージを、各色のダメージにそれぞれの相性を掛けて求める。軽減率や上限は考慮しない。
+    pub fn calc_wild_damage(&self, chains: &Vec<Chain>) -> f64 {
+        let boost_ratio = calc_boost_ratio(sum_boost_count(chains));
+        let colored_damage: f64 = COLOR_ATTRS
+            .iter()
+            .map(|attr| sum_pure_attr_damage(chains, *attr) * self.calc_affinity(*attr))
+            .sum();
+        let special_damage = COLOR_ATTRS
+            .iter()
+            .fold(sum_special_damage(chains), |acc, attr| {
+                acc + sum_targeted_special_damage(chains, *attr)
+            });
+        (colored_damage + special_damage) * boost_ratio
+    }
+
+    /// 相性を考慮したダメージに、軽減率と上限を適用して敵に与えるダメージを求める。
+    pub fn apply_defense(&self, damage: f64) -> f64 {
+        let mut damage = damage * (1.0 - self.damage_reduction);
+        if let Some(cap) = self.damage_cap {
+            damage = damage.min(cap);
+        }
+        if let Some(hp) = self.hp {
+            damage = damage.min(hp);
+        }
+        damage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::AttributeChain;
+    use std::collections::HashMap;
+
+    fn enemy(attr: PuyoAttr) -> Enemy {
+        Enemy {
+            attr,
+            hp: None,
+            damage_reduction: 0.0,
+            damage_cap: None,
+        }
+    }
+
+    #[test]
+    fn test_calc_attr_affinity() {
+        assert_eq!(calc_attr_affinity(PuyoAttr::Red, PuyoAttr::Green), 2.0);
+        assert_eq!(calc_attr_affinity(PuyoAttr::Green, PuyoAttr::Blue), 2.0);
+        assert_eq!(calc_attr_affinity(PuyoAttr::Blue, PuyoAttr::Red), 2.0);
+        assert_eq!(calc_attr_affinity(PuyoAttr::Green, PuyoAttr::Red), 0.5);
+        assert_eq!(calc_attr_affinity(PuyoAttr::Blue, PuyoAttr::Green), 0.5);
+        assert_eq!(calc_attr_affinity(PuyoAttr::Red, PuyoAttr::Blue), 0.5);
+        assert_eq!(calc_attr_affinity(PuyoAttr::Yellow, PuyoAttr::Purple), 2.0);
+        assert_eq!(calc_attr_affinity(PuyoAttr::Purple, PuyoAttr::Yellow), 2.0);
+        assert_eq!(calc_attr_affinity(PuyoAttr::Red, PuyoAttr::Red), 1.0);
+        assert_eq!(calc_attr_affinity(PuyoAttr::Yellow, PuyoAttr::Red), 1.0);
+        assert_eq!(calc_attr_affinity(PuyoAttr::Red, PuyoAttr::Purple), 1.0);
+    }
+
+    #[test]
+    fn test_apply_defense() {
+        assert_relative_eq!(enemy(PuyoAttr::Green).apply_defense(220.0), 220.0);
+        assert_relative_eq!(
+            Enemy {
+                damage_reduction: 0.5,
+                ..enemy(PuyoAttr::Green)
+            }
+            .apply_defense(220.0),
+            110.0
+        );
+        assert_relative_eq!(
+            Enemy {
+                damage_cap: Some(150.0),
+                ..enemy(PuyoAttr::Green)
+            }
+            .apply_defense(220.0),
+            150.0
+        );
+        assert_relative_eq!(
+            Enemy {
+                hp: Some(120.0),
+                damage_cap: Some(150.0),
+                ..enemy(PuyoAttr::Green)
+            }
+            .apply_defense(220.0),
+            120.0
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(enemy(PuyoAttr::Red).validate().is_ok());
+        assert!(enemy(PuyoAttr::Prism).validate().is_err());
+        for damage_reduction in [-0.1, 1.5, f64::NAN] {
+            assert!(Enemy {
+                damage_reduction,
+                ..enemy(PuyoAttr::Red)
+            }
+            .validate()
+            .is_err());
+        }
+        assert!(Enemy {
+            damage_cap: Some(-1.0),
+            ..enemy(PuyoAttr::Red)
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_calc_damage_against_enemy() {
+        // Arrange
+        let chains: Vec<Chain> = Vec::from([Chain {
+            chain_num: 1,
+            simultaneous_num: 9,
+            boost_count: 5,
+            puyo_tsukai_count: 9,
+            attributes: HashMap::from([
+                (
+                    PuyoAttr::Red,
+                    AttributeChain {
+                        strength: 1.0,
+                        popped_count: 4,
+                        separated_blocks_num: 1,
+                    },
+                ),
+                (
+                    PuyoAttr::Yellow,
+                    AttributeChain {
+                        strength: 2.0,
+                        popped_count: 4,
+                        separated_blocks_num: 1,
+                    },
+                ),
+                (
+                    PuyoAttr::Prism,
+                    AttributeChain {
+                        strength: 3.0,
+                        popped_count: 1,
+                        separated_blocks_num: 1,
+                    },
+                ),
+            ]),
+            popped_chance_num: 0,
+            is_all_cleared: false,
+            targeted_special_strengths: HashMap::new(),
+        }]);
+
+        // Act & Assert
+        // 赤は青に弱く、黄は等倍で、プリズムには相性を掛けない: (1.0 * 0.5 + 2.0 + 3.0) * 1.2
+        assert_relative_eq!(enemy(PuyoAttr::Blue).calc_wild_damage(&chains), 6.6);
+        // 黄は紫に強い: (1.0 + 2.0 * 2.0 + 3.0) * 1.2
+        assert_relative_eq!(enemy(PuyoAttr::Purple).calc_wild_damage(&chains), 9.6);
+        // 色属性で攻撃するときも、プリズムのダメージには相性を掛けない: (1.0 * 0.5 + 3.0) * 1.2
+        assert_relative_eq!(
+            enemy(PuyoAttr::Blue).calc_attr_damage(&chains, PuyoAttr::Red),
+            4.2
+        );
+        // 赤は緑に強い: (1.0 * 2.0 + 3.0) * 1.2
+        assert_relative_eq!(
+            enemy(PuyoAttr::Green).calc_attr_damage(&chains, PuyoAttr::Red),
+            6.0
+        );
+    }
+}
@@ -0,0 +1,596 @@
+//! 整数で計算するダメージ。
+//!
+//! 設定値の倍率を有理数に直し、同時消し倍率・連鎖倍率・ブースト倍率を有理数のまま組み立てる。
+//! 同時消し倍率・連鎖倍率・ブースト倍率・副属性の割合を掛けるたびに小数点以下を切り捨てる。
+//! 浮動小数点の演算順序に依存しないので、ネイティブと wasm で結果が一致する。
+
+use std::cmp;
+
+use crate::{
+    chain::Chain,
+    chain_helper::sum_boost_count,
+    puyo_attr::{PuyoAttr, SPECIAL_ATTRS},
+    simulation_environment::SimulationEnvironment,
+};
+
+/** 有理数 (分子, 分母)。分子は非負、分母は正で、既約分数にする。 */
+pub type Ratio = (i128, i128);
+
+/** 倍率を有理数に直すときの分母の上限。小数点以下9桁までの小数や 1/3 のような分数を誤差なく表せる。 */
+const MAX_DENOMINATOR: i128 = 1_000_000_000;
+
+/** 同時消し係数の既定値 (0.15) */
+const DEFAULT_POPPING_COEFFICIENT: Ratio = (3, 20);
+
+/** 連鎖係数の既定値 (1〜3連鎖。4連鎖は 1.0 でそれ以降 0.2 ずつ上がる) */
+const DEFAULT_CHAIN_COEFFICIENT_TABLE: [Ratio; 3] = [(0, 1), (2, 5), (7, 10)];
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn reduce((numerator, denominator): Ratio) -> Ratio {
+    let divisor = gcd(numerator, denominator).max(1);
+    (numerator / divisor, denominator / divisor)
+}
+
+/// 倍率を有理数に直す。1.23456 や 1/3 のように設定値として与えられる倍率を誤差なく表すため、
+/// 元の値に戻る最短の10進表記が小数点以下9桁までならその小数とし、
+/// そうでなければ浮動小数点数に直すと元の値に戻る有理数のうち最も分母が小さいものとする。
+/// 負の値や有限でない値、分母が MAX_DENOMINATOR 以下の有理数で表せない値はエラーにする。
+pub fn to_ratio(value: f64) -> Result<Ratio, String> {
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("factor must be non-negative and finite: {}", value));
+    }
+    let not_representable = || {
+        format!(
+            "factor {} cannot be represented as a ratio with a denominator up to {}",
+            value, MAX_DENOMINATOR
+        )
+    };
+    if let Some(ratio) = parse_decimal(&value.to_string()) {
+        return Ok(ratio);
+    }
+
+    // 浮動小数点数の値を 仮数 / 2^指数 の有理数として正確に表す
+    let bits = value.to_bits();
+    let biased_exponent = ((bits >> 52) & 0x7ff) as i32;
+    let (mantissa, exponent) = if biased_exponent == 0 {
+        (bits & ((1 << 52) - 1), -1074)
+    } else {
+        ((bits & ((1 << 52) - 1)) | (1 << 52), biased_exponent - 1075)
+    };
+    if exponent >= 0 {
+        if exponent > 64 {
+            return Err(not_representable());
+        }
+        return Ok(((mantissa as i128) << exponent, 1));
+    }
+    if exponent < -126 {
+        return Err(not_representable());
+    }
+
+    // 連分数展開の近似分数を、浮動小数点数に直すと元の値に戻るまで順に求める
+    let (mut numerator, mut denominator) = (mantissa as i128, 1_i128 << -exponent);
+    let (mut p0, mut q0, mut p1, mut q1) = (0_i128, 1_i128, 1_i128, 0_i128);
+    while denominator != 0 {
+        let a = numerator / denominator;
+        let (Some(p2), Some(q2)) = (
+            a.checked_mul(p1).and_then(|v| v.checked_add(p0)),
+            a.checked_mul(q1).and_then(|v| v.checked_add(q0)),
+        ) else {
+            break;
+        };
+        if q2 > MAX_DENOMINATOR {
+            break;
+        }
+        (p0, q0, p1, q1) = (p1, q1, p2, q2);
+        if p1 as f64 / q1 as f64 == value {
+            return Ok((p1, q1));
+        }
+        (numerator, denominator) = (denominator, numerator - a * denominator);
+    }
+    Err(not_representable())
+}
+
+/// 小数点以下が9桁までの10進表記を有理数に直す。
+fn parse_decimal(text: &str) -> Option<Ratio> {
+    let (integer, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if fraction.len() > 9 {
+        return None;
+    }
+    let denominator = 10_i128.pow(fraction.len() as u32);
+    let numerator = integer
+        .parse::<i128>()
+        .ok()?
+        .checked_mul(denominator)?
+        .checked_add(if fraction.is_empty() {
+            0
+        } else {
+            fraction.parse::<i128>().ok()?
+        })?;
+    Some(reduce((numerator, denominator)))
+}
+
+/// 2つの有理数の積を求める。i128 に収まらなければエラーにする。
+pub fn mul_ratio(a: Ratio, b: Ratio) -> Result<Ratio, String> {
+    let (a, b) = (reduce((a.0, b.1)), reduce((b.0, a.1)));
+    match (a.0.checked_mul(b.0), a.1.checked_mul(b.1)) {
+        (Some(numerator), Some(denominator)) => Ok(reduce((numerator, denominator))),
+        _ => Err("factor is too large to calculate exactly".to_string()),
+    }
+}
+
+/// 2つの有理数の和を求める。i128 に収まらなければエラーにする。
+pub fn add_ratio(a: Ratio, b: Ratio) -> Result<Ratio, String> {
+    let divisor = gcd(a.1, b.1);
+    let sum = (|| {
+        let denominator = (a.1 / divisor).checked_mul(b.1)?;
+        let numerator =
+            a.0.checked_mul(b.1 / divisor)?
+                .checked_add(b.0.checked_mul(a.1 / divisor)?)?;
+        Some((numerator, denominator))
+    })();
+    match sum {
+        Some(sum) => Ok(reduce(sum)),
+        None => Err("factor is too large to calculate exactly".to_string()),
+    }
+}
+
+/// 整数に倍率を掛けて小数点以下を切り捨てる。結果が i64 に収まらなければ i64 の範囲に丸める。
+pub fn mul_floor(value: i64, (numerator, denominator): Ratio) -> i64 {
+    match (value as i128).checked_mul(numerator) {
+        Some(product) => product
+            .div_euclid(denominator)
+            .clamp(i64::MIN as i128, i64::MAX as i128) as i64,
+        None if value < 0 => i64::MIN,
+        None => i64::MAX,
+    }
+}
+
+/// 同時消し倍率 (1 + (同時消し数 - 消えるのに必要な数) × 同時消し係数 × 同時消し倍率の倍率) × 分離数 を有理数で求める。
+pub fn calc_exact_popping_factor(
+    environment: &SimulationEnvironment,
+    simultaneous_num: u32,
+    separated_blocks_num: u32,
+) -> Result<Ratio, String> {
+    let coefficient = match environment.popping_coefficient {
+        Some(coefficient) => to_ratio(coefficient)?,
+        None => DEFAULT_POPPING_COEFFICIENT,
+    };
+    let extra_num = (
+        simultaneous_num as i128 - environment.minimum_puyo_num_for_popping as i128,
+        1,
+    );
+    let extra = mul_ratio(
+        mul_ratio(extra_num, coefficient)?,
+        to_ratio(environment.popping_leverage)?,
+    )?;
+    mul_ratio(add_ratio((1, 1), extra)?, (separated_blocks_num as i128, 1))
+}
+
+/// 連鎖倍率 (連鎖係数 × 連鎖倍率の倍率 + 1) を有理数で求める。係数の選び方は damage::calc_chain_factor と同じ。
+pub fn calc_exact_chain_factor(
+    environment: &SimulationEnvironment,
+    chain_num: u32,
+) -> Result<Ratio, String> {
+    if chain_num < 1 {
+        return Err("chain_num must be positive".to_string());
+    }
+    let coefficient = match environment.chain_coefficient_table.as_deref() {
+        Some(table) if !table.is_empty() => {
+            to_ratio(table[cmp::min(chain_num as usize, table.len()) - 1])?
+        }
+        _ if chain_num >= 4 => (chain_num as i128 + 1, 5),
+        _ => DEFAULT_CHAIN_COEFFICIENT_TABLE[chain_num as usize - 1],
+    };
+    add_ratio(
+        mul_ratio(coefficient, to_ratio(environment.chain_leverage)?)?,
+        (1, 1),
+    )
+}
+
+/// ブースト倍率 (1 + ブーストカウント × 0.04。カウントは50まで) を有理数で求める。
+pub fn calc_exact_boost_ratio(boost_count: u32) -> Ratio {
+    reduce((25 + cmp::min(boost_count, 50) as i128, 25))
+}
+
+/** 盤面 (フィールドとネクスト) のぷよの数。同時消し数・分離数・連鎖数はこれを超えない。 */
+const MAX_PUYO_NUM: u32 = 56;
+
+/// 環境の設定値から、整数で計算するときの倍率を組み立てられるか検証する。
+/// 倍率は同時消し数・分離数・連鎖数について単調なので、盤面で起こりうる最大の値で組み立てられれば常に組み立てられる。
+pub fn validate_exact_factors(environment: &SimulationEnvironment) -> Result<(), String> {
+    calc_exact_popping_factor(environment, MAX_PUYO_NUM, MAX_PUYO_NUM)?;
+    for chain_num in 1..=MAX_PUYO_NUM {
+        calc_exact_chain_factor(environment, chain_num)?;
+    }
+    for strength in environment.special_puyo_strengths.values() {
+        to_ratio(strength.strength_per_puyo)?;
+    }
+    Ok(())
+}
+
+/// 攻撃力 attack で対象の色属性を攻撃したときのダメージを整数で求める。
+/// 連鎖ごとに 攻撃力 × 同時消し倍率 (切り捨て) × 連鎖倍率 (切り捨て) を求め、
+/// 特殊ぷよの強さの分を加えた合計にブースト倍率を掛けて切り捨てる。
+/// 倍率を有理数で表せなければエラーを返す (SimulationEnvironment::validate を通った環境ではエラーにならない)。
+pub fn calc_exact_attr_damage(
+    environment: &SimulationEnvironment,
+    attack: i64,
+    chains: &Vec<Chain>,
+    attr: PuyoAttr,
+) -> Result<i64, String> {
+    calc_exact_attr_damage_with_affinity(environment, attack, chains, attr, 1.0)
+}
+
+/// calc_exact_attr_damage と同じく整数で求めるが、連鎖ごとの色属性による部分に敵との相性の倍率 affinity を掛けて切り捨てる。
+/// 特殊ぷよの強さの分には相性を掛けない。
+pub fn calc_exact_attr_damage_with_affinity(
+    environment: &SimulationEnvironment,
+    attack: i64,
+    chains: &Vec<Chain>,
+    attr: PuyoAttr,
+    affinity: f64,
+) -> Result<i64, String> {
+    let affinity = to_ratio(affinity)?;
+    let mut damage = 0;
+
+    for chain in chains {
+        let chain_factor = calc_exact_chain_factor(environment, chain.chain_num)?;
+
+        if let Some(attr_chain) = chain.attributes.get(&attr) {
+            if attr_chain.separated_blocks_num > 0 {
+                let popping_factor = calc_exact_popping_factor(
+                    environment,
+                    chain.simultaneous_num,
+                    attr_chain.separated_blocks_num,
+                )?;
+                damage = i64::saturating_add(
+                    damage,
+                    mul_floor(
+                        mul_floor(mul_floor(attack, popping_factor), chain_factor),
+                        affinity,
+                    ),
+                );
+            }
+        }
+
+        for special_attr in SPECIAL_ATTRS {
+            let Some(special_chain) = chain.attributes.get(&special_attr) else {
+                continue;
+            };
+            let rule = environment.get_special_puyo_strength(special_attr);
+            if !rule.applies_to(attr) {
+                continue;
+            }
+            damage = i64::saturating_add(
+                damage,
+                rule.calc_strength(
+                    attack.saturating_mul(special_chain.popped_count as i64),
+                    to_ratio(rule.strength_per_puyo)?,
+                    chain_factor,
+                    mul_floor,
+                ),
+            );
+        }
+    }
+
+    Ok(mul_floor(
+        damage,
+        calc_exact_boost_ratio(sum_boost_count(chains)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chain::AttributeChain,
+        chain_helper::{calc_boost_ratio, sum_colored_attr_damage},
+        damage::{calc_chain_factor, calc_popping_factor},
+        trace_mode::TraceMode,
+    };
+    use std::collections::HashMap;
+
+    fn environment() -> SimulationEnvironment {
+        SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 5,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_to_ratio() {
+        assert_eq!(to_ratio(0.0), Ok((0, 1)));
+        assert_eq!(to_ratio(1.0), Ok((1, 1)));
+        assert_eq!(to_ratio(1.15), Ok((23, 20)));
+        assert_eq!(to_ratio(1.4), Ok((7, 5)));
+        assert_eq!(to_ratio(1.0 + 0.15 * 5.0), Ok((7, 4)));
+        assert_eq!(to_ratio(1.0 / 3.0), Ok((1, 3)));
+        assert_eq!(to_ratio(calc_boost_ratio(3)), Ok((28, 25)));
+        assert_eq!(to_ratio(27.6), Ok((138, 5)));
+        // 分母が 10000 を超える倍率も近似せずに表す
+        assert_eq!(to_ratio(1.23456), Ok((3858, 3125)));
+        assert_eq!(to_ratio(0.123456789), Ok((123456789, 1_000_000_000)));
+        assert_eq!(to_ratio(1e15), Ok((1_000_000_000_000_000, 1)));
+        // 負の値や有限でない値、分母が上限を超える値はエラーにする
+        assert!(to_ratio(f64::NAN).is_err());
+        assert!(to_ratio(f64::INFINITY).is_err());
+        assert!(to_ratio(-1.5).is_err());
+        assert!(to_ratio(1e-10).is_err());
+        assert!(to_ratio(1e40).is_err());
+    }
+
+    #[test]
+    fn test_mul_floor() {
+        assert_eq!(mul_floor(1234, (23, 20)), 1419);
+        assert_eq!(mul_floor(1419, (7, 5)), 1986);
+        assert_eq!(mul_floor(3, (1, 3)), 1);
+        assert_eq!(mul_floor(5, (1, 3)), 1);
+        assert_eq!(mul_floor(i64::MAX, (2, 1)), i64::MAX);
+        assert_eq!(mul_floor(i64::MAX, (i128::MAX, 1)), i64::MAX);
+    }
+
+    #[test]
+    fn test_calc_exact_factors() {
+        // 既定の係数では浮動小数点の計算式と同じ値を有理数で表す
+        let mut environment = environment();
+        assert_eq!(calc_exact_popping_factor(&environment, 4, 1), Ok((1, 1)));
+        assert_eq!(calc_exact_popping_factor(&environment, 5, 1), Ok((23, 20)));
+        assert_eq!(calc_exact_popping_factor(&environment, 8, 2), Ok((16, 5)));
+        for (chain_num, expected) in [
+            (1, (1, 1)),
+            (2, (7, 5)),
+            (3, (17, 10)),
+            (4, (2, 1)),
+            (10, (16, 5)),
+        ] {
+            assert_eq!(
+                calc_exact_chain_factor(&environment, chain_num),
+                Ok(expected)
+            );
+        }
+        assert!(calc_exact_chain_factor(&environment, 0).is_err());
+        assert_eq!(calc_exact_boost_ratio(3), (28, 25));
+        assert_eq!(calc_exact_boost_ratio(100), (3, 1));
+
+        // 既定でない倍率も設定値から有理数のまま組み立てる
+        // 1 + 0.15 × 1.23456 = 1.185184、1.4 × 1.23456 の係数 0.4 × 1.23456 + 1 = 1.493824
+        environment.popping_leverage = 1.23456;
+        environment.chain_leverage = 1.23456;
+        assert_eq!(
+            calc_exact_popping_factor(&environment, 5, 1),
+            Ok((37037, 31250))
+        );
+        assert_eq!(calc_exact_chain_factor(&environment, 2), Ok((23341, 15625)));
+        environment.chain_coefficient_table = Some(Vec::from([0.0, 1.0 / 3.0]));
+        assert_eq!(calc_exact_chain_factor(&environment, 5), Ok((4411, 3125)));
+
+        assert!(validate_exact_factors(&environment).is_ok());
+        environment.popping_leverage = 1e-10;
+        assert!(validate_exact_factors(&environment).is_err());
+    }
+
+    #[test]
+    fn test_calc_exact_attr_damage_with_precise_leverage() {
+        // 同時消し倍率 1.185184 を分母 10000 以下の有理数に近似すると 32/27 になり、
+        // 27 × 1.185184 = 31.999968 の切り捨てが 32 になってしまう
+        let mut environment = environment();
+        environment.popping_leverage = 1.23456;
+        let chains: Vec<Chain> = Vec::from([Chain {
+            chain_num: 1,
+            simultaneous_num: 5,
+            boost_count: 0,
+            puyo_tsukai_count: 5,
+            attributes: HashMap::from([(
+                PuyoAttr::Red,
+                AttributeChain {
+                    strength: 1.185184,
+                    popped_count: 5,
+                    separated_blocks_num: 1,
+                },
+            )]),
+            popped_chance_num: 0,
+            is_all_cleared: false,
+            targeted_special_strengths: HashMap::new(),
+        }]);
+
+        assert_eq!(
+            calc_exact_attr_damage(&environment, 31250, &chains, PuyoAttr::Red),
+            Ok(37037)
+        );
+        assert_eq!(
+            calc_exact_attr_damage(&environment, 27, &chains, PuyoAttr::Red),
+            Ok(31)
+        );
+        assert!(calc_exact_attr_damage_with_affinity(
+            &environment,
+            31250,
+            &chains,
+            PuyoAttr::Red,
+            f64::NAN
+        )
+        .is_err());
+    }
+
+    // 計算式から求めた期待値による回帰テスト (ゲーム内の実測値による検証ではない)。
+    // 浮動小数点のままだと 1234 × 1.15 × 1.4 = 1986.74 となる箇所で、各段階の切り捨てにより 1986 になることを確認する。
+    #[test]
+    fn test_calc_exact_attr_damage() {
+        // Arrange
+        let environment = environment();
+        let chains: Vec<Chain> = Vec::from([
+            Chain {
+                chain_num: 1,
+                simultaneous_num: 4,
+                boost_count: 1,
+                puyo_tsukai_count: 4,
+                attributes: HashMap::from([(
+                    PuyoAttr::Red,
+                    AttributeChain {
+                        strength: 1.0,
+                        popped_count: 4,
+                        separated_blocks_num: 1,
+                    },
+                )]),
+                popped_chance_num: 0,
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
+            },
+            Chain {
+                chain_num: 2,
+                simultaneous_num: 5,
+                boost_count: 2,
+                puyo_tsukai_count: 5,
+                attributes: HashMap::from([
+                    (
+                        PuyoAttr::Red,
+                        AttributeChain {
+                            strength: 1.61,
+                            popped_count: 4,
+                            separated_blocks_num: 1,
+                        },
+                    ),
+                    (
+                        PuyoAttr::Prism,
+                        AttributeChain {
+                            strength: 3.0,
+                            popped_count: 1,
+                            separated_blocks_num: 0,
+                        },
+                    ),
+                ]),
+                popped_chance_num: 0,
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
+            },
+        ]);
+
+        // Act & Assert
+        // 赤: 1連鎖目 1234 × 1.0 × 1.0 = 1234
+        //     2連鎖目 floor(1234 × 1.15) = 1419, floor(1419 × 1.4) = 1986
+        //     プリズム 1234 × 3 = 3702
+        //     合計 6922 にブースト倍率 1.12 を掛けて floor(7752.64) = 7752
+        assert_eq!(
+            calc_exact_attr_damage(&environment, 1234, &chains, PuyoAttr::Red),
+            Ok(7752)
+        );
+        // 青: プリズムのみ 3702 × 1.12 = floor(4146.24) = 4146
+        assert_eq!(
+            calc_exact_attr_damage(&environment, 1234, &chains, PuyoAttr::Blue),
+            Ok(4146)
+        );
+    }
+
+    // 各段階で切り捨てるので、整数で求めたダメージは浮動小数点のダメージ以下で、
+    // 差は切り捨てによる誤差 (連鎖ごとに 連鎖倍率 + 1 未満、ブースト倍率を掛けた後にさらに 1 未満) に収まる。
+    #[test]
+    fn test_calc_exact_attr_damage_is_bounded_by_float_damage() {
+        let environment = environment();
+
+        for chain_len in 1..=8 {
+            for simultaneous_num in 4..=12 {
+                let chains: Vec<Chain> = (1..=chain_len)
+                    .map(|chain_num| {
+                        let popping_factor =
+                            calc_popping_factor(simultaneous_num, 1, None, None, None);
+                        let chain_factor = calc_chain_factor(chain_num, None, None).unwrap();
+                        let mut attributes = HashMap::from([(
+                            PuyoAttr::Red,
+                            AttributeChain {
+                                strength: popping_factor * chain_factor,
+                                popped_count: simultaneous_num,
+                                separated_blocks_num: 1,
+                            },
+                        )]);
+                        if chain_num == 1 {
+                            attributes.insert(
+                                PuyoAttr::Prism,
+                                AttributeChain {
+                                    strength: 3.0,
+                                    popped_count: 1,
+                                    separated_blocks_num: 0,
+                                },
+                            );
+                        }
+                        Chain {
+                            chain_num,
+                            simultaneous_num,
+                            boost_count: chain_num % 3,
+                            puyo_tsukai_count: simultaneous_num,
+                            attributes,
+                            popped_chance_num: 0,
+                            is_all_cleared: false,
+                            targeted_special_strengths: HashMap::new(),
+                        }
+                    })
+                    .collect();
+                let boost_ratio = calc_boost_ratio(sum_boost_count(&chains));
+                let max_error = boost_ratio
+                    * (1..=chain_len)
+                        .map(|chain_num| calc_chain_factor(chain_num, None, None).unwrap() + 1.0)
+                        .sum::<f64>()
+                    + 1.0;
+
+                for attack in [1, 7, 999, 1234, 4321, 65535] {
+                    let exact = calc_exact_attr_damage(&environment, attack, &chains, PuyoAttr::Red)
+                        .unwrap() as f64;
+                    let float = attack as f64 * sum_colored_attr_damage(&chains, PuyoAttr::Red);
+                    assert!(exact <= float + 1e-6, "{} > {}", exact, float);
+                    assert!(float - exact < max_error, "{} - {}", float, exact);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_calc_exact_attr_damage_with_huge_attack() {
+        let environment = environment();
+        let chains: Vec<Chain> = Vec::from([Chain {
+            chain_num: 5,
+            simultaneous_num: 12,
+            boost_count: 3,
+            puyo_tsukai_count: 12,
+            attributes: HashMap::from([
+                (
+                    PuyoAttr::Red,
+                    AttributeChain {
+                        strength: 1.0,
+                        popped_count: 8,
+                        separated_blocks_num: 2,
+                    },
+                ),
+                (
+                    PuyoAttr::Prism,
+                    AttributeChain {
+                        strength: 12.0,
+                        popped_count: 4,
+                        separated_blocks_num: 0,
+                    },
+                ),
+            ]),
+            popped_chance_num: 0,
+            is_all_cleared: false,
+            targeted_special_strengths: HashMap::new(),
+        }]);
+
+        // i64 に収まらないダメージはパニックせずに上限に丸める
+        assert_eq!(
+            calc_exact_attr_damage(&environment, i64::MAX / 2, &chains, PuyoAttr::Red),
+            Ok(i64::MAX)
+        );
+    }
+}
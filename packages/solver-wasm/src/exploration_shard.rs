@@ -0,0 +1,92 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::trace_mode::TraceMode;
+
+/**
+ * 探索を分担するときの最小単位。
+ * なぞり始めの座標から、2番目になぞる座標ごとに探索を分ける。
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ShardUnit {
+    /** なぞるときのトレースモード */
+    pub trace_mode: TraceMode,
+    /** なぞり始めの座標のインデックス */
+    pub start_index: u8,
+    /**
+     * 2番目になぞる座標の、なぞり始めの座標から見た次の候補リストでのインデックス。
+     * None なら、なぞり始めの座標だけをなぞる候補を表す。
+     */
+    pub next_candidate_index: Option<u8>,
+}
+
+/** 探索の分担 (ワーカー1つ分の仕事) */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExplorationShard {
+    /** 分担のインデックス。結果はこの順に併合する。 */
+    pub shard_index: u32,
+    /** 探索する単位のリスト。直列に探索したときの順に並んでいる。 */
+    pub units: Vec<ShardUnit>,
+    /** 見積もった候補数 */
+    pub estimated_candidates_num: u64,
+}
+
+/// 重みのリストを、順番を保ったまま重みの合計がなるべく等しい shard_num 個の連続した区間に分ける。
+/// 区間の数は常に shard_num 個で、重みが足りなければ空の区間もできる。
+pub fn partition_by_weights(weights: &[u64], shard_num: u32) -> Vec<Range<usize>> {
+    let shard_num = shard_num.max(1) as u128;
+    let total: u128 = weights.iter().map(|w| *w as u128).sum();
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut accumulated: u128 = 0;
+    for i in 1..=shard_num {
+        // i 番目の区間は累積の重みが total × i / shard_num に最も近くなるところで切る
+        let goal = total * i / shard_num;
+        let mut end = start;
+        while end < weights.len() {
+            let next = accumulated + weights[end] as u128;
+            if next > goal && next - goal > goal.saturating_sub(accumulated) {
+                break;
+            }
+            accumulated = next;
+            end += 1;
+        }
+        if i == shard_num {
+            end = weights.len();
+        }
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_by_weights() {
+        assert_eq!(
+            partition_by_weights(&[1, 1, 1, 1, 1, 1], 3),
+            [0..2, 2..4, 4..6]
+        );
+        assert_eq!(
+            partition_by_weights(&[10, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1], 2),
+            [0..1, 1..11]
+        );
+        assert_eq!(partition_by_weights(&[5, 0, 0, 5], 2), [0..3, 3..4]);
+        // 前の区間が目標を超えていれば、次の区間は空になる
+        assert_eq!(
+            partition_by_weights(&[10, 1, 1, 1], 4),
+            [0..0, 0..1, 1..1, 1..4]
+        );
+        // 重みが足りなければ空の区間もできる
+        assert_eq!(partition_by_weights(&[3], 3), [0..0, 0..1, 1..1]);
+        assert_eq!(partition_by_weights(&[], 2), [0..0, 0..0]);
+        // shard_num が 0 なら 1 として扱う
+        let ranges = partition_by_weights(&[1, 2], 0);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0], 0..2);
+    }
+}
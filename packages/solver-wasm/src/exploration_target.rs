@@ -1,8 +1,14 @@
+use std::collections::{HashMap, HashSet};
+
 use num_derive::{FromPrimitive, ToPrimitive};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
-use crate::puyo_attr::PuyoAttr;
+use crate::{
+    board_transform::BoardTransform, constraint::SolutionConstraint, enemy::Enemy,
+    next_expectation::NextExpectation, pareto::ParetoObjective, puyo_attr::PuyoAttr,
+    puyo_coord::PuyoCoord, scoring::Scoring, team::Team, trace_mode::TraceMode,
+};
 
 /** 探索カテゴリー */
 #[derive(
@@ -25,6 +31,16 @@ pub enum ExplorationCategory {
     SkillPuyoCount = 2,
     /** ぷよ使いカウント */
     PuyotsukaiCount = 3,
+    /** 敵との属性相性や軽減を考慮したダメージ */
+    EffectiveDamage = 4,
+    /** ハートによる回復量 */
+    Recovery = 5,
+    /** 連鎖数 */
+    ChainCount = 6,
+    /** 最大の同時消し数 */
+    MaxSimultaneousNum = 7,
+    /** 全ての連鎖で消えたぷよの総数 */
+    TotalPoppedCount = 8,
 }
 
 /** 好みの種類 */
@@ -86,6 +102,8 @@ pub enum PreferenceKind {
     MoreHeartPop = 26,
     /** おじゃま消し数が多い (固ぷよも含む。固ぷよが完全に消えたら2個分) */
     MoreOjamaPop = 27,
+    /** ハートによる回復量が多い */
+    MoreRecovery = 28,
 
     //
     // 数が少ない
@@ -98,6 +116,103 @@ pub enum PreferenceKind {
     LessHeartPop = 36,
     /** おじゃま消し数が少ない (固ぷよも含む。固ぷよが完全に消えたら2個分) */
     LessOjamaPop = 37,
+    /** ハートによる回復量が少ない */
+    LessRecovery = 38,
+
+    //
+    // 連鎖の規模が大きい
+    //
+    /** 連鎖数が多い */
+    MoreChainCount = 41,
+    /** 最大の同時消し数が多い */
+    MoreMaxSimultaneousNum = 42,
+    /** 消えたぷよの総数が多い */
+    MoreTotalPoppedCount = 43,
+
+    //
+    // 連鎖の規模が小さい
+    //
+    /** 連鎖数が少ない */
+    LessChainCount = 51,
+    /** 最大の同時消し数が少ない */
+    LessMaxSimultaneousNum = 52,
+    /** 消えたぷよの総数が少ない */
+    LessTotalPoppedCount = 53,
+}
+
+impl PreferenceKind {
+    /// 区間の幅を指定できる (値の大小で比べる) 好みかどうか。
+    pub fn accepts_bucket_width(&self) -> bool {
+        matches!(
+            self,
+            PreferenceKind::BiggerValue
+                | PreferenceKind::SmallerValue
+                | PreferenceKind::SmallerTraceNum
+                | PreferenceKind::BiggerTraceNum
+                | PreferenceKind::MoreChancePop
+                | PreferenceKind::MorePrismPop
+                | PreferenceKind::MoreHeartPop
+                | PreferenceKind::MoreOjamaPop
+                | PreferenceKind::MoreRecovery
+                | PreferenceKind::LessChancePop
+                | PreferenceKind::LessPrismPop
+                | PreferenceKind::LessHeartPop
+                | PreferenceKind::LessOjamaPop
+                | PreferenceKind::LessRecovery
+                | PreferenceKind::MoreChainCount
+                | PreferenceKind::MoreMaxSimultaneousNum
+                | PreferenceKind::MoreTotalPoppedCount
+                | PreferenceKind::LessChainCount
+                | PreferenceKind::LessMaxSimultaneousNum
+                | PreferenceKind::LessTotalPoppedCount
+        )
+    }
+}
+
+/**
+ * 好みで比べる値を区切る区間の幅。
+ * 値を区間に区切り、同じ区間に入る2つの値を同等とみなして次の好みで比べる。
+ * 絶対値が absolute / relative 未満の範囲は幅 absolute の区間、それ以上の範囲は隣の区間との比が 1 + relative の区間に区切る。
+ * 区間は値だけで決まるので、同等の関係は推移的 (A と B、B と C が同等なら A と C も同等) になり、解を比べる順序に結果が依存しない。
+ * 2つの値の差で比べる許容誤差とは異なり、差が absolute より小さくても区間の境界をまたぐ2つの値は同等とみなさない。
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BucketWidth {
+    /** 絶対値が小さい範囲の区間の幅 */
+    #[serde(default)]
+    pub absolute: f64,
+    /** 絶対値が大きい範囲の区間の相対的な幅 (隣の区間との比 - 1) */
+    #[serde(default)]
+    pub relative: f64,
+}
+
+impl BucketWidth {
+    /// 2つの値が同じ区間に入るかどうか。
+    pub fn is_same_bucket(&self, a: f64, b: f64) -> bool {
+        if self.absolute <= 0.0 && self.relative <= 0.0 {
+            return a == b;
+        }
+        self.bucket(a) == self.bucket(b)
+    }
+
+    /// 値が入る区間を (符号, 区間の番号) で返す。幅 absolute の区間は符号を 0 とし、値の符号をまたいで番号を振る。
+    fn bucket(&self, value: f64) -> (i8, i64) {
+        let magnitude = value.abs();
+        let threshold = if self.relative > 0.0 {
+            self.absolute / self.relative
+        } else {
+            f64::INFINITY
+        };
+        if magnitude < threshold {
+            return (0, (value / self.absolute).floor() as i64);
+        }
+        if magnitude == 0.0 {
+            return (0, 0);
+        }
+        let base = if threshold > 0.0 { threshold } else { 1.0 };
+        let index = ((magnitude / base).ln() / self.relative.ln_1p()).floor() as i64;
+        (if value > 0.0 { 1 } else { -1 }, index)
+    }
 }
 
 /** カウントボーナスのタイプ */
@@ -141,14 +256,115 @@ pub struct ExplorationTarget {
     pub category: ExplorationCategory,
     /** 各好みの優先度配列。インデックスの小さい要素の方を優先する。 */
     pub preference_priorities: Vec<PreferenceKind>, // All
+    /**
+     * 好みごとの区間の幅。指定した好みでは値が同じ区間に入る解を同等とみなし、次の好みで比べる (BucketWidth を参照)。
+     */
+    #[serde(default)]
+    pub preference_bucket_widths: HashMap<PreferenceKind, BucketWidth>, // All
     /** 最適解のベスト何個までを結果に返すか */
     pub optimal_solution_count: u32,
     /** 主属性 */
-    pub main_attr: Option<PuyoAttr>, // ::Damage, ::SkillPuyoCount
+    pub main_attr: Option<PuyoAttr>, // ::Damage, ::SkillPuyoCount, ::EffectiveDamage
     /** 副属性 */
-    pub sub_attr: Option<PuyoAttr>, // ::Damage
+    pub sub_attr: Option<PuyoAttr>, // ::Damage, ::EffectiveDamage
     /** 副属性 / 主属性 のダメージ率 (1/3か1)  */
-    pub main_sub_ratio: Option<f64>, // ::Damage
+    pub main_sub_ratio: Option<f64>, // ::Damage, ::EffectiveDamage
     /** 加速ボーナス */
     pub counting_bonus: Option<StepCountingBonus>, // ::SKillPuyoCount
+    /** 不明なネクストぷよを補って value を期待値で評価する設定。None なら不明なマスは空として扱う。 */
+    #[serde(default)]
+    pub next_expectation: Option<NextExpectation>, // All
+    /** デッキ。指定すると最適解にデッキのダメージを付与する。回復量はデッキの回復力の合計 (未指定なら1) で計算する。EffectiveDamage では主属性・副属性の代わりにデッキの各カードで攻撃する。 */
+    #[serde(default)]
+    pub team: Option<Team>, // All
+    /** 敵。EffectiveDamage では必須 */
+    #[serde(default)]
+    pub enemy: Option<Enemy>, // ::EffectiveDamage
+    /**
+     * パレート最適解を求めるときの目的のリスト。
+     * 指定すると optimal_solution_count を無視し、これらの目的について他のどの解にも支配されない解を全て返す。
+     * 目的の値が全て等しい解は preference_priorities で最も良いものだけを残し、結果は preference_priorities の順に並べる。
+     */
+    #[serde(default)]
+    pub pareto_objectives: Option<Vec<ParetoObjective>>, // All
+    /** 採点式。指定すると探索カテゴリーに応じた値の代わりにこの式の値を value とする。 */
+    #[serde(default)]
+    pub scoring: Option<Scoring>, // All
+    /** 解が満たすべき条件のリスト。全ての条件を満たす解だけを最適解の候補とする。 */
+    #[serde(default)]
+    pub constraints: Vec<SolutionConstraint>, // All
+    /** なぞりに必ず含める座標の集合 */
+    #[serde(default)]
+    pub required_coords: HashSet<PuyoCoord>, // All
+    /** なぞってはいけない座標の集合 */
+    #[serde(default)]
+    pub forbidden_coords: HashSet<PuyoCoord>, // All
+    /**
+     * 一筆書きでなぞれる (隣接する座標を順にたどって全ての座標を1回ずつ通れる) 集合だけを候補にするかどうか。
+     * true のとき、各解にはなぞる順番 (SolutionResult::drawing_order) を付与する。
+     */
+    #[serde(default)]
+    pub drawable_path_only: bool, // All
+    /**
+     * 試すトレースモードのリスト。指定すると SimulationEnvironment::trace_mode の代わりに各トレースモードでなぞり、
+     * 全てのトレースモードの解から最適解を選ぶ。各解の trace_mode にそのなぞりで使ったトレースモードが入る。
+     */
+    #[serde(default)]
+    pub trace_modes: Option<Vec<TraceMode>>, // All
+    /** なぞる前に盤面に適用するスキル効果のリスト。先頭から順に適用する。 */
+    #[serde(default)]
+    pub board_transforms: Vec<BoardTransform>, // All
+}
+
+impl ExplorationTarget {
+    /// 探索を始める前に探索対象の設定が正しいか検証する。
+    pub fn validate(&self) -> Result<(), String> {
+        for (kind, bucket_width) in self.preference_bucket_widths.iter() {
+            if !kind.accepts_bucket_width() {
+                return Err(format!("{:?} does not accept bucket_width", kind));
+            }
+            let is_valid = |v: f64| v >= 0.0 && v.is_finite();
+            if !is_valid(bucket_width.absolute) || !is_valid(bucket_width.relative) {
+                return Err(format!(
+                    "bucket_width of {:?} must be non-negative and finite",
+                    kind
+                ));
+            }
+        }
+        if self.category == ExplorationCategory::EffectiveDamage && self.enemy.is_none() {
+            return Err("enemy is required for EffectiveDamage".to_string());
+        }
+        if let Some(team) = &self.team {
+            team.validate()?;
+        }
+        if let Some(enemy) = &self.enemy {
+            enemy.validate()?;
+        }
+        if let Some(expectation) = &self.next_expectation {
+            expectation.validate()?;
+        }
+        if let Some(scoring) = &self.scoring {
+            scoring.validate()?;
+        }
+        for constraint in self.constraints.iter() {
+            constraint.validate()?;
+        }
+        if !self.required_coords.is_disjoint(&self.forbidden_coords) {
+            return Err("required_coords and forbidden_coords must not overlap".to_string());
+        }
+        for transform in self.board_transforms.iter() {
+            transform.validate()?;
+        }
+        if let Some(trace_modes) = &self.trace_modes {
+            if trace_modes.is_empty() {
+                return Err("trace_modes must not be empty".to_string());
+            }
+            for (i, trace_mode) in trace_modes.iter().enumerate() {
+                if trace_modes[..i].contains(trace_mode) {
+                    return Err(format!("{:?} appears twice in trace_modes", trace_mode));
+                }
+            }
+        }
+        Ok(())
+    }
 }
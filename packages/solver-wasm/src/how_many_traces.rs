@@ -7,12 +7,20 @@ const CELL_INDEXES: [u8; 48] = [
 ];
 
 pub fn count_candidates_num_for_each_indexes(max_trace_num: u32) -> [u64; 48] {
+    count_candidates_num_for_each_indexes_in_range(0, max_trace_num)
+}
+
+/// なぞり数が min_trace_num 以上 max_trace_num 以下の候補数を、なぞり始めのインデックスごとに数える。
+pub fn count_candidates_num_for_each_indexes_in_range(
+    min_trace_num: u32,
+    max_trace_num: u32,
+) -> [u64; 48] {
     let v: [u64; 48] = CELL_INDEXES
         .par_iter()
         .map(|i| {
             let coord = PuyoCoord::index_to_coord(*i).unwrap();
             let state = SolutionState::new(*i);
-            return advance_trace_for_count_up(&state, coord, max_trace_num);
+            return advance_trace_for_count_up(&state, coord, min_trace_num, max_trace_num);
         })
         .collect::<Vec<u64>>()
         .try_into()
@@ -21,12 +29,22 @@ pub fn count_candidates_num_for_each_indexes(max_trace_num: u32) -> [u64; 48] {
 }
 
 pub fn count_up_candidates_num(max_trace_num: u32) -> u64 {
-    return count_candidates_num_for_each_indexes(max_trace_num)
+    count_up_candidates_num_in_range(0, max_trace_num)
+}
+
+/// なぞり数が min_trace_num 以上 max_trace_num 以下の候補数を数える。
+pub fn count_up_candidates_num_in_range(min_trace_num: u32, max_trace_num: u32) -> u64 {
+    count_candidates_num_for_each_indexes_in_range(min_trace_num, max_trace_num)
         .iter()
-        .sum();
+        .sum()
 }
 
-fn advance_trace_for_count_up(state: &SolutionState, coord: PuyoCoord, max_trace_num: u32) -> u64 {
+fn advance_trace_for_count_up(
+    state: &SolutionState,
+    coord: PuyoCoord,
+    min_trace_num: u32,
+    max_trace_num: u32,
+) -> u64 {
     let trace_len = state.get_trace_coords().len();
     if trace_len == max_trace_num as usize {
         return 0;
@@ -35,17 +53,21 @@ fn advance_trace_for_count_up(state: &SolutionState, coord: PuyoCoord, max_trace
     let mut st = state.clone();
     st.add_trace_coord(coord);
 
-    let mut result = 1;
+    let mut result = if trace_len + 1 >= min_trace_num as usize {
+        1
+    } else {
+        0
+    };
 
     if trace_len == 0 {
         result += st
             .get_next_candidate_coords()
             .par_iter()
-            .map(|c| advance_trace_for_count_up(&st, *c, max_trace_num))
+            .map(|c| advance_trace_for_count_up(&st, *c, min_trace_num, max_trace_num))
             .sum::<u64>();
     } else {
         for next_coord in st.get_next_candidate_coords() {
-            result += advance_trace_for_count_up(&st, *next_coord, max_trace_num);
+            result += advance_trace_for_count_up(&st, *next_coord, min_trace_num, max_trace_num);
         }
     }
 
@@ -85,4 +107,12 @@ mod tests {
     fn test_count_up_candidates_num_for_max_5() {
         assert_eq!(count_up_candidates_num(5), 15359);
     }
+
+    #[test]
+    fn test_count_up_candidates_num_in_range() {
+        assert_eq!(count_up_candidates_num_in_range(1, 5), 15359);
+        assert_eq!(count_up_candidates_num_in_range(4, 5), 15359 - 804);
+        assert_eq!(count_up_candidates_num_in_range(3, 3), 804 - 200);
+        assert_eq!(count_up_candidates_num_in_range(5, 4), 0);
+    }
 }
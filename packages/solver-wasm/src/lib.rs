@@ -1,31 +1,55 @@
 // ベンチマークから参照するため pub を付けている。
 // pub をつけることによる wasm へのサイズ影響は 100 バイト程度。
+pub mod animation_step;
+pub mod board_transform;
 pub mod chain;
 pub mod chain_helper;
+pub mod constraint;
 pub mod damage;
+pub mod enemy;
+pub mod exact_damage;
+pub mod exploration_shard;
 pub mod exploration_target;
 pub mod how_many_traces;
+pub mod multi_turn_simulator;
+pub mod next_expectation;
+pub mod pareto;
 pub mod puyo;
 pub mod puyo_attr;
 pub mod puyo_coord;
+pub mod puyo_tracking;
 pub mod puyo_type;
+pub mod random;
+pub mod recovery;
+pub mod refill;
+pub mod scoring;
 pub mod simulation_environment;
 pub mod simulator_bb;
 pub mod solution;
 pub mod solution_explorer;
+pub mod team;
 pub mod trace_mode;
+pub mod trace_path;
 
 #[cfg_attr(test, macro_use)]
 extern crate approx;
 extern crate console_error_panic_hook;
 extern crate num_derive;
 
+use exploration_shard::ExplorationShard;
 use exploration_target::ExplorationTarget;
+use multi_turn_simulator::MultiTurnSimulator;
 use puyo::{Field, NextPuyos};
 use puyo_coord::PuyoCoord;
+use refill::RefillModel;
 use simulation_environment::SimulationEnvironment;
+use simulator_bb::SimulatorBB;
+use solution::ExplorationResult;
 use solution_explorer::SolutionExplorer;
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicBool, Ordering},
+};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -62,10 +86,16 @@ pub fn solve_all_traces(
         Ok(v) => v,
         Err(e) => return Err(JsError::new(&e.to_string())),
     };
+    if let Err(e) = exploration_target.validate() {
+        return Err(JsError::new(&e));
+    }
     let environment: SimulationEnvironment = match from_value(js_environment) {
         Ok(v) => v,
         Err(e) => return Err(JsError::new(&e.to_string())),
     };
+    if let Err(e) = environment.validate() {
+        return Err(JsError::new(&e));
+    }
     let boost_area_coord_set: HashSet<PuyoCoord> = match from_value(js_boost_area_coord_set) {
         Ok(v) => v,
         Err(e) => return Err(JsError::new(&e.to_string())),
@@ -93,6 +123,91 @@ pub fn solve_all_traces(
     }
 }
 
+/// solve_all_traces と同じ探索をしながら、探索の進捗 (ExplorationProgress) を js_on_progress に渡して呼ぶ。
+/// js_on_progress が true を返すと探索を打ち切り、途中までの結果を is_partial を true にして返す。
+/// js_on_progress が例外を投げたときや進捗を JS の値に変換できなかったときは、探索を打ち切ってエラーを返す。
+#[wasm_bindgen]
+pub fn solve_all_traces_with_progress(
+    js_exploration_target: JsValue,
+    js_environment: JsValue,
+    js_boost_area_coord_set: JsValue,
+    js_field: JsValue,
+    js_next_puyos: JsValue,
+    js_on_progress: &js_sys::Function,
+) -> Result<JsValue, JsError> {
+    console_error_panic_hook::set_once();
+
+    let exploration_target: ExplorationTarget = match from_value(js_exploration_target) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = exploration_target.validate() {
+        return Err(JsError::new(&e));
+    }
+    let environment: SimulationEnvironment = match from_value(js_environment) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = environment.validate() {
+        return Err(JsError::new(&e));
+    }
+    let boost_area_coord_set: HashSet<PuyoCoord> = match from_value(js_boost_area_coord_set) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let field: Field = match from_value(js_field) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let next_puyos: NextPuyos = match from_value(js_next_puyos) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let explorer = SolutionExplorer::new(
+        &exploration_target,
+        &environment,
+        &boost_area_coord_set,
+        &field,
+        &next_puyos,
+    );
+    let cancel_flag = AtomicBool::new(false);
+    let mut progress_error: Option<JsError> = None;
+    let exploration_result = explorer.solve_all_traces_with_progress(&cancel_flag, |progress| {
+        let cancelled = match to_value(progress) {
+            Ok(js_progress) => match js_on_progress.call1(&JsValue::NULL, &js_progress) {
+                Ok(v) => v.is_truthy(),
+                Err(e) => {
+                    progress_error = Some(JsError::new(&js_exception_message(&e)));
+                    true
+                }
+            },
+            Err(e) => {
+                progress_error = Some(JsError::new(&e.to_string()));
+                true
+            }
+        };
+        if cancelled {
+            cancel_flag.store(true, Ordering::Relaxed);
+        }
+    });
+    if let Some(e) = progress_error {
+        return Err(e);
+    }
+
+    match to_value(&exploration_result) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(JsError::new(&e.to_string())),
+    }
+}
+
+/// JS の例外からエラーメッセージを取り出す。Error でなければ値をそのまま文字列にする。
+fn js_exception_message(exception: &JsValue) -> String {
+    match exception.dyn_ref::<js_sys::Error>() {
+        Some(error) => String::from(error.message()),
+        None => format!("{:?}", exception),
+    }
+}
+
 #[wasm_bindgen]
 pub fn solve_traces_including_index(
     js_exploration_target: JsValue,
@@ -108,10 +223,16 @@ pub fn solve_traces_including_index(
         Ok(v) => v,
         Err(e) => return Err(JsError::new(&e.to_string())),
     };
+    if let Err(e) = exploration_target.validate() {
+        return Err(JsError::new(&e));
+    }
     let environment: SimulationEnvironment = match from_value(js_environment) {
         Ok(v) => v,
         Err(e) => return Err(JsError::new(&e.to_string())),
     };
+    if let Err(e) = environment.validate() {
+        return Err(JsError::new(&e));
+    }
     let boost_area_coord_set: HashSet<PuyoCoord> = match from_value(js_boost_area_coord_set) {
         Ok(v) => v,
         Err(e) => return Err(JsError::new(&e.to_string())),
@@ -138,3 +259,303 @@ pub fn solve_traces_including_index(
         Err(e) => Err(JsError::new(&e.to_string())),
     }
 }
+
+#[wasm_bindgen]
+pub fn simulate_trace(
+    js_exploration_target: JsValue,
+    js_environment: JsValue,
+    js_boost_area_coord_set: JsValue,
+    js_field: JsValue,
+    js_next_puyos: JsValue,
+    js_trace_coords: JsValue,
+) -> Result<JsValue, JsError> {
+    console_error_panic_hook::set_once();
+
+    let exploration_target: ExplorationTarget = match from_value(js_exploration_target) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = exploration_target.validate() {
+        return Err(JsError::new(&e));
+    }
+    let environment: SimulationEnvironment = match from_value(js_environment) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = environment.validate() {
+        return Err(JsError::new(&e));
+    }
+    let boost_area_coord_set: HashSet<PuyoCoord> = match from_value(js_boost_area_coord_set) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let field: Field = match from_value(js_field) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let next_puyos: NextPuyos = match from_value(js_next_puyos) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let trace_coords: Vec<PuyoCoord> = match from_value(js_trace_coords) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let explorer = SolutionExplorer::new(
+        &exploration_target,
+        &environment,
+        &boost_area_coord_set,
+        &field,
+        &next_puyos,
+    );
+    let solution_result = match explorer.simulate_trace(&trace_coords) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e)),
+    };
+
+    match to_value(&solution_result) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(JsError::new(&e.to_string())),
+    }
+}
+
+#[wasm_bindgen]
+pub fn simulate_trace_with_steps(
+    js_environment: JsValue,
+    js_boost_area_coord_set: JsValue,
+    js_field: JsValue,
+    js_next_puyos: JsValue,
+    js_trace_coords: JsValue,
+) -> Result<JsValue, JsError> {
+    console_error_panic_hook::set_once();
+
+    let environment: SimulationEnvironment = match from_value(js_environment) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = environment.validate() {
+        return Err(JsError::new(&e));
+    }
+    let boost_area_coord_set: HashSet<PuyoCoord> = match from_value(js_boost_area_coord_set) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let field: Field = match from_value(js_field) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let next_puyos: NextPuyos = match from_value(js_next_puyos) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let trace_coords: Vec<PuyoCoord> = match from_value(js_trace_coords) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let simulator = SimulatorBB {
+        environment: &environment,
+        boost_area: SimulatorBB::coords_to_board(boost_area_coord_set.iter()),
+    };
+    let (_, steps) = match simulator.simulate_trace_with_steps(&field, &next_puyos, &trace_coords) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e)),
+    };
+
+    match to_value(&steps) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(JsError::new(&e.to_string())),
+    }
+}
+
+#[wasm_bindgen]
+pub fn split_into_shards(
+    js_exploration_target: JsValue,
+    js_environment: JsValue,
+    js_boost_area_coord_set: JsValue,
+    js_field: JsValue,
+    js_next_puyos: JsValue,
+    shard_num: u32,
+) -> Result<JsValue, JsError> {
+    console_error_panic_hook::set_once();
+
+    let exploration_target: ExplorationTarget = match from_value(js_exploration_target) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = exploration_target.validate() {
+        return Err(JsError::new(&e));
+    }
+    let environment: SimulationEnvironment = match from_value(js_environment) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = environment.validate() {
+        return Err(JsError::new(&e));
+    }
+    let boost_area_coord_set: HashSet<PuyoCoord> = match from_value(js_boost_area_coord_set) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let field: Field = match from_value(js_field) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let next_puyos: NextPuyos = match from_value(js_next_puyos) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let explorer = SolutionExplorer::new(
+        &exploration_target,
+        &environment,
+        &boost_area_coord_set,
+        &field,
+        &next_puyos,
+    );
+    let shards = explorer.split_into_shards(shard_num);
+
+    match to_value(&shards) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(JsError::new(&e.to_string())),
+    }
+}
+
+#[wasm_bindgen]
+pub fn solve_shard(
+    js_exploration_target: JsValue,
+    js_environment: JsValue,
+    js_boost_area_coord_set: JsValue,
+    js_field: JsValue,
+    js_next_puyos: JsValue,
+    js_shard: JsValue,
+) -> Result<JsValue, JsError> {
+    console_error_panic_hook::set_once();
+
+    let exploration_target: ExplorationTarget = match from_value(js_exploration_target) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = exploration_target.validate() {
+        return Err(JsError::new(&e));
+    }
+    let environment: SimulationEnvironment = match from_value(js_environment) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = environment.validate() {
+        return Err(JsError::new(&e));
+    }
+    let boost_area_coord_set: HashSet<PuyoCoord> = match from_value(js_boost_area_coord_set) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let field: Field = match from_value(js_field) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let next_puyos: NextPuyos = match from_value(js_next_puyos) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let shard: ExplorationShard = match from_value(js_shard) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let explorer = SolutionExplorer::new(
+        &exploration_target,
+        &environment,
+        &boost_area_coord_set,
+        &field,
+        &next_puyos,
+    );
+    let exploration_result = explorer.solve_shard(&shard);
+
+    match to_value(&exploration_result) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(JsError::new(&e.to_string())),
+    }
+}
+
+#[wasm_bindgen]
+pub fn merge_exploration_results(
+    js_exploration_target: JsValue,
+    js_exploration_results: JsValue,
+) -> Result<JsValue, JsError> {
+    console_error_panic_hook::set_once();
+
+    let exploration_target: ExplorationTarget = match from_value(js_exploration_target) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = exploration_target.validate() {
+        return Err(JsError::new(&e));
+    }
+    let exploration_results: Vec<ExplorationResult> = match from_value(js_exploration_results) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let exploration_result = match solution_explorer::merge_exploration_results(
+        &exploration_target,
+        exploration_results,
+    ) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e)),
+    };
+
+    match to_value(&exploration_result) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(JsError::new(&e.to_string())),
+    }
+}
+
+#[wasm_bindgen]
+pub fn simulate_multi_turns(
+    js_environment: JsValue,
+    js_boost_area_coord_set: JsValue,
+    js_field: JsValue,
+    js_next_puyos: JsValue,
+    js_traces: JsValue,
+    js_refill_model: JsValue,
+) -> Result<JsValue, JsError> {
+    console_error_panic_hook::set_once();
+
+    let environment: SimulationEnvironment = match from_value(js_environment) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = environment.validate() {
+        return Err(JsError::new(&e));
+    }
+    let boost_area_coord_set: HashSet<PuyoCoord> = match from_value(js_boost_area_coord_set) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let field: Field = match from_value(js_field) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let next_puyos: NextPuyos = match from_value(js_next_puyos) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let traces: Vec<Vec<PuyoCoord>> = match from_value(js_traces) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    let refill_model: RefillModel = match from_value(js_refill_model) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e.to_string())),
+    };
+    if let Err(e) = refill_model.validate() {
+        return Err(JsError::new(&e));
+    }
+    let simulator = MultiTurnSimulator::new(&environment, &boost_area_coord_set, &refill_model);
+    let turn_results = match simulator.simulate(&field, &next_puyos, &traces) {
+        Ok(v) => v,
+        Err(e) => return Err(JsError::new(&e)),
+    };
+
+    match to_value(&turn_results) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(JsError::new(&e.to_string())),
+    }
+}
@@ -1,15 +1,24 @@
-use solver::how_many_traces::count_candidates_num_for_each_indexes;
+use solver::how_many_traces::count_candidates_num_for_each_indexes_in_range;
 use std::env;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
+    if args.len() != 2 && args.len() != 3 {
         usage();
         return;
     }
+    let min_trace_num = match args.get(2).map(|arg| arg.parse::<u32>()) {
+        None => 0,
+        Some(Ok(min_trace_num)) => min_trace_num,
+        Some(Err(_e)) => {
+            usage();
+            return;
+        }
+    };
     match args[1].parse::<u32>() {
         Ok(max_trace_num) => {
-            let num_array = count_candidates_num_for_each_indexes(max_trace_num);
+            let num_array =
+                count_candidates_num_for_each_indexes_in_range(min_trace_num, max_trace_num);
             println!("{:?},", &num_array[0..8]);
             println!("{:?},", &num_array[8..16]);
             println!("{:?},", &num_array[16..24]);
@@ -26,5 +35,5 @@ fn main() {
 }
 
 fn usage() {
-    println!("cargo run --release <max_trace_num>");
+    println!("cargo run --release <max_trace_num> [<min_trace_num>]");
 }
@@ -0,0 +1,288 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chain::Chain,
+    chain_helper::{sum_colored_attr_damage, sum_wild_damage},
+    puyo::{Field, FieldAndNext, NextPuyos},
+    puyo_attr::{PuyoAttr, COLOR_ATTRS},
+    puyo_coord::PuyoCoord,
+    refill::{RefillGenerator, RefillModel},
+    simulation_environment::SimulationEnvironment,
+    simulator_bb::SimulatorBB,
+    trace_path::validate_trace,
+};
+
+/** 1ターン分のシミュレーション結果 */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TurnResult {
+    /** なぞり位置 */
+    pub trace_coords: Vec<PuyoCoord>,
+    /** 連鎖情報 */
+    pub chains: Vec<Chain>,
+    /** 各色属性における総ダメージ (プリズムのダメージやブーストカウントによる倍率も考慮に入れる) */
+    pub damages: HashMap<PuyoAttr, f64>,
+    /** ワイルドによる総ダメージ */
+    pub wild_damage: f64,
+    /** 空いたマスを補充した後の盤面 (次のターンの開始盤面) */
+    pub board_after_refill: FieldAndNext,
+}
+
+/** なぞりの列に従って、補充を挟みながら複数ターンを続けてシミュレーションする。 */
+pub struct MultiTurnSimulator<'a> {
+    environment: &'a SimulationEnvironment,
+    boost_area: u64,
+    refill_model: &'a RefillModel,
+}
+
+impl<'a> MultiTurnSimulator<'a> {
+    pub fn new(
+        environment: &'a SimulationEnvironment,
+        boost_area_coord_set: &'a HashSet<PuyoCoord>,
+        refill_model: &'a RefillModel,
+    ) -> MultiTurnSimulator<'a> {
+        MultiTurnSimulator {
+            environment,
+            boost_area: SimulatorBB::coords_to_board(boost_area_coord_set.iter()),
+            refill_model,
+        }
+    }
+
+    /// 各ターンのなぞり位置を順に適用する。補充するぷよのIDは盤面上の最大のIDの次から振る。
+    /// なぞりはそのターンの開始盤面に対して検証し、正しくなければそのターンを表すエラーを返す。
+    pub fn simulate(
+        &self,
+        field: &Field,
+        next_puyos: &NextPuyos,
+        traces: &[Vec<PuyoCoord>],
+    ) -> Result<Vec<TurnResult>, String> {
+        let sim = SimulatorBB {
+            environment: self.environment,
+            boost_area: self.boost_area,
+        };
+        let max_id = field
+            .iter()
+            .flatten()
+            .chain(next_puyos.iter())
+            .flatten()
+            .map(|p| p.id)
+            .max()
+            .unwrap_or(0);
+        let mut generator = RefillGenerator::new(self.refill_model, max_id + 1);
+
+        let mut board = FieldAndNext {
+            field: *field,
+            next_puyos: *next_puyos,
+        };
+        let mut results = Vec::with_capacity(traces.len());
+
+        for (turn, trace_coords) in traces.iter().enumerate() {
+            if let Err(e) = validate_trace(
+                &board.field,
+                trace_coords,
+                self.environment.get_actual_max_trace_num(),
+            ) {
+                return Err(format!("turn {}: {}", turn + 1, e));
+            }
+            let mut boards = SimulatorBB::create_bit_boards(
+                &board.field.map(|row| row.map(|c| c.map(|p| p.puyo_type))),
+                &board.next_puyos.map(|c| c.map(|p| p.puyo_type)),
+            );
+            let mut id_board = SimulatorBB::create_puyo_id_board(&board.field, &board.next_puyos);
+
+            let (chains, _) = sim.do_chains_with_tracking(
+                &mut boards,
+                &mut id_board,
+                SimulatorBB::coords_to_board(trace_coords.iter()),
+            );
+
+            board = SimulatorBB::decode_field_and_next(&boards, &id_board);
+            generator.refill(&mut board);
+
+            let damages = COLOR_ATTRS
+                .iter()
+                .map(|attr| (*attr, sum_colored_attr_damage(&chains, *attr)))
+                .collect();
+            let wild_damage = sum_wild_damage(&chains);
+
+            results.push(TurnResult {
+                trace_coords: trace_coords.clone(),
+                chains,
+                damages,
+                wild_damage,
+                board_after_refill: board.clone(),
+            });
+        }
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{puyo::Puyo, puyo_type::PuyoType, trace_mode::TraceMode};
+
+    #[test]
+    fn test_simulate_two_turns() {
+        // Arrange
+        let environment = SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 5,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        };
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let refill_model = RefillModel {
+            seed: 1,
+            attr_weights: HashMap::new(),
+            chance_rate: 0.0,
+            plus_rate: 0.0,
+            known_queue: vec![PuyoType::Yellow; 4],
+        };
+        let r = PuyoType::Red;
+        let b = PuyoType::Blue;
+        let g = PuyoType::Green;
+        let h = PuyoType::Heart;
+        let mut id_counter = 0;
+        let field = [
+            [h, h, h, h, h, h, h, h],
+            [h, h, h, h, h, h, h, h],
+            [h, h, h, h, h, h, h, h],
+            [g, h, h, h, h, h, h, h],
+            [r, h, h, h, h, h, h, h],
+            [b, r, r, r, h, h, h, h],
+        ]
+        .map(|row| {
+            row.map(|puyo_type| {
+                id_counter += 1;
+                Some(Puyo {
+                    id: id_counter,
+                    puyo_type,
+                })
+            })
+        });
+        let next_puyos: NextPuyos = [None; 8];
+        let simulator = MultiTurnSimulator::new(&environment, &boost_area_coord_set, &refill_model);
+        let traces = vec![
+            vec![PuyoCoord { x: 0, y: 5 }],
+            vec![PuyoCoord { x: 7, y: 0 }],
+        ];
+
+        // Act
+        let actual = simulator.simulate(&field, &next_puyos, &traces).unwrap();
+
+        // Assert
+        assert_eq!(actual.len(), 2);
+
+        // 1ターン目: 青を消すと赤4つが繋がって1連鎖 (隣接するハート4つも消える)
+        let turn1 = &actual[0];
+        assert_eq!(turn1.chains.len(), 1);
+        assert_relative_eq!(turn1.damages[&PuyoAttr::Red], 1.0);
+        assert_relative_eq!(turn1.damages[&PuyoAttr::Blue], 0.0);
+        assert_relative_eq!(turn1.wild_damage, 1.0);
+        // 空いたマス (フィールド9マスとネクスト8マス) は既知の列 (黄4つ) の後に乱数で補充される
+        let refilled = &turn1.board_after_refill;
+        assert!(refilled.field.iter().flatten().all(|c| c.is_some()));
+        assert!(refilled.next_puyos.iter().all(|c| c.is_some()));
+        assert_eq!(
+            refilled.field[1][0],
+            Some(Puyo {
+                id: 49,
+                puyo_type: PuyoType::Yellow
+            })
+        );
+        assert_eq!(
+            refilled.field[1][3],
+            Some(Puyo {
+                id: 52,
+                puyo_type: PuyoType::Yellow
+            })
+        );
+        assert_eq!(refilled.field[0][4].unwrap().id, 57);
+        assert_eq!(refilled.field[5][0].unwrap().id, 25);
+        assert_eq!(refilled.next_puyos[7].unwrap().id, 65);
+
+        // 2ターン目は補充後の盤面から始まり、補充された黄4つが繋がって消える
+        let turn2 = &actual[1];
+        assert_eq!(turn2.trace_coords, traces[1]);
+        assert!(turn2.chains[0].attributes[&PuyoAttr::Yellow].popped_count >= 4);
+        assert_eq!(turn2.board_after_refill.field[5][0], refilled.field[5][0]);
+    }
+
+    #[test]
+    fn test_simulate_invalid_traces() {
+        // Arrange
+        let environment = SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 3,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        };
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let refill_model = RefillModel {
+            seed: 1,
+            attr_weights: HashMap::new(),
+            chance_rate: 0.0,
+            plus_rate: 0.0,
+            known_queue: Vec::new(),
+        };
+        let r = PuyoType::Red;
+        let b = PuyoType::Blue;
+        let o = PuyoType::Ojama;
+        let mut id_counter = 0;
+        let field = [
+            [b, r, b, r, b, r, b, r],
+            [r, b, r, b, r, b, r, b],
+            [b, r, b, r, b, r, b, r],
+            [r, b, r, b, r, b, r, b],
+            [b, r, b, r, b, r, b, r],
+            [o, o, r, b, r, b, r, b],
+        ]
+        .map(|row| {
+            row.map(|puyo_type| {
+                id_counter += 1;
+                Some(Puyo {
+                    id: id_counter,
+                    puyo_type,
+                })
+            })
+        });
+        let next_puyos: NextPuyos = [None; 8];
+        let simulator = MultiTurnSimulator::new(&environment, &boost_area_coord_set, &refill_model);
+        let simulate = |traces: &[Vec<PuyoCoord>]| simulator.simulate(&field, &next_puyos, traces);
+        let c = |x: u8, y: u8| PuyoCoord { x, y };
+
+        // Act & Assert
+        assert_eq!(
+            simulate(&[vec![c(8, 0)]]),
+            Err("turn 1: (8, 0) is out of the field".to_string())
+        );
+        assert_eq!(
+            simulate(&[vec![c(0, 5)]]),
+            Err("turn 1: (0, 5) has no traceable puyo".to_string())
+        );
+        assert_eq!(
+            simulate(&[vec![c(0, 0)], vec![c(0, 0), c(2, 0)]]),
+            Err("turn 2: trace must be connected".to_string())
+        );
+        assert_eq!(
+            simulate(&[vec![c(0, 0), c(1, 0), c(2, 0), c(3, 0)]]),
+            Err("turn 1: trace must not exceed 3 puyos".to_string())
+        );
+        assert_eq!(simulate(&[vec![c(0, 0), c(1, 1)]]).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use num_derive::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+use crate::{
+    puyo_attr::PuyoAttr,
+    puyo_type::{convert_type, PuyoType},
+    random::SeededRandom,
+    refill::ordered_attr_weights,
+};
+
+/** 不明なネクストぷよの補い方 */
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, FromPrimitive, ToPrimitive, Serialize_repr, Deserialize_repr,
+)]
+#[repr(u8)]
+pub enum NextExpectationMethod {
+    /** 乱数で補った盤面を sample_num 個作って平均を取る */
+    MonteCarlo = 1,
+    /**
+     * 全ての組み合わせを確率で重み付けして平均を取る。
+     * 組み合わせ数 (属性の種類数の、不明なマスの数乗) が MAX_EXHAUSTIVE_COMBINATION_NUM を超えるときは MonteCarlo で補う。
+     */
+    Exhaustive = 2,
+}
+
+/** Exhaustive で全ての組み合わせを列挙する組み合わせ数の上限。候補ごとに組み合わせの数だけ連鎖をシミュレーションするため。 */
+pub const MAX_EXHAUSTIVE_COMBINATION_NUM: usize = 4096;
+
+/**
+ * ネクストのうち不明なマス (None) を属性の出現分布から補い、value をその期待値で評価するための設定。
+ * 不明なマスが無ければ通常の探索と同じになる。
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NextExpectation {
+    /** 補い方 */
+    pub method: NextExpectationMethod,
+    /** 属性ごとの出現の重み。含まれない属性は出現しない。全ての重みが0なら5色が等確率で出現する。 */
+    pub attr_weights: HashMap<PuyoAttr, f64>,
+    /** MonteCarlo の試行回数 (1以上)。Exhaustive の組み合わせ数が上限を超えたときもこの回数だけ試行する。 */
+    pub sample_num: u32,
+    /** MonteCarlo の乱数のシード */
+    pub seed: u64,
+}
+
+impl NextExpectation {
+    /// 設定が正しいか検証する。
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sample_num == 0 {
+            return Err("sample_num of next_expectation must be positive".to_string());
+        }
+        if self
+            .attr_weights
+            .values()
+            .any(|w| !w.is_finite() || *w < 0.0)
+        {
+            return Err(
+                "attr_weights of next_expectation must be non-negative and finite".to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/** 不明なネクストぷよを補った各盤面における value の統計量 */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValueStatistics {
+    /** 期待値 */
+    pub mean: f64,
+    /** 分散 */
+    pub variance: f64,
+    /** 最小値 */
+    pub min: f64,
+    /** 10パーセンタイル */
+    pub p10: f64,
+    /** 中央値 */
+    pub median: f64,
+    /** 90パーセンタイル */
+    pub p90: f64,
+    /** 最大値 */
+    pub max: f64,
+}
+
+/// ネクストの不明なマスを補ったネクストとその確率のリストを返す。
+/// 全ての候補で乱数の列を共有するように、MonteCarlo のサンプルは探索の前に一度だけ作る。
+/// Exhaustive でも組み合わせ数が MAX_EXHAUSTIVE_COMBINATION_NUM を超えるときは MonteCarlo と同じくサンプルを作る。
+pub fn complete_unknown_next(
+    expectation: &NextExpectation,
+    next_puyos: &[Option<PuyoType>; 8],
+) -> Vec<([Option<PuyoType>; 8], f64)> {
+    let unknown_xs: Vec<usize> = (0..next_puyos.len())
+        .filter(|x| next_puyos[*x].is_none())
+        .collect();
+    if unknown_xs.is_empty() {
+        return Vec::new();
+    }
+
+    let attr_weights = ordered_attr_weights(&expectation.attr_weights);
+    let total: f64 = attr_weights.iter().map(|(_, w)| *w).sum();
+    let types: Vec<PuyoType> = attr_weights
+        .iter()
+        .map(|(attr, _)| convert_type(PuyoType::Red, *attr))
+        .collect();
+
+    let is_exhaustive = expectation.method == NextExpectationMethod::Exhaustive
+        && u32::try_from(unknown_xs.len())
+            .ok()
+            .and_then(|n| types.len().checked_pow(n))
+            .is_some_and(|n| n <= MAX_EXHAUSTIVE_COMBINATION_NUM);
+
+    if !is_exhaustive {
+        let weights: Vec<f64> = attr_weights.iter().map(|(_, w)| *w).collect();
+        let mut random = SeededRandom::new(expectation.seed);
+        return (0..expectation.sample_num)
+            .map(|_| {
+                let mut next = *next_puyos;
+                for x in unknown_xs.iter() {
+                    next[*x] = Some(types[random.choose_weighted(&weights).unwrap()]);
+                }
+                (next, 1.0)
+            })
+            .collect();
+    }
+
+    let mut results = Vec::new();
+    // 各不明マスに割り当てる属性のインデックスを多進数のように数え上げる
+    let mut digits = vec![0; unknown_xs.len()];
+    loop {
+        let mut next = *next_puyos;
+        let mut probability = 1.0;
+        for (x, digit) in unknown_xs.iter().zip(digits.iter()) {
+            next[*x] = Some(types[*digit]);
+            probability *= attr_weights[*digit].1 / total;
+        }
+        results.push((next, probability));
+
+        let mut i = 0;
+        while i < digits.len() {
+            digits[i] += 1;
+            if digits[i] < types.len() {
+                break;
+            }
+            digits[i] = 0;
+            i += 1;
+        }
+        if i == digits.len() {
+            return results;
+        }
+    }
+}
+
+/// (value, 重み) のリストから統計量を計算する。パーセンタイルは累積の重みが初めてその割合に達する値とする。
+/// samples は value の昇順に並べ替えられる。
+pub fn calc_value_statistics(samples: &mut [(f64, f64)]) -> ValueStatistics {
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let total: f64 = samples.iter().map(|(_, w)| *w).sum();
+    let mean = samples.iter().map(|(v, w)| v * w).sum::<f64>() / total;
+    let variance = samples
+        .iter()
+        .map(|(v, w)| (v - mean) * (v - mean) * w)
+        .sum::<f64>()
+        / total;
+
+    let percentile = |q: f64| -> f64 {
+        let threshold = q * total;
+        let mut cumulative = 0.0;
+        for (v, w) in samples.iter() {
+            cumulative += w;
+            // 確率の積による丸め誤差で閾値にわずかに届かないことがあるので許容誤差を設ける
+            if cumulative >= threshold - 1e-9 * total {
+                return *v;
+            }
+        }
+        samples[samples.len() - 1].0
+    };
+
+    ValueStatistics {
+        mean,
+        variance,
+        min: samples[0].0,
+        p10: percentile(0.1),
+        median: percentile(0.5),
+        p90: percentile(0.9),
+        max: samples[samples.len() - 1].0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expectation(method: NextExpectationMethod) -> NextExpectation {
+        NextExpectation {
+            method,
+            attr_weights: HashMap::from([(PuyoAttr::Red, 1.0), (PuyoAttr::Blue, 3.0)]),
+            sample_num: 100,
+            seed: 1,
+        }
+    }
+
+    #[test]
+    fn test_complete_unknown_next_exhaustive() {
+        // Arrange
+        let g = Some(PuyoType::Green);
+        let next = [g, None, g, g, g, g, g, None];
+
+        // Act
+        let actual = complete_unknown_next(&expectation(NextExpectationMethod::Exhaustive), &next);
+
+        // Assert
+        assert_eq!(actual.len(), 4);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        assert_eq!(actual[0].0, [g, r, g, g, g, g, g, r]);
+        assert_relative_eq!(actual[0].1, 1.0 / 16.0);
+        assert_eq!(actual[1].0, [g, b, g, g, g, g, g, r]);
+        assert_relative_eq!(actual[1].1, 3.0 / 16.0);
+        assert_eq!(actual[3].0, [g, b, g, g, g, g, g, b]);
+        assert_relative_eq!(actual[3].1, 9.0 / 16.0);
+        assert_relative_eq!(actual.iter().map(|(_, p)| p).sum::<f64>(), 1.0);
+    }
+
+    #[test]
+    fn test_complete_unknown_next_monte_carlo() {
+        // Arrange
+        let next = [None; 8];
+        let e = expectation(NextExpectationMethod::MonteCarlo);
+
+        // Act
+        let actual = complete_unknown_next(&e, &next);
+
+        // Assert
+        assert_eq!(actual.len(), 100);
+        assert_eq!(actual, complete_unknown_next(&e, &next));
+        assert!(actual.iter().all(|(next, p)| *p == 1.0
+            && next
+                .iter()
+                .all(|c| *c == Some(PuyoType::Red) || *c == Some(PuyoType::Blue))));
+    }
+
+    #[test]
+    fn test_complete_unknown_next_exhaustive_over_limit() {
+        // Arrange
+        // 5色が8マスとも不明だと 5^8 = 390625 通りになるので MonteCarlo で補う
+        let next = [None; 8];
+        let mut e = expectation(NextExpectationMethod::Exhaustive);
+        e.attr_weights = HashMap::new();
+
+        // Act
+        let actual = complete_unknown_next(&e, &next);
+
+        // Assert
+        assert_eq!(actual.len(), 100);
+        assert_eq!(
+            actual,
+            complete_unknown_next(
+                &NextExpectation {
+                    method: NextExpectationMethod::MonteCarlo,
+                    ..e.clone()
+                },
+                &next
+            )
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(expectation(NextExpectationMethod::MonteCarlo)
+            .validate()
+            .is_ok());
+        let mut e = expectation(NextExpectationMethod::MonteCarlo);
+        e.sample_num = 0;
+        assert!(e.validate().is_err());
+        let mut e = expectation(NextExpectationMethod::Exhaustive);
+        e.attr_weights.insert(PuyoAttr::Green, -1.0);
+        assert!(e.validate().is_err());
+        e.attr_weights.insert(PuyoAttr::Green, f64::NAN);
+        assert!(e.validate().is_err());
+    }
+
+    #[test]
+    fn test_complete_unknown_next_without_unknown() {
+        let next = [Some(PuyoType::Red); 8];
+        assert!(
+            complete_unknown_next(&expectation(NextExpectationMethod::Exhaustive), &next)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_calc_value_statistics() {
+        // Arrange
+        let mut samples = vec![(10.0, 0.25), (0.0, 0.5), (4.0, 0.25)];
+
+        // Act
+        let actual = calc_value_statistics(&mut samples);
+
+        // Assert
+        assert_relative_eq!(actual.mean, 3.5);
+        assert_relative_eq!(actual.variance, 0.5 * 12.25 + 0.25 * 0.25 + 0.25 * 42.25);
+        assert_eq!(actual.min, 0.0);
+        assert_eq!(actual.p10, 0.0);
+        assert_eq!(actual.median, 0.0);
+        assert_eq!(actual.p90, 10.0);
+        assert_eq!(actual.max, 10.0);
+    }
+}
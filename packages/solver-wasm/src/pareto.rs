@@ -0,0 +1,108 @@
+use std::cmp::Ordering;
+
+use num_derive::{FromPrimitive, ToPrimitive};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+/** パレート最適解を求めるときの目的 */
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    Hash,
+    PartialEq,
+    Eq,
+    FromPrimitive,
+    ToPrimitive,
+    Serialize_repr,
+    Deserialize_repr,
+)]
+#[repr(u8)]
+pub enum ParetoObjective {
+    /** 探索カテゴリーの値 (大きい方が良い) */
+    Value = 1,
+    /** ダメージ (探索カテゴリーが Damage のときの値。大きい方が良い) */
+    Damage = 2,
+    /** スキル溜めのぷよ数 (探索カテゴリーが SkillPuyoCount のときの値。大きい方が良い) */
+    SkillPuyoCount = 3,
+    /** ぷよ使いカウント (大きい方が良い) */
+    PuyotsukaiCount = 4,
+    /** ハートによる回復量 (大きい方が良い) */
+    Recovery = 5,
+    /** 連鎖数 (大きい方が良い) */
+    ChainCount = 6,
+    /** チャンスぷよ消し数 (大きい方が良い) */
+    ChancePop = 7,
+    /** プリズム消し数 (大きい方が良い) */
+    PrismPop = 8,
+    /** ハート消し数 (大きい方が良い) */
+    HeartPop = 9,
+    /** おじゃま消し数 (固ぷよも含む。大きい方が良い) */
+    OjamaPop = 10,
+    /** 全消ししたら1、しなければ0 (大きい方が良い) */
+    AllClear = 11,
+    /** なぞり数 (**小さい**方が良い) */
+    TraceNum = 12,
+}
+
+impl ParetoObjective {
+    /// 値が大きい方が良い目的かどうか。
+    pub fn is_maximized(&self) -> bool {
+        !matches!(self, ParetoObjective::TraceNum)
+    }
+}
+
+/// 目的ごとの値のリスト a と b を比べる。
+/// a が b を支配する (全ての目的で同等以上かつどれかで真に良い) なら Greater、
+/// b が a を支配するなら Less、全て等しいなら Equal、どちらも支配しないなら None を返す。
+pub fn compare_objective_values(
+    objectives: &[ParetoObjective],
+    a: &[f64],
+    b: &[f64],
+) -> Option<Ordering> {
+    let mut a_better = false;
+    let mut b_better = false;
+    for ((objective, va), vb) in objectives.iter().zip(a).zip(b) {
+        let (va, vb) = if objective.is_maximized() {
+            (*va, *vb)
+        } else {
+            (-*va, -*vb)
+        };
+        if va > vb {
+            a_better = true;
+        } else if va < vb {
+            b_better = true;
+        }
+    }
+    match (a_better, b_better) {
+        (true, false) => Some(Ordering::Greater),
+        (false, true) => Some(Ordering::Less),
+        (false, false) => Some(Ordering::Equal),
+        (true, true) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_objective_values() {
+        let objectives = [ParetoObjective::Damage, ParetoObjective::TraceNum];
+        assert_eq!(
+            compare_objective_values(&objectives, &[10.0, 3.0], &[8.0, 4.0]),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare_objective_values(&objectives, &[10.0, 4.0], &[10.0, 3.0]),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare_objective_values(&objectives, &[10.0, 3.0], &[10.0, 3.0]),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            compare_objective_values(&objectives, &[10.0, 5.0], &[8.0, 3.0]),
+            None
+        );
+    }
+}
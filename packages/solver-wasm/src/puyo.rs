@@ -10,3 +10,12 @@ pub struct Puyo {
 
 pub type Field = [[Option<Puyo>; PuyoCoord::X_NUM as usize]; PuyoCoord::Y_NUM as usize];
 pub type NextPuyos = [Option<Puyo>; PuyoCoord::X_NUM as usize];
+
+/** フィールドとネクスト情報 */
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FieldAndNext {
+    /** フィールドぷよ8x6個分 */
+    pub field: Field,
+    /** ネクストぷよ8個分 */
+    pub next_puyos: NextPuyos,
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{puyo::NextPuyos, puyo_coord::PuyoCoord};
+
+/** IDで識別されるぷよとその位置 */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackedPuyo {
+    /** ぷよのID */
+    pub id: i32,
+    /** ぷよの座標 */
+    pub coord: PuyoCoord,
+}
+
+/** なぞり消しから連鎖終了までぷよのIDを追跡した結果 */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PuyoTracking {
+    /** なぞり消しで消えたぷよのリスト (塗り替えの場合は空) */
+    pub traced_puyos: Vec<TrackedPuyo>,
+    /** 各連鎖で弾けたぷよのリスト。インデックスは chains のインデックスに対応する。座標は弾けた時点のもの。 */
+    pub popped_puyos: Vec<Vec<TrackedPuyo>>,
+    /** 連鎖後にフィールドに残ったぷよとその最終位置 */
+    pub surviving_puyos: Vec<TrackedPuyo>,
+    /** 連鎖後もネクストに残ったぷよ */
+    pub remaining_next_puyos: NextPuyos,
+}
@@ -0,0 +1,92 @@
+/**
+ * シード付きの疑似乱数生成器 (SplitMix64)。
+ * 同じシードからはネイティブでも wasm でも同じ系列が得られる。
+ */
+#[derive(Debug, Clone)]
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> SeededRandom {
+        SeededRandom { state: seed }
+    }
+
+    /// 64ビットの乱数を返す。
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// [0, 1) の一様乱数を返す。
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// 重みに比例した確率でインデックスを選ぶ。重みの合計が正でなければ None を返す。
+    pub fn choose_weighted(&mut self, weights: &[f64]) -> Option<usize> {
+        let total: f64 = weights.iter().filter(|w| **w > 0.0).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut r = self.next_f64() * total;
+        let mut last = None;
+        for (i, w) in weights.iter().enumerate() {
+            if *w <= 0.0 {
+                continue;
+            }
+            if r < *w {
+                return Some(i);
+            }
+            r -= w;
+            last = Some(i);
+        }
+        // 浮動小数点の誤差で抜けた場合は最後の候補とする
+        last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut r1 = SeededRandom::new(42);
+        let mut r2 = SeededRandom::new(42);
+        for _ in 0..100 {
+            assert_eq!(r1.next_u64(), r2.next_u64());
+        }
+        assert_ne!(
+            SeededRandom::new(1).next_u64(),
+            SeededRandom::new(2).next_u64()
+        );
+    }
+
+    #[test]
+    fn test_next_f64_range() {
+        let mut r = SeededRandom::new(0);
+        for _ in 0..1000 {
+            let v = r.next_f64();
+            assert!((0.0..1.0).contains(&v));
+        }
+    }
+
+    #[test]
+    fn test_choose_weighted() {
+        let mut r = SeededRandom::new(7);
+        assert_eq!(r.choose_weighted(&[]), None);
+        assert_eq!(r.choose_weighted(&[0.0, -1.0]), None);
+        for _ in 0..100 {
+            assert_eq!(r.choose_weighted(&[0.0, 3.0, 0.0]), Some(1));
+        }
+        let mut counts = [0; 2];
+        for _ in 0..10000 {
+            counts[r.choose_weighted(&[1.0, 3.0]).unwrap()] += 1;
+        }
+        assert!(counts[0] > 2000 && counts[0] < 3000);
+    }
+}
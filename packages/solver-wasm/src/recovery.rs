@@ -0,0 +1,118 @@
+use crate::{
+    chain::Chain, damage::calc_chain_factor, puyo_attr::PuyoAttr,
+    simulation_environment::SimulationEnvironment,
+};
+
+/// ハートが弾けたことによる回復量を求める。
+/// 連鎖ごとに 回復力 × 弾けたハートの数 × 連鎖倍率 を合計する。
+pub fn calc_recovery(
+    environment: &SimulationEnvironment,
+    recovery_stat: f64,
+    chains: &[Chain],
+) -> f64 {
+    chains
+        .iter()
+        .map(|chain| {
+            let heart_num = match chain.attributes.get(&PuyoAttr::Heart) {
+                Some(heart) => heart.popped_count,
+                None => 0,
+            };
+            if heart_num == 0 {
+                return 0.0;
+            }
+            let chain_factor = calc_chain_factor(
+                chain.chain_num,
+                environment.chain_coefficient_table.as_deref(),
+                Some(environment.chain_leverage),
+            )
+            .unwrap();
+            recovery_stat * heart_num as f64 * chain_factor
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chain::AttributeChain, trace_mode::TraceMode};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_calc_recovery() {
+        // Arrange
+        let environment = SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 5,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        };
+        let heart = |popped_count: u32| {
+            (
+                PuyoAttr::Heart,
+                AttributeChain {
+                    strength: 0.0,
+                    popped_count,
+                    separated_blocks_num: 0,
+                },
+            )
+        };
+        let red = (
+            PuyoAttr::Red,
+            AttributeChain {
+                strength: 1.0,
+                popped_count: 4,
+                separated_blocks_num: 1,
+            },
+        );
+        let chains: Vec<Chain> = Vec::from([
+            Chain {
+                chain_num: 1,
+                simultaneous_num: 4,
+                boost_count: 0,
+                puyo_tsukai_count: 6,
+                attributes: HashMap::from([red, heart(2)]),
+                popped_chance_num: 0,
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
+            },
+            Chain {
+                chain_num: 2,
+                simultaneous_num: 4,
+                boost_count: 0,
+                puyo_tsukai_count: 4,
+                attributes: HashMap::from([(
+                    PuyoAttr::Blue,
+                    AttributeChain {
+                        strength: 1.4,
+                        popped_count: 4,
+                        separated_blocks_num: 1,
+                    },
+                )]),
+                popped_chance_num: 0,
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
+            },
+            Chain {
+                chain_num: 3,
+                simultaneous_num: 4,
+                boost_count: 0,
+                puyo_tsukai_count: 5,
+                attributes: HashMap::from([heart(1)]),
+                popped_chance_num: 0,
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
+            },
+        ]);
+
+        // Act & Assert
+        // 100 × 2 × 1.0 + 100 × 1 × 1.7
+        assert_relative_eq!(calc_recovery(&environment, 100.0, &chains), 370.0);
+        assert_relative_eq!(calc_recovery(&environment, 100.0, &[]), 0.0);
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use num_traits::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    puyo::{FieldAndNext, Puyo},
+    puyo_attr::{is_colored_attr, PuyoAttr, COLOR_ATTRS, POPPABLE_ATTRS},
+    puyo_type::{convert_type, PuyoType},
+    random::SeededRandom,
+};
+
+/** 連鎖後に空いたマスを埋めるぷよの生成モデル */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefillModel {
+    /** 乱数のシード */
+    pub seed: u64,
+    /** 属性ごとの出現の重み。含まれない属性は出現しない。全ての重みが0なら5色が等確率で出現する。 */
+    pub attr_weights: HashMap<PuyoAttr, f64>,
+    /** 色ぷよがチャンスぷよになる確率 (0.0〜1.0) */
+    pub chance_rate: f64,
+    /** 色ぷよがプラスぷよになる確率 (0.0〜1.0) */
+    pub plus_rate: f64,
+    /** 降ってくることが分かっているぷよの列。先頭から順に使い、使い切ったら乱数で生成する。 */
+    #[serde(default)]
+    pub known_queue: Vec<PuyoType>,
+}
+
+impl RefillModel {
+    /// 設定が正しいか検証する。
+    pub fn validate(&self) -> Result<(), String> {
+        let is_rate = |v: f64| (0.0..=1.0).contains(&v);
+        if !is_rate(self.chance_rate) || !is_rate(self.plus_rate) {
+            return Err("chance_rate and plus_rate of refill_model must be in 0..=1".to_string());
+        }
+        if self
+            .attr_weights
+            .values()
+            .any(|w| !w.is_finite() || *w < 0.0)
+        {
+            return Err("attr_weights of refill_model must be non-negative and finite".to_string());
+        }
+        if self.known_queue.contains(&PuyoType::Padding) {
+            return Err("known_queue of refill_model must not contain Padding".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// 属性ごとの出現の重みを、乱数の消費順が固定されるように属性順に並べる。
+/// 重みが正の属性だけを残し、1つもなければ5色を等しい重みとする。
+pub fn ordered_attr_weights(attr_weights: &HashMap<PuyoAttr, f64>) -> Vec<(PuyoAttr, f64)> {
+    let weights: Vec<(PuyoAttr, f64)> = POPPABLE_ATTRS
+        .iter()
+        .filter_map(|attr| attr_weights.get(attr).map(|w| (*attr, *w)))
+        .filter(|(_, w)| *w > 0.0)
+        .collect();
+    if weights.is_empty() {
+        return COLOR_ATTRS.iter().map(|attr| (*attr, 1.0)).collect();
+    }
+    weights
+}
+
+/** RefillModel に従ってぷよを生成する。 */
+pub struct RefillGenerator<'a> {
+    model: &'a RefillModel,
+    random: SeededRandom,
+    attr_weights: Vec<(PuyoAttr, f64)>,
+    queue_index: usize,
+    next_id: i32,
+}
+
+impl<'a> RefillGenerator<'a> {
+    /// 生成するぷよのIDは first_id から順に振る。
+    pub fn new(model: &'a RefillModel, first_id: i32) -> RefillGenerator<'a> {
+        RefillGenerator {
+            model,
+            random: SeededRandom::new(model.seed),
+            attr_weights: ordered_attr_weights(&model.attr_weights),
+            queue_index: 0,
+            next_id: first_id,
+        }
+    }
+
+    /// ぷよを1つ生成する。
+    pub fn generate(&mut self) -> Puyo {
+        let puyo_type = if self.queue_index < self.model.known_queue.len() {
+            self.queue_index += 1;
+            self.model.known_queue[self.queue_index - 1]
+        } else {
+            self.generate_type()
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        Puyo { id, puyo_type }
+    }
+
+    /// 盤面の空きマスを全て埋める。
+    /// フィールドは下の行から、各行は左の列から順に埋め、最後にネクストを左から埋める。
+    pub fn refill(&mut self, board: &mut FieldAndNext) {
+        for row in board.field.iter_mut().rev() {
+            for cell in row.iter_mut() {
+                if cell.is_none() {
+                    *cell = Some(self.generate());
+                }
+            }
+        }
+        for cell in board.next_puyos.iter_mut() {
+            if cell.is_none() {
+                *cell = Some(self.generate());
+            }
+        }
+    }
+
+    fn generate_type(&mut self) -> PuyoType {
+        let weights: Vec<f64> = self.attr_weights.iter().map(|(_, w)| *w).collect();
+        let attr = self.attr_weights[self.random.choose_weighted(&weights).unwrap()].0;
+
+        if !is_colored_attr(attr) {
+            return convert_type(PuyoType::Padding, attr);
+        }
+
+        let is_chance = self.random.next_f64() < self.model.chance_rate;
+        let is_plus = self.random.next_f64() < self.model.plus_rate;
+        let base = convert_type(PuyoType::Red, attr).to_u8().unwrap();
+        let enhance = (if is_plus { 1 } else { 0 }) + (if is_chance { 2 } else { 0 });
+        PuyoType::from_u8(base + enhance).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(
+        attr_weights: HashMap<PuyoAttr, f64>,
+        chance_rate: f64,
+        plus_rate: f64,
+        known_queue: Vec<PuyoType>,
+    ) -> RefillModel {
+        RefillModel {
+            seed: 12345,
+            attr_weights,
+            chance_rate,
+            plus_rate,
+            known_queue,
+        }
+    }
+
+    #[test]
+    fn test_generate_uses_known_queue_first() {
+        // Arrange
+        let m = model(
+            HashMap::from([(PuyoAttr::Green, 1.0)]),
+            0.0,
+            0.0,
+            vec![PuyoType::Heart, PuyoType::RedPlus],
+        );
+        let mut generator = RefillGenerator::new(&m, 100);
+
+        // Act & Assert
+        assert_eq!(
+            generator.generate(),
+            Puyo {
+                id: 100,
+                puyo_type: PuyoType::Heart
+            }
+        );
+        assert_eq!(
+            generator.generate(),
+            Puyo {
+                id: 101,
+                puyo_type: PuyoType::RedPlus
+            }
+        );
+        assert_eq!(
+            generator.generate(),
+            Puyo {
+                id: 102,
+                puyo_type: PuyoType::Green
+            }
+        );
+    }
+
+    #[test]
+    fn test_generate_is_reproducible_by_seed() {
+        let m = model(HashMap::new(), 0.1, 0.2, vec![]);
+        let mut g1 = RefillGenerator::new(&m, 0);
+        let mut g2 = RefillGenerator::new(&m, 0);
+        for _ in 0..100 {
+            assert_eq!(g1.generate(), g2.generate());
+        }
+    }
+
+    #[test]
+    fn test_generate_with_rates() {
+        // Arrange
+        let m = model(
+            HashMap::from([(PuyoAttr::Blue, 1.0), (PuyoAttr::Prism, 0.0)]),
+            1.0,
+            1.0,
+            vec![],
+        );
+        let mut generator = RefillGenerator::new(&m, 0);
+
+        // Act & Assert
+        for _ in 0..10 {
+            assert_eq!(generator.generate().puyo_type, PuyoType::BlueChancePlus);
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(model(
+            HashMap::from([(PuyoAttr::Red, 1.0)]),
+            0.0,
+            1.0,
+            vec![PuyoType::Kata]
+        )
+        .validate()
+        .is_ok());
+        assert!(model(HashMap::new(), 1.5, 0.0, vec![]).validate().is_err());
+        assert!(model(HashMap::new(), 0.0, -0.1, vec![]).validate().is_err());
+        assert!(model(HashMap::new(), f64::NAN, 0.0, vec![])
+            .validate()
+            .is_err());
+        assert!(
+            model(HashMap::from([(PuyoAttr::Red, -1.0)]), 0.0, 0.0, vec![])
+                .validate()
+                .is_err()
+        );
+        assert!(model(
+            HashMap::from([(PuyoAttr::Red, f64::INFINITY)]),
+            0.0,
+            0.0,
+            vec![]
+        )
+        .validate()
+        .is_err());
+        assert!(model(HashMap::new(), 0.0, 0.0, vec![PuyoType::Padding])
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_refill() {
+        // Arrange
+        let m = model(HashMap::from([(PuyoAttr::Heart, 1.0)]), 0.0, 0.0, vec![]);
+        let mut generator = RefillGenerator::new(&m, 1);
+        let r = Some(Puyo {
+            id: 99,
+            puyo_type: PuyoType::Red,
+        });
+        let mut board = FieldAndNext {
+            field: [[None; 8]; 6],
+            next_puyos: [r, None, None, None, None, None, None, None],
+        };
+        board.field[5][0] = r;
+
+        // Act
+        generator.refill(&mut board);
+
+        // Assert
+        assert_eq!(board.field[5][0], r);
+        assert_eq!(board.field[5][1].unwrap().id, 1);
+        assert_eq!(board.field[4][0].unwrap().id, 8);
+        assert_eq!(board.field[0][7].unwrap().id, 47);
+        assert_eq!(board.next_puyos[0], r);
+        assert_eq!(board.next_puyos[1].unwrap().id, 48);
+        assert_eq!(board.next_puyos[7].unwrap().id, 54);
+        assert!(board
+            .field
+            .iter()
+            .flatten()
+            .chain(board.next_puyos.iter())
+            .all(|c| c.is_some()));
+        assert_eq!(board.field[0][0].unwrap().puyo_type, PuyoType::Heart);
+    }
+}
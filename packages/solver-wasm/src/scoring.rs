@@ -0,0 +1,190 @@
+use num_derive::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+use crate::puyo_attr::PuyoAttr;
+
+/** 採点に使う指標 */
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    Hash,
+    PartialEq,
+    Eq,
+    FromPrimitive,
+    ToPrimitive,
+    Serialize_repr,
+    Deserialize_repr,
+)]
+#[repr(u8)]
+pub enum ScoringMetric {
+    /** 探索カテゴリーの値 */
+    CategoryValue = 1,
+    /** ダメージ (探索カテゴリーが Damage のときの値) */
+    Damage = 2,
+    /** スキル溜めのぷよ数 (探索カテゴリーが SkillPuyoCount のときの値) */
+    SkillPuyoCount = 3,
+    /** ぷよ使いカウント */
+    PuyotsukaiCount = 4,
+    /** ハートによる回復量 */
+    Recovery = 5,
+    /** 連鎖数 */
+    ChainCount = 6,
+    /** 最大の同時消し数 */
+    MaxSimultaneousNum = 7,
+    /** 全ての連鎖で消えたぷよの総数 */
+    TotalPoppedCount = 8,
+    /** チャンスぷよ消し数 */
+    ChancePop = 9,
+    /** プリズム消し数 */
+    PrismPop = 10,
+    /** ハート消し数 */
+    HeartPop = 11,
+    /** おじゃま消し数 (固ぷよも含む) */
+    OjamaPop = 12,
+    /** 全消ししたら1、しなければ0 */
+    AllClear = 13,
+    /** なぞり数 */
+    TraceNum = 14,
+
+    //
+    // 属性ごと (attr の指定が必要)
+    //
+    /** 対象属性のポップカウント総数 */
+    AttrPoppedCount = 21,
+    /** 対象属性による純粋なダメージ (特殊ぷよのダメージやブーストカウントによる倍率は考慮に入れない) */
+    AttrDamage = 22,
+}
+
+impl ScoringMetric {
+    /// 属性の指定が必要な指標かどうか。
+    pub fn requires_attr(&self) -> bool {
+        matches!(
+            self,
+            ScoringMetric::AttrPoppedCount | ScoringMetric::AttrDamage
+        )
+    }
+
+    /// 属性の指定が指標に合っているか検証する。
+    pub fn validate_attr(&self, attr: Option<PuyoAttr>) -> Result<(), String> {
+        if self.requires_attr() && attr.is_none() {
+            return Err(format!("{:?} requires attr", self));
+        }
+        if !self.requires_attr() && attr.is_some() {
+            return Err(format!("{:?} does not take attr", self));
+        }
+        Ok(())
+    }
+}
+
+/** 採点式の項 (指標 × 重み) */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoringTerm {
+    /** 指標 */
+    pub metric: ScoringMetric,
+    /** 対象属性。属性ごとの指標のときだけ指定する。 */
+    #[serde(default)]
+    pub attr: Option<PuyoAttr>,
+    /** 重み */
+    pub weight: f64,
+}
+
+/**
+ * 各指標の重み付き和による採点式。
+ * 例えば「ダメージ + 5000 × ハート消し数 − 1000 × なぞり数」は
+ * (Damage, 1), (HeartPop, 5000), (TraceNum, -1000) の3項で表す。
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scoring {
+    /** 項のリスト */
+    pub terms: Vec<ScoringTerm>,
+}
+
+impl Scoring {
+    /// 探索を始める前に採点式が正しいか検証する。
+    pub fn validate(&self) -> Result<(), String> {
+        if self.terms.is_empty() {
+            return Err("scoring terms must not be empty".to_string());
+        }
+        for (i, term) in self.terms.iter().enumerate() {
+            if !term.weight.is_finite() {
+                return Err(format!("scoring term {i}: weight must be finite"));
+            }
+            if let Err(e) = term.metric.validate_attr(term.attr) {
+                return Err(format!("scoring term {i}: {e}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// 各項の指標の値を metric_value で求め、重み付き和を返す。
+    pub fn calc_score<F>(&self, metric_value: F) -> f64
+    where
+        F: Fn(&ScoringTerm) -> f64,
+    {
+        self.terms
+            .iter()
+            .map(|term| term.weight * metric_value(term))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(metric: ScoringMetric, attr: Option<PuyoAttr>, weight: f64) -> ScoringTerm {
+        ScoringTerm {
+            metric,
+            attr,
+            weight,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = Scoring {
+            terms: Vec::from([
+                term(ScoringMetric::Damage, None, 1.0),
+                term(ScoringMetric::AttrPoppedCount, Some(PuyoAttr::Red), 2.0),
+            ]),
+        };
+        assert!(valid.validate().is_ok());
+
+        assert!(Scoring { terms: Vec::new() }.validate().is_err());
+        assert!(Scoring {
+            terms: Vec::from([term(ScoringMetric::HeartPop, None, f64::NAN)])
+        }
+        .validate()
+        .is_err());
+        assert!(Scoring {
+            terms: Vec::from([term(ScoringMetric::AttrDamage, None, 1.0)])
+        }
+        .validate()
+        .is_err());
+        assert!(Scoring {
+            terms: Vec::from([term(ScoringMetric::TraceNum, Some(PuyoAttr::Red), 1.0)])
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_calc_score() {
+        let scoring = Scoring {
+            terms: Vec::from([
+                term(ScoringMetric::Damage, None, 1.0),
+                term(ScoringMetric::HeartPop, None, 5000.0),
+                term(ScoringMetric::TraceNum, None, -1000.0),
+            ]),
+        };
+        let actual = scoring.calc_score(|term| match term.metric {
+            ScoringMetric::Damage => 12345.0,
+            ScoringMetric::HeartPop => 2.0,
+            ScoringMetric::TraceNum => 3.0,
+            _ => 0.0,
+        });
+        assert_relative_eq!(actual, 12345.0 + 10000.0 - 3000.0);
+    }
+}
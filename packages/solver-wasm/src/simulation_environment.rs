@@ -1,12 +1,230 @@
-use crate::trace_mode::TraceMode;
+use std::collections::HashMap;
+
+use crate::{
+    exact_damage::validate_exact_factors,
+    puyo_attr::{is_colored_attr, PuyoAttr, SPECIAL_ATTRS},
+    trace_mode::TraceMode,
+};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+/** 特殊ぷよ (プリズム、ハート、おじゃま、固ぷよ) が弾けたときの強さのルール */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpecialPuyoStrength {
+    /** 1個あたりの強さ */
+    pub strength_per_puyo: f64,
+    /** 連鎖倍率を掛けるかどうか */
+    #[serde(default)]
+    pub applies_chain_factor: bool,
+    /** 強さが加わる色属性のリスト。None なら全ての色属性に加わる (プリズムの既定の挙動) */
+    #[serde(default)]
+    pub target_attrs: Option<Vec<PuyoAttr>>,
+}
+
+impl SpecialPuyoStrength {
+    /// 対象の色属性に強さが加わるかどうか。
+    pub fn applies_to(&self, attr: PuyoAttr) -> bool {
+        match &self.target_attrs {
+            Some(target_attrs) => target_attrs.contains(&attr),
+            None => true,
+        }
+    }
+
+    /// 弾けた数 (整数で計算するときは 攻撃力 × 弾けた数) を base として強さを求める。
+    /// 1個あたりの強さを掛けてから、連鎖倍率を掛けるルールなら連鎖倍率を掛ける。
+    /// strength_per_puyo は self.strength_per_puyo を倍率の型 (整数で計算するときは有理数) に直したもの。
+    /// mul は倍率の掛け方で、整数で計算するときは掛けるたびに切り捨てる。
+    pub fn calc_strength<T, F>(
+        &self,
+        base: T,
+        strength_per_puyo: F,
+        chain_factor: F,
+        mul: impl Fn(T, F) -> T,
+    ) -> T {
+        let strength = mul(base, strength_per_puyo);
+        if self.applies_chain_factor {
+            mul(strength, chain_factor)
+        } else {
+            strength
+        }
+    }
+}
+
+static DEFAULT_PRISM_STRENGTH: SpecialPuyoStrength = SpecialPuyoStrength {
+    strength_per_puyo: 3.0,
+    applies_chain_factor: false,
+    target_attrs: None,
+};
+
+static DEFAULT_NO_STRENGTH: SpecialPuyoStrength = SpecialPuyoStrength {
+    strength_per_puyo: 0.0,
+    applies_chain_factor: false,
+    target_attrs: None,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimulationEnvironment {
     pub is_chance_mode: bool,
     pub minimum_puyo_num_for_popping: u32,
     pub max_trace_num: u32,
+    /** なぞり数の下限。これより少ないなぞりは候補にしない。ちょうど N 個なぞるときは max_trace_num と同じ値にする。 */
+    #[serde(default)]
+    pub min_trace_num: u32,
     pub trace_mode: TraceMode,
     pub popping_leverage: f64,
     pub chain_leverage: f64,
+    /** 同時消し係数。None なら既定の 0.15 */
+    #[serde(default)]
+    pub popping_coefficient: Option<f64>,
+    /** 連鎖係数の表 (インデックス0が1連鎖目)。表より長い連鎖では最後の係数を使う。None なら既定の係数 */
+    #[serde(default)]
+    pub chain_coefficient_table: Option<Vec<f64>>,
+    /** 特殊ぷよの属性ごとの強さのルール。含まれない属性は既定 (プリズムは1個あたり3.0、それ以外は0) */
+    #[serde(default)]
+    pub special_puyo_strengths: HashMap<PuyoAttr, SpecialPuyoStrength>,
+}
+
+impl SimulationEnvironment {
+    /// ダメージの計算に使う倍率などの設定が正しいか検証する。
+    /// 倍率は整数でダメージを計算するときに有理数として扱えること (exact_damage::to_ratio を参照)。
+    pub fn validate(&self) -> Result<(), String> {
+        let is_valid = |v: f64| v >= 0.0 && v.is_finite();
+        if !is_valid(self.popping_leverage) || !is_valid(self.chain_leverage) {
+            return Err(
+                "popping_leverage and chain_leverage must be non-negative and finite".to_string(),
+            );
+        }
+        if !self.popping_coefficient.is_none_or(is_valid) {
+            return Err("popping_coefficient must be non-negative and finite".to_string());
+        }
+        if let Some(table) = &self.chain_coefficient_table {
+            if !table.iter().all(|v| is_valid(*v)) {
+                return Err("chain_coefficient_table must be non-negative and finite".to_string());
+            }
+        }
+        for (attr, strength) in self.special_puyo_strengths.iter() {
+            if !SPECIAL_ATTRS.contains(attr) {
+                return Err(format!(
+                    "special_puyo_strengths must not contain {:?}, which is not a special attribute",
+                    attr
+                ));
+            }
+            if !strength
+                .target_attrs
+                .as_ref()
+                .is_none_or(|attrs| attrs.iter().all(|a| is_colored_attr(*a)))
+            {
+                return Err(format!(
+                    "target_attrs of {:?} must be colored attributes",
+                    attr
+                ));
+            }
+            if !is_valid(strength.strength_per_puyo) {
+                return Err(format!(
+                    "strength_per_puyo of {:?} must be non-negative and finite",
+                    attr
+                ));
+            }
+        }
+        validate_exact_factors(self)
+    }
+
+    /// 実際に適用される最大なぞり数。チャンスモードでは常に5。
+    pub fn get_actual_max_trace_num(&self) -> u32 {
+        if self.is_chance_mode {
+            5
+        } else {
+            self.max_trace_num
+        }
+    }
+
+    /// 特殊ぷよの強さのルールを取得する。
+    pub fn get_special_puyo_strength(&self, attr: PuyoAttr) -> &SpecialPuyoStrength {
+        match self.special_puyo_strengths.get(&attr) {
+            Some(strength) => strength,
+            None => match attr {
+                PuyoAttr::Prism => &DEFAULT_PRISM_STRENGTH,
+                _ => &DEFAULT_NO_STRENGTH,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn environment() -> SimulationEnvironment {
+        SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 5,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(environment().validate().is_ok());
+
+        let mut invalid = environment();
+        invalid.popping_leverage = f64::NAN;
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = environment();
+        invalid.chain_leverage = -1.0;
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = environment();
+        invalid.popping_coefficient = Some(f64::INFINITY);
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = environment();
+        invalid.chain_coefficient_table = Some(Vec::from([0.0, 0.4, -0.7]));
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = environment();
+        invalid.popping_coefficient = Some(0.15 + 1e-12);
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = environment();
+        invalid.special_puyo_strengths.insert(
+            PuyoAttr::Heart,
+            SpecialPuyoStrength {
+                strength_per_puyo: f64::NAN,
+                applies_chain_factor: false,
+                target_attrs: None,
+            },
+        );
+        assert!(invalid.validate().is_err());
+
+        let strength = |target_attrs| SpecialPuyoStrength {
+            strength_per_puyo: 1.0,
+            applies_chain_factor: false,
+            target_attrs,
+        };
+        let mut valid = environment();
+        valid
+            .special_puyo_strengths
+            .insert(PuyoAttr::Kata, strength(Some(Vec::from([PuyoAttr::Red]))));
+        assert!(valid.validate().is_ok());
+
+        let mut invalid = environment();
+        invalid.special_puyo_strengths.insert(
+            PuyoAttr::Prism,
+            strength(Some(Vec::from([PuyoAttr::Red, PuyoAttr::Heart]))),
+        );
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = environment();
+        invalid
+            .special_puyo_strengths
+            .insert(PuyoAttr::Red, strength(None));
+        assert!(invalid.validate().is_err());
+    }
 }
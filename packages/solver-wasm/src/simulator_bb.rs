@@ -7,13 +7,17 @@
  */
 
 use crate::{
+    animation_step::{AnimationStep, AnimationStepKind},
     chain::{AttributeChain, Chain},
     damage::*,
+    puyo::{Field, FieldAndNext, NextPuyos, Puyo},
     puyo_attr::PuyoAttr,
     puyo_coord::PuyoCoord,
+    puyo_tracking::{PuyoTracking, TrackedPuyo},
     puyo_type::{get_attr, is_chance_type, is_plus_type, PuyoType},
     simulation_environment::SimulationEnvironment,
     trace_mode::*,
+    trace_path::validate_trace,
 };
 use bitintr::{Pdep, Pext};
 use num_traits::{FromPrimitive, ToPrimitive};
@@ -69,6 +73,91 @@ impl BitBoards {
         occ &= FIELD_MASK;
         return occ == 0;
     }
+
+    /// ぷよの存在するビットを全て立てたボードを返す。(ネクストも含む)
+    pub fn occupied_board(&self) -> u64 {
+        self.colors.iter().fold(0, |acc, c| acc | c)
+            | self.heart
+            | self.prism
+            | self.ojama
+            | self.kata
+            | self.padding
+    }
+
+    /// 指定のビット位置にあるぷよの種類を返す。ぷよがなければ None を返す。
+    pub fn get_puyo_type(&self, bit: u64) -> Option<PuyoType> {
+        let plus_term = if self.plus & bit != 0 { 1 } else { 0 };
+        let chance_term = if self.chance & bit != 0 { 2 } else { 0 };
+
+        for (i, color) in self.colors.iter().enumerate() {
+            if color & bit != 0 {
+                let base = PuyoType::Red.to_u8().unwrap() + 4 * i as u8;
+                return PuyoType::from_u8(base + plus_term + chance_term);
+            }
+        }
+        if self.heart & bit != 0 {
+            return Some(PuyoType::Heart);
+        }
+        if self.prism & bit != 0 {
+            return Some(PuyoType::Prism);
+        }
+        if self.ojama & bit != 0 {
+            return Some(PuyoType::Ojama);
+        }
+        if self.kata & bit != 0 {
+            return Some(PuyoType::Kata);
+        }
+        if self.padding & bit != 0 {
+            return Some(PuyoType::Padding);
+        }
+        None
+    }
+}
+
+/**
+ * 盤面上の各ぷよのIDを、ビットボードのビット位置をインデックスとして保持したもの。
+ * BitBoards と組にして使い、ぷよが消えたり落ちたりしてもどのぷよがどこにあるかを追跡できるようにする。
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct PuyoIdBoard {
+    ids: [i32; WIDTH * HEIGHT],
+}
+
+impl PuyoIdBoard {
+    /// 指定のビット位置にあるぷよのIDを返す。
+    pub fn get_id(&self, bit_index: u32) -> i32 {
+        self.ids[bit_index as usize]
+    }
+
+    /// from の立っているビット位置のIDを、下位ビットから順に to の立っているビット位置に移動する。
+    /// (PEXT と PDEP による落下処理と同じ移動になる)
+    fn relocate(&mut self, from: u64, to: u64) {
+        let mut ids = self.ids;
+        let mut f = from;
+        while f != 0 {
+            ids[f.trailing_zeros() as usize] = 0;
+            f &= f - 1;
+        }
+        let mut f = from;
+        let mut t = to;
+        while f != 0 && t != 0 {
+            ids[t.trailing_zeros() as usize] = self.ids[f.trailing_zeros() as usize];
+            f &= f - 1;
+            t &= t - 1;
+        }
+        self.ids = ids;
+    }
+
+    /// ボードの立っているビット位置にあるぷよを座標順に列挙する。(フィールド部分のみ)
+    fn collect_puyos(&self, board: u64) -> Vec<TrackedPuyo> {
+        SimulatorBB::board_to_coords(board)
+            .into_iter()
+            .map(|coord| TrackedPuyo {
+                id: self.get_id(SimulatorBB::coord_to_bit_index(&coord)),
+                coord,
+            })
+            .collect()
+    }
 }
 
 /** フィールドの幅 */
@@ -149,6 +238,33 @@ impl<'a> SimulatorBB<'a> {
         return boards;
     }
 
+    /// フィールドとネクストぷよからぷよのIDボードを作成する。
+    pub fn create_puyo_id_board(field: &Field, next_puyos: &NextPuyos) -> PuyoIdBoard {
+        let mut id_board = PuyoIdBoard {
+            ids: [0; WIDTH * HEIGHT],
+        };
+
+        for (y, row) in field.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if let Some(puyo) = cell {
+                    id_board.ids[(7 - x) * HEIGHT + (5 - y)] = puyo.id;
+                }
+            }
+        }
+        for (x, cell) in next_puyos.iter().enumerate() {
+            if let Some(puyo) = cell {
+                id_board.ids[(7 - x) * HEIGHT + 6] = puyo.id;
+            }
+        }
+
+        id_board
+    }
+
+    /// 座標に対応するビット位置を求める。
+    pub fn coord_to_bit_index(coord: &PuyoCoord) -> u32 {
+        ((7 - coord.x as usize) * HEIGHT + (5 - coord.y as usize)) as u32
+    }
+
     /// 座標のリストからビットボードを作成する。
     pub fn coords_to_board<'b, T: std::iter::Iterator<Item = &'b PuyoCoord>>(coord_iter: T) -> u64 {
         let mut board = 0;
@@ -162,24 +278,229 @@ impl<'a> SimulatorBB<'a> {
         return board;
     }
 
+    /// ビットボードのフィールド部分から座標のリストを作成する。(coords_to_board の逆変換)
+    pub fn board_to_coords(board: u64) -> Vec<PuyoCoord> {
+        let mut coords = Vec::new();
+
+        for y in 0..PuyoCoord::Y_NUM {
+            for x in 0..PuyoCoord::X_NUM {
+                let bit: u64 = 1 << ((7 - x as usize) * HEIGHT + (5 - y as usize));
+                if board & bit != 0 {
+                    coords.push(PuyoCoord { x, y });
+                }
+            }
+        }
+        coords
+    }
+
+    /// ビットボードからフィールドとネクストぷよの種類を復元する。(create_bit_boards の逆変換)
+    pub fn decode_bit_boards(
+        boards: &BitBoards,
+    ) -> ([[Option<PuyoType>; 8]; 6], [Option<PuyoType>; 8]) {
+        let mut field: [[Option<PuyoType>; 8]; 6] = [[None; 8]; 6];
+        let mut next_puyos: [Option<PuyoType>; 8] = [None; 8];
+
+        for (y, row) in field.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                *cell = boards.get_puyo_type(1 << ((7 - x) * HEIGHT + (5 - y)));
+            }
+        }
+        for (x, cell) in next_puyos.iter_mut().enumerate() {
+            *cell = boards.get_puyo_type(1 << ((7 - x) * HEIGHT + 6));
+        }
+
+        (field, next_puyos)
+    }
+
+    /// ビットボードとIDボードからフィールドとネクストぷよを復元する。
+    /// create_bit_boards と create_puyo_id_board の逆変換で、プラスやチャンスの情報も含めて元に戻る。
+    pub fn decode_field_and_next(boards: &BitBoards, id_board: &PuyoIdBoard) -> FieldAndNext {
+        let (field_types, next_types) = Self::decode_bit_boards(boards);
+        let to_puyo = |puyo_type: Option<PuyoType>, bit_index: usize| {
+            puyo_type.map(|puyo_type| Puyo {
+                id: id_board.get_id(bit_index as u32),
+                puyo_type,
+            })
+        };
+
+        let mut field: Field = [[None; 8]; 6];
+        let mut next_puyos: NextPuyos = [None; 8];
+        for (y, row) in field.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                *cell = to_puyo(field_types[y][x], (7 - x) * HEIGHT + (5 - y));
+            }
+        }
+        for (x, cell) in next_puyos.iter_mut().enumerate() {
+            *cell = to_puyo(next_types[x], (7 - x) * HEIGHT + 6);
+        }
+
+        FieldAndNext { field, next_puyos }
+    }
+
     /// なぞり消し(あるいは塗り替え)を実施して連鎖を発生させる。
     pub fn do_chains(&self, boards: &mut BitBoards, trace: u64) -> Vec<Chain> {
+        self.do_chains_with_observer(boards, trace, &mut |_, _, _| {})
+    }
+
+    /// do_chains と同様に連鎖させつつ、途中の盤面の変化をアニメーションステップとして全て記録する。
+    /// 先頭のステップはなぞる前の初期状態になる。
+    pub fn do_chains_with_steps(
+        &self,
+        boards: &mut BitBoards,
+        trace: u64,
+    ) -> (Vec<Chain>, Vec<AnimationStep>) {
+        fn create_step(
+            kind: AnimationStepKind,
+            chain_num: usize,
+            popped: u64,
+            boards: &BitBoards,
+        ) -> AnimationStep {
+            let (field, next_puyos) = SimulatorBB::decode_bit_boards(boards);
+            AnimationStep {
+                kind,
+                chain_num: chain_num as u32,
+                popped_coords: SimulatorBB::board_to_coords(popped),
+                field,
+                next_puyos,
+            }
+        }
+
+        let mut steps = vec![create_step(AnimationStepKind::Initial, 0, 0, boards)];
+        let mut prev_boards = boards.clone();
+
+        let chains = self.do_chains_with_observer(boards, trace, &mut |kind, boards, chains| {
+            // 落下処理は何も動かなくても呼ばれるので、変化がなければ記録しない。
+            if *boards == prev_boards {
+                return;
+            }
+            let popped = match kind {
+                AnimationStepKind::Traced | AnimationStepKind::Popped => {
+                    prev_boards.occupied_board() & !boards.occupied_board()
+                }
+                _ => 0,
+            };
+            steps.push(create_step(kind, chains.len(), popped, boards));
+            prev_boards = boards.clone();
+        });
+
+        (chains, steps)
+    }
+
+    /// 盤面となぞり位置から、なぞる前の初期状態から連鎖が終わるまでのアニメーションステップを求める。
+    /// なぞりは盤面に対して検証し、正しくなければエラーを返す。
+    pub fn simulate_trace_with_steps(
+        &self,
+        field: &Field,
+        next_puyos: &NextPuyos,
+        trace_coords: &[PuyoCoord],
+    ) -> Result<(Vec<Chain>, Vec<AnimationStep>), String> {
+        validate_trace(
+            field,
+            trace_coords,
+            self.environment.get_actual_max_trace_num(),
+        )?;
+        let mut boards = SimulatorBB::create_bit_boards(
+            &field.map(|row| row.map(|c| c.map(|p| p.puyo_type))),
+            &next_puyos.map(|c| c.map(|p| p.puyo_type)),
+        );
+        Ok(self.do_chains_with_steps(
+            &mut boards,
+            SimulatorBB::coords_to_board(trace_coords.iter()),
+        ))
+    }
+
+    /// do_chains と同様に連鎖させつつ、ぷよのIDを追跡して、各連鎖で弾けたぷよと残ったぷよの位置を求める。
+    /// id_board は boards と同じ盤面から作成されている必要があり、連鎖後の状態に更新される。
+    pub fn do_chains_with_tracking(
+        &self,
+        boards: &mut BitBoards,
+        id_board: &mut PuyoIdBoard,
+        trace: u64,
+    ) -> (Vec<Chain>, PuyoTracking) {
+        let mut traced_puyos: Vec<TrackedPuyo> = Vec::new();
+        let mut popped_puyos: Vec<Vec<TrackedPuyo>> = Vec::new();
+        let mut prev_occ = boards.occupied_board();
+
+        let chains = self.do_chains_with_observer(boards, trace, &mut |kind, boards, chains| {
+            let occ = boards.occupied_board();
+            match kind {
+                AnimationStepKind::Traced | AnimationStepKind::Popped => {
+                    let puyos = id_board.collect_puyos(prev_occ & !occ);
+                    if chains.len() > popped_puyos.len() {
+                        popped_puyos.push(puyos);
+                    } else {
+                        traced_puyos = puyos;
+                    }
+                }
+                AnimationStepKind::DroppedInField => {
+                    id_board.relocate(prev_occ & FIELD_MASK, occ & FIELD_MASK);
+                }
+                AnimationStepKind::NextDropped => {
+                    id_board.relocate(prev_occ, occ);
+                }
+                AnimationStepKind::Initial => {}
+            }
+            prev_occ = occ;
+        });
+
+        let occ = boards.occupied_board();
+        let mut remaining_next_puyos: NextPuyos = [None; 8];
+        for (x, cell) in remaining_next_puyos.iter_mut().enumerate() {
+            let bit_index = ((7 - x) * HEIGHT + 6) as u32;
+            if let Some(puyo_type) = boards.get_puyo_type(1 << bit_index) {
+                *cell = Some(Puyo {
+                    id: id_board.get_id(bit_index),
+                    puyo_type,
+                });
+            }
+        }
+
+        let tracking = PuyoTracking {
+            traced_puyos,
+            popped_puyos,
+            surviving_puyos: id_board.collect_puyos(occ & FIELD_MASK),
+            remaining_next_puyos,
+        };
+
+        (chains, tracking)
+    }
+
+    /// なぞり消し(あるいは塗り替え)を実施して連鎖を発生させる。
+    /// 盤面が変化しうる各段階の後に observer が呼ばれる。
+    fn do_chains_with_observer<F>(
+        &self,
+        boards: &mut BitBoards,
+        trace: u64,
+        observer: &mut F,
+    ) -> Vec<Chain>
+    where
+        F: FnMut(AnimationStepKind, &BitBoards, &Vec<Chain>),
+    {
         let mut chains: Vec<Chain> = Vec::new();
 
-        if self.activate_tracing(boards, trace, &mut chains) {
+        let activated = self.activate_tracing(boards, trace, &mut chains);
+        observer(AnimationStepKind::Traced, boards, &chains);
+
+        if activated {
             while self.drop_in_field(boards) {
+                observer(AnimationStepKind::DroppedInField, boards, &chains);
                 if !self.pop_puyo_blocks(boards, false, &mut chains) {
                     break;
                 }
+                observer(AnimationStepKind::Popped, boards, &chains);
             }
             while self.drop_next_into_field(boards) {
+                observer(AnimationStepKind::NextDropped, boards, &chains);
                 if !self.pop_puyo_blocks(boards, true, &mut chains) {
                     break;
                 }
+                observer(AnimationStepKind::Popped, boards, &chains);
                 while self.drop_in_field(boards) {
+                    observer(AnimationStepKind::DroppedInField, boards, &chains);
                     if !self.pop_puyo_blocks(boards, true, &mut chains) {
                         break;
                     }
+                    observer(AnimationStepKind::Popped, boards, &chains);
                 }
             }
         }
@@ -282,6 +603,12 @@ impl<'a> SimulatorBB<'a> {
             + ojama_connected.count_ones();
 
         let mut attributes: HashMap<PuyoAttr, AttributeChain> = HashMap::new();
+        let chain_factor = calc_chain_factor(
+            chain_num,
+            self.environment.chain_coefficient_table.as_deref(),
+            Some(self.environment.chain_leverage),
+        )
+        .unwrap();
 
         for i in 0..colors_connected.len() {
             let (connected, separated_blocks_num) = colors_connected[i];
@@ -297,10 +624,10 @@ impl<'a> SimulatorBB<'a> {
                     simultaneous_num,
                     separated_blocks_num,
                     Some(self.environment.minimum_puyo_num_for_popping),
-                    None,
+                    self.environment.popping_coefficient,
                     Some(self.environment.popping_leverage),
                 ),
-                calc_chain_factor(chain_num, Some(self.environment.chain_leverage)).unwrap(),
+                chain_factor,
             );
 
             attributes.insert(
@@ -313,47 +640,45 @@ impl<'a> SimulatorBB<'a> {
             );
         }
 
-        if heart_connected != 0 {
-            attributes.insert(
-                PuyoAttr::Heart,
-                AttributeChain {
-                    strength: 0.0,
-                    popped_count: heart_connected.count_ones(),
-                    separated_blocks_num: 0,
-                },
+        let mut targeted_special_strengths: HashMap<PuyoAttr, f64> = HashMap::new();
+        for (attr, connected) in [
+            (PuyoAttr::Heart, heart_connected),
+            (PuyoAttr::Prism, prism_connected),
+            (PuyoAttr::Ojama, ojama_connected),
+            (PuyoAttr::Kata, kata_connected),
+        ] {
+            if connected == 0 {
+                continue;
+            }
+
+            let popped_count = connected.count_ones();
+            let rule = self.environment.get_special_puyo_strength(attr);
+            let mut strength = rule.calc_strength(
+                popped_count as f64,
+                rule.strength_per_puyo,
+                chain_factor,
+                |a, f| a * f,
             );
-        }
-        if prism_connected != 0 {
-            let popped_count = prism_connected.count_ones();
+
+            // 対象の色属性が指定されていれば、その属性に加わる強さとして別に集計する
+            if let Some(target_attrs) = &rule.target_attrs {
+                for target_attr in target_attrs {
+                    *targeted_special_strengths
+                        .entry(*target_attr)
+                        .or_insert(0.0) += strength;
+                }
+                strength = 0.0;
+            }
+
             attributes.insert(
-                PuyoAttr::Prism,
+                attr,
                 AttributeChain {
-                    strength: 3.0 * popped_count as f64,
+                    strength,
                     popped_count,
                     separated_blocks_num: 0,
                 },
             );
         }
-        if ojama_connected != 0 {
-            attributes.insert(
-                PuyoAttr::Ojama,
-                AttributeChain {
-                    strength: 0.0,
-                    popped_count: ojama_connected.count_ones(),
-                    separated_blocks_num: 0,
-                },
-            );
-        }
-        if kata_connected != 0 {
-            attributes.insert(
-                PuyoAttr::Kata,
-                AttributeChain {
-                    strength: 0.0,
-                    popped_count: kata_connected.count_ones(),
-                    separated_blocks_num: 0,
-                },
-            );
-        }
 
         let poppable_connected =
             total_colored_connected | heart_connected | prism_connected | ojama_connected;
@@ -388,6 +713,7 @@ impl<'a> SimulatorBB<'a> {
             attributes,
             popped_chance_num: chance_connected.count_ones(),
             is_all_cleared,
+            targeted_special_strengths,
         };
 
         chains.push(chain);
@@ -731,6 +1057,7 @@ mod tests {
     use std::collections::HashSet;
 
     use super::*;
+    use crate::{puyo_attr::COLOR_ATTRS, simulation_environment::SpecialPuyoStrength};
 
     #[test]
     fn test_pack_board() {
@@ -1445,9 +1772,13 @@ mod tests {
             is_chance_mode: false,
             minimum_puyo_num_for_popping: 3,
             max_trace_num: 5,
+            min_trace_num: 0,
             trace_mode: TraceMode::Normal,
             popping_leverage: 1.0,
             chain_leverage: 7.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
         };
         let field = [
             [r, p, h, p, y, g, y, y],
@@ -1489,6 +1820,7 @@ mod tests {
                 )]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -1507,7 +1839,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1526,7 +1859,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1545,7 +1879,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1564,7 +1899,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1593,7 +1929,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1612,7 +1949,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1641,7 +1979,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1660,7 +1999,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1689,7 +2029,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1708,7 +2049,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1727,7 +2069,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1746,7 +2089,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1765,7 +2109,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
     }
@@ -1785,9 +2130,13 @@ mod tests {
             is_chance_mode: false,
             minimum_puyo_num_for_popping: 4,
             max_trace_num: 5,
+            min_trace_num: 0,
             trace_mode: TraceMode::ToBlue,
             popping_leverage: 1.0,
             chain_leverage: 10.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
         };
         let field = [
             [y, p, r, g, y, g, b, g],
@@ -1844,7 +2193,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1863,7 +2213,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1882,7 +2233,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1901,7 +2253,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1920,7 +2273,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1939,7 +2293,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1958,7 +2313,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1977,7 +2333,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1996,7 +2353,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2015,7 +2373,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
     }
@@ -2034,9 +2393,13 @@ mod tests {
             is_chance_mode: true,
             minimum_puyo_num_for_popping: 4,
             max_trace_num: 5,
+            min_trace_num: 0,
             trace_mode: TraceMode::Normal,
             popping_leverage: 1.0,
             chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
         };
         let field = [
             [p, b, e, g, g, g, e, e],
@@ -2093,6 +2456,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2121,7 +2485,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2150,7 +2515,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2179,7 +2545,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: true
+                is_all_cleared: true,
+                targeted_special_strengths: HashMap::new()
             }
         );
     }
@@ -2203,9 +2570,13 @@ mod tests {
             is_chance_mode: false,
             minimum_puyo_num_for_popping: 4,
             max_trace_num: 5,
+            min_trace_num: 0,
             trace_mode: TraceMode::Normal,
             popping_leverage: 1.0,
             chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
         };
         let boost_area_coord_set = HashSet::from([
             PuyoCoord { x: 4, y: 1 },
@@ -2278,7 +2649,8 @@ mod tests {
                     ),
                 ]),
                 popped_chance_num: 1,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2307,7 +2679,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
     }
@@ -2330,9 +2703,13 @@ mod tests {
             is_chance_mode: false,
             minimum_puyo_num_for_popping: 4,
             max_trace_num: 5,
+            min_trace_num: 0,
             trace_mode: TraceMode::Normal,
             popping_leverage: 1.0,
             chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
         };
         let simulator = SimulatorBB {
             environment: &environment,
@@ -2411,6 +2788,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2439,7 +2817,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2476,8 +2855,688 @@ mod tests {
                     ),
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_bit_boards() {
+        // Arrange
+        let r = Some(PuyoType::Red);
+        let rp = Some(PuyoType::RedPlus);
+        let bc = Some(PuyoType::BlueChance);
+        let gcp = Some(PuyoType::GreenChancePlus);
+        let y = Some(PuyoType::Yellow);
+        let p = Some(PuyoType::PurplePlus);
+        let h = Some(PuyoType::Heart);
+        let w = Some(PuyoType::Prism);
+        let o = Some(PuyoType::Ojama);
+        let k = Some(PuyoType::Kata);
+        let d = Some(PuyoType::Padding);
+        let e: Option<PuyoType> = None;
+        let field = [
+            [e, e, e, y, e, e, e, d],
+            [e, e, e, p, k, e, e, d],
+            [y, rp, y, w, bc, e, e, d],
+            [p, p, p, bc, gcp, k, k, k],
+            [r, w, r, bc, gcp, h, h, h],
+            [r, r, h, bc, gcp, o, h, h],
+        ];
+        let next_puyos = [r, e, gcp, e, h, e, o, d];
+        let boards = SimulatorBB::create_bit_boards(&field, &next_puyos);
+
+        // Act
+        let (actual_field, actual_next_puyos) = SimulatorBB::decode_bit_boards(&boards);
+
+        // Assert
+        assert_eq!(actual_field, field);
+        assert_eq!(actual_next_puyos, next_puyos);
+    }
+
+    #[test]
+    fn test_decode_field_and_next() {
+        // Arrange
+        let r = PuyoType::RedChancePlus;
+        let b = PuyoType::BlueChance;
+        let g = PuyoType::GreenPlus;
+        let y = PuyoType::Yellow;
+        let h = PuyoType::Heart;
+        let k = PuyoType::Kata;
+        let e = PuyoType::Padding;
+        let mut id_counter = 0;
+        let mut to_puyo = |puyo_type: PuyoType| {
+            id_counter += 1;
+            if puyo_type == PuyoType::Padding {
+                return None;
+            }
+            Some(Puyo {
+                id: id_counter * 10,
+                puyo_type,
+            })
+        };
+        let field = [
+            [e, e, e, y, e, e, e, e],
+            [e, e, e, r, k, e, e, e],
+            [y, y, y, h, b, e, e, e],
+            [r, r, r, b, g, k, k, k],
+            [r, h, r, b, g, h, h, h],
+            [r, r, h, b, g, b, h, h],
+        ]
+        .map(|row| row.map(&mut to_puyo));
+        let next_puyos = [g, e, e, b, r, e, e, k].map(&mut to_puyo);
+        let boards = SimulatorBB::create_bit_boards(
+            &field.map(|row| row.map(|c| c.map(|p| p.puyo_type))),
+            &next_puyos.map(|c| c.map(|p| p.puyo_type)),
+        );
+        let id_board = SimulatorBB::create_puyo_id_board(&field, &next_puyos);
+
+        // Act
+        let actual = SimulatorBB::decode_field_and_next(&boards, &id_board);
+
+        // Assert
+        assert_eq!(actual, FieldAndNext { field, next_puyos });
+    }
+
+    #[test]
+    fn test_board_to_coords() {
+        // Arrange
+        let coords = vec![
+            PuyoCoord { x: 0, y: 0 },
+            PuyoCoord { x: 5, y: 2 },
+            PuyoCoord { x: 6, y: 2 },
+            PuyoCoord { x: 7, y: 5 },
+        ];
+        let board = SimulatorBB::coords_to_board(coords.iter()) | NEXT_MASK;
+
+        // Act
+        let actual = SimulatorBB::board_to_coords(board);
+
+        // Assert
+        assert_eq!(actual, coords);
+    }
+
+    #[test]
+    fn test_do_chains_with_custom_coefficients() {
+        // Arrange
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let p = Some(PuyoType::Purple);
+        let h = Some(PuyoType::Heart);
+        let w = Some(PuyoType::Prism);
+        let o = Some(PuyoType::Ojama);
+        let k = Some(PuyoType::Kata);
+        let e: Option<PuyoType> = None;
+
+        let environment = SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 5,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 2.0,
+            popping_coefficient: Some(0.5),
+            chain_coefficient_table: Some(vec![0.1, 0.2]),
+            special_puyo_strengths: HashMap::new(),
+        };
+        let simulator = SimulatorBB {
+            environment: &environment,
+            boost_area: 0,
+        };
+        let field = [
+            [e, e, e, y, e, e, e, e],
+            [e, e, e, p, k, e, e, e],
+            [y, y, y, w, b, e, e, e],
+            [p, p, p, b, g, k, k, k],
+            [r, w, r, b, g, h, h, h],
+            [r, r, h, b, g, o, h, h],
+        ];
+        let next_puyos = [g, e, e, e, r, e, e, e];
+        let mut boards = SimulatorBB::create_bit_boards(&field, &next_puyos);
+        let trace = SimulatorBB::coords_to_board(
+            [
+                PuyoCoord { x: 4, y: 3 },
+                PuyoCoord { x: 4, y: 4 },
+                PuyoCoord { x: 4, y: 5 },
+            ]
+            .iter(),
+        );
+
+        // Act
+        let actual = simulator.do_chains(&mut boards, trace);
+
+        // Assert
+        assert_eq!(actual.len(), 3);
+        assert_relative_eq!(actual[0].attributes[&PuyoAttr::Blue].strength, 2.4);
+        // 2連鎖目までは表の係数、3連鎖目は表の長さを超えるので最後の係数を使う
+        let chain_coefficients = [0.1, 0.2, 0.2];
+        for (chain, chain_coefficient) in actual.iter().zip(chain_coefficients) {
+            for attr in COLOR_ATTRS {
+                let Some(attr_chain) = chain.attributes.get(&attr) else {
+                    continue;
+                };
+                let popping_factor = (1.0 + (chain.simultaneous_num - 4) as f64 * 0.5)
+                    * attr_chain.separated_blocks_num as f64;
+                assert_relative_eq!(
+                    attr_chain.strength,
+                    popping_factor * (chain_coefficient * 2.0 + 1.0)
+                );
             }
+        }
+    }
+
+    #[test]
+    fn test_do_chains_with_special_puyo_strengths() {
+        // Arrange
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let p = Some(PuyoType::Purple);
+        let h = Some(PuyoType::Heart);
+        let w = Some(PuyoType::Prism);
+        let o = Some(PuyoType::Ojama);
+        let k = Some(PuyoType::Kata);
+        let e: Option<PuyoType> = None;
+
+        let default_environment = SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 5,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        };
+        let field = [
+            [e, e, e, y, e, e, e, e],
+            [e, e, e, p, k, e, e, e],
+            [y, y, y, w, b, e, e, e],
+            [p, p, p, b, g, k, k, k],
+            [r, w, r, b, g, h, h, h],
+            [r, r, h, b, g, o, h, h],
+        ];
+        let next_puyos = [g, e, e, e, r, e, e, e];
+        let trace = SimulatorBB::coords_to_board(
+            [
+                PuyoCoord { x: 4, y: 3 },
+                PuyoCoord { x: 4, y: 4 },
+                PuyoCoord { x: 4, y: 5 },
+            ]
+            .iter(),
+        );
+        let default_chains = SimulatorBB {
+            environment: &default_environment,
+            boost_area: 0,
+        }
+        .do_chains(
+            &mut SimulatorBB::create_bit_boards(&field, &next_puyos),
+            trace,
+        );
+        let environment = SimulationEnvironment {
+            special_puyo_strengths: HashMap::from([
+                (
+                    PuyoAttr::Heart,
+                    SpecialPuyoStrength {
+                        strength_per_puyo: 2.0,
+                        applies_chain_factor: true,
+                        target_attrs: Some(vec![PuyoAttr::Red]),
+                    },
+                ),
+                (
+                    PuyoAttr::Prism,
+                    SpecialPuyoStrength {
+                        strength_per_puyo: 1.0,
+                        applies_chain_factor: false,
+                        target_attrs: None,
+                    },
+                ),
+                (
+                    PuyoAttr::Ojama,
+                    SpecialPuyoStrength {
+                        strength_per_puyo: 0.5,
+                        applies_chain_factor: false,
+                        target_attrs: None,
+                    },
+                ),
+            ]),
+            ..default_environment
+        };
+
+        // Act
+        let actual = SimulatorBB {
+            environment: &environment,
+            boost_area: 0,
+        }
+        .do_chains(
+            &mut SimulatorBB::create_bit_boards(&field, &next_puyos),
+            trace,
+        );
+
+        // Assert
+        assert_eq!(actual.len(), default_chains.len());
+        for (chain, default_chain) in actual.iter().zip(default_chains.iter()) {
+            let strength = |attr: PuyoAttr| chain.attributes.get(&attr).map(|a| a.strength);
+            let popped_count =
+                |attr: PuyoAttr| chain.attributes.get(&attr).map_or(0, |a| a.popped_count) as f64;
+            let default_strength = |attr: PuyoAttr| {
+                default_chain
+                    .attributes
+                    .get(&attr)
+                    .map_or(0.0, |a| a.strength)
+            };
+            let chain_factor = calc_chain_factor(chain.chain_num, None, Some(1.0)).unwrap();
+
+            if popped_count(PuyoAttr::Prism) > 0.0 {
+                assert_relative_eq!(
+                    strength(PuyoAttr::Prism).unwrap(),
+                    popped_count(PuyoAttr::Prism)
+                );
+            }
+            if popped_count(PuyoAttr::Ojama) > 0.0 {
+                assert_relative_eq!(
+                    strength(PuyoAttr::Ojama).unwrap(),
+                    0.5 * popped_count(PuyoAttr::Ojama)
+                );
+            }
+            // ハートの強さは赤に加わる。赤の連鎖情報自体は変わらない
+            assert_eq!(
+                strength(PuyoAttr::Red).unwrap_or(0.0),
+                default_strength(PuyoAttr::Red)
+            );
+            if popped_count(PuyoAttr::Heart) > 0.0 {
+                assert_eq!(strength(PuyoAttr::Heart), Some(0.0));
+                assert_relative_eq!(
+                    chain.targeted_special_strengths[&PuyoAttr::Red],
+                    2.0 * popped_count(PuyoAttr::Heart) * chain_factor
+                );
+            } else {
+                assert!(chain.targeted_special_strengths.is_empty());
+            }
+            // 消えていない色属性の連鎖情報は作らない
+            assert_eq!(
+                chain.attributes.contains_key(&PuyoAttr::Red),
+                default_chain.attributes.contains_key(&PuyoAttr::Red)
+            );
+            assert_eq!(
+                strength(PuyoAttr::Blue).unwrap_or(0.0),
+                default_strength(PuyoAttr::Blue)
+            );
+        }
+        assert!(actual
+            .iter()
+            .any(|c| c.attributes.contains_key(&PuyoAttr::Heart)));
+    }
+
+    #[test]
+    fn test_do_chains_with_steps() {
+        // Arrange
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let p = Some(PuyoType::Purple);
+        let h = Some(PuyoType::Heart);
+        let w = Some(PuyoType::Prism);
+        let o = Some(PuyoType::Ojama);
+        let k = Some(PuyoType::Kata);
+        let e: Option<PuyoType> = None;
+
+        let environment = SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 5,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        };
+        let simulator = SimulatorBB {
+            environment: &environment,
+            boost_area: 0,
+        };
+        let field = [
+            [e, e, e, y, e, e, e, e],
+            [e, e, e, p, k, e, e, e],
+            [y, y, y, w, b, e, e, e],
+            [p, p, p, b, g, k, k, k],
+            [r, w, r, b, g, h, h, h],
+            [r, r, h, b, g, o, h, h],
+        ];
+        let next_puyos = [g, e, e, e, r, e, e, e];
+        let mut boards = SimulatorBB::create_bit_boards(&field, &next_puyos);
+        let mut boards_for_expectation = boards.clone();
+        let trace_coords: Vec<PuyoCoord> = vec![
+            PuyoCoord { x: 4, y: 3 },
+            PuyoCoord { x: 4, y: 4 },
+            PuyoCoord { x: 4, y: 5 },
+        ];
+        let trace = SimulatorBB::coords_to_board(trace_coords.iter());
+
+        // Act
+        let (actual_chains, actual_steps) = simulator.do_chains_with_steps(&mut boards, trace);
+
+        // Assert
+        let expected_chains = simulator.do_chains(&mut boards_for_expectation, trace);
+        assert_eq!(actual_chains, expected_chains);
+        assert_eq!(boards, boards_for_expectation);
+
+        assert_eq!(
+            actual_steps.iter().map(|s| s.kind).collect::<Vec<_>>(),
+            vec![
+                AnimationStepKind::Initial,
+                AnimationStepKind::Traced,
+                AnimationStepKind::DroppedInField,
+                AnimationStepKind::Popped,
+                AnimationStepKind::DroppedInField,
+                AnimationStepKind::Popped,
+                AnimationStepKind::DroppedInField,
+                AnimationStepKind::Popped,
+                AnimationStepKind::NextDropped,
+            ]
+        );
+        assert_eq!(
+            actual_steps.iter().map(|s| s.chain_num).collect::<Vec<_>>(),
+            vec![0, 0, 0, 1, 1, 2, 2, 3, 3]
+        );
+
+        let initial = &actual_steps[0];
+        assert_eq!(initial.field, field);
+        assert_eq!(initial.next_puyos, next_puyos);
+        assert_eq!(initial.popped_coords, vec![]);
+
+        let traced = &actual_steps[1];
+        assert_eq!(traced.popped_coords, trace_coords);
+        assert_eq!(traced.field[3][4], None);
+        assert_eq!(traced.field[4][4], None);
+        assert_eq!(traced.field[5][4], None);
+
+        let dropped = &actual_steps[2];
+        assert_eq!(dropped.popped_coords, vec![]);
+        assert_eq!(dropped.field[1][4], None);
+        assert_eq!(dropped.field[4][4], k);
+        assert_eq!(dropped.field[5][4], b);
+
+        let popped = &actual_steps[3];
+        assert_eq!(
+            popped.popped_coords,
+            vec![
+                PuyoCoord { x: 3, y: 2 },
+                PuyoCoord { x: 3, y: 3 },
+                PuyoCoord { x: 3, y: 4 },
+                PuyoCoord { x: 2, y: 5 },
+                PuyoCoord { x: 3, y: 5 },
+                PuyoCoord { x: 4, y: 5 },
+                PuyoCoord { x: 5, y: 5 },
+            ]
+        );
+        // 固ぷよはおじゃまになる
+        assert_eq!(popped.field[4][4], o);
+
+        let (last_field, last_next_puyos) = SimulatorBB::decode_bit_boards(&boards);
+        let last = actual_steps.last().unwrap();
+        assert_eq!(last.field, last_field);
+        assert_eq!(last.next_puyos, last_next_puyos);
+        assert_eq!(last.next_puyos, [e, e, e, e, e, e, e, e]);
+    }
+
+    #[test]
+    fn test_simulate_trace_with_steps() {
+        // Arrange
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let e: Option<PuyoType> = None;
+
+        let environment = SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 2,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        };
+        let simulator = SimulatorBB {
+            environment: &environment,
+            boost_area: 0,
+        };
+        let field_types = [
+            [e, e, e, e, e, e, e, e],
+            [e, e, e, e, e, e, e, e],
+            [e, e, e, e, e, e, e, e],
+            [e, e, e, e, e, e, e, e],
+            [r, e, e, e, e, e, e, e],
+            [y, r, r, r, g, e, e, e],
+        ];
+        let next_types = [b, e, e, e, e, e, e, e];
+        let mut id_counter = 0;
+        let mut to_puyo = |option: Option<PuyoType>| {
+            option.map(|puyo_type| {
+                id_counter += 1;
+                Puyo {
+                    id: id_counter,
+                    puyo_type,
+                }
+            })
+        };
+        let field = field_types.map(|row| row.map(&mut to_puyo));
+        let next_puyos = next_types.map(&mut to_puyo);
+        let trace_coords = vec![PuyoCoord { x: 0, y: 5 }];
+        let mut boards = SimulatorBB::create_bit_boards(&field_types, &next_types);
+
+        // Act
+        let actual = simulator.simulate_trace_with_steps(&field, &next_puyos, &trace_coords);
+
+        // Assert
+        let expected = simulator.do_chains_with_steps(
+            &mut boards,
+            SimulatorBB::coords_to_board(trace_coords.iter()),
+        );
+        let (chains, steps) = actual.unwrap();
+        assert_eq!(chains, expected.0);
+        assert_eq!(steps, expected.1);
+        assert_eq!(chains.len(), 1);
+        assert_eq!(steps[0].field, field_types);
+        assert_eq!(steps.last().unwrap().field[5][0], b);
+        // なぞりが盤面に対して正しくなければエラーになる
+        assert!(simulator
+            .simulate_trace_with_steps(&field, &next_puyos, &[PuyoCoord { x: 5, y: 5 }])
+            .is_err());
+        assert!(simulator
+            .simulate_trace_with_steps(
+                &field,
+                &next_puyos,
+                &[
+                    PuyoCoord { x: 0, y: 4 },
+                    PuyoCoord { x: 0, y: 5 },
+                    PuyoCoord { x: 1, y: 5 },
+                ]
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_do_chains_with_tracking() {
+        // Arrange
+        let r = PuyoType::Red;
+        let b = PuyoType::Blue;
+        let g = PuyoType::Green;
+        let y = PuyoType::Yellow;
+        let p = PuyoType::Purple;
+        let h = PuyoType::Heart;
+        let w = PuyoType::Prism;
+        let o = PuyoType::Ojama;
+        let k = PuyoType::Kata;
+        let e = PuyoType::Padding;
+
+        let environment = SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 5,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        };
+        let simulator = SimulatorBB {
+            environment: &environment,
+            boost_area: 0,
+        };
+        // IDは y * 8 + x + 1 とし、ネクストは 101 から始める。
+        let mut id_counter = 0;
+        let field = [
+            [e, e, e, y, e, e, e, e],
+            [e, e, e, p, k, e, e, e],
+            [y, y, y, w, b, e, e, e],
+            [p, p, p, b, g, k, k, k],
+            [r, w, r, b, g, h, h, h],
+            [r, r, h, b, g, o, h, h],
+        ]
+        .map(|row| {
+            row.map(|puyo_type| {
+                id_counter += 1;
+                if puyo_type == PuyoType::Padding {
+                    return None;
+                }
+                Some(Puyo {
+                    id: id_counter,
+                    puyo_type,
+                })
+            })
+        });
+        let next_puyos: NextPuyos = [
+            Some(Puyo {
+                id: 101,
+                puyo_type: g,
+            }),
+            None,
+            None,
+            None,
+            Some(Puyo {
+                id: 105,
+                puyo_type: r,
+            }),
+            None,
+            None,
+            None,
+        ];
+        let mut boards = SimulatorBB::create_bit_boards(
+            &field.map(|row| row.map(|c| c.map(|p| p.puyo_type))),
+            &next_puyos.map(|c| c.map(|p| p.puyo_type)),
+        );
+        let mut id_board = SimulatorBB::create_puyo_id_board(&field, &next_puyos);
+        let trace_coords: Vec<PuyoCoord> = vec![
+            PuyoCoord { x: 4, y: 3 },
+            PuyoCoord { x: 4, y: 4 },
+            PuyoCoord { x: 4, y: 5 },
+        ];
+        let trace = SimulatorBB::coords_to_board(trace_coords.iter());
+        let t = |id: i32, x: u8, y: u8| TrackedPuyo {
+            id,
+            coord: PuyoCoord { x, y },
+        };
+
+        // Act
+        let (actual_chains, actual) =
+            simulator.do_chains_with_tracking(&mut boards, &mut id_board, trace);
+
+        // Assert
+        assert_eq!(actual_chains.len(), 3);
+        assert_eq!(
+            actual.traced_puyos,
+            vec![t(29, 4, 3), t(37, 4, 4), t(45, 4, 5)]
+        );
+        assert_eq!(actual.popped_puyos.len(), 3);
+        assert_eq!(
+            actual.popped_puyos[0],
+            vec![
+                t(20, 3, 2),
+                t(28, 3, 3),
+                t(36, 3, 4),
+                t(43, 2, 5),
+                t(44, 3, 5),
+                t(21, 4, 5),
+                t(46, 5, 5),
+            ]
+        );
+        assert_eq!(
+            actual.popped_puyos[1],
+            vec![
+                t(33, 0, 4),
+                t(34, 1, 4),
+                t(41, 0, 5),
+                t(42, 1, 5),
+                t(35, 2, 5),
+            ]
+        );
+        // 固ぷよ(ID:13)はおじゃまになってから弾ける
+        assert_eq!(
+            actual.popped_puyos[2],
+            vec![
+                t(17, 0, 4),
+                t(18, 1, 4),
+                t(19, 2, 4),
+                t(4, 3, 4),
+                t(25, 0, 5),
+                t(26, 1, 5),
+                t(27, 2, 5),
+                t(12, 3, 5),
+                t(13, 4, 5),
+            ]
+        );
+        assert_eq!(
+            actual.surviving_puyos,
+            vec![
+                t(31, 6, 3),
+                t(32, 7, 3),
+                t(30, 5, 4),
+                t(39, 6, 4),
+                t(40, 7, 4),
+                t(101, 0, 5),
+                t(105, 4, 5),
+                t(38, 5, 5),
+                t(47, 6, 5),
+                t(48, 7, 5),
+            ]
+        );
+        assert_eq!(actual.remaining_next_puyos, [None; 8]);
+
+        let after = SimulatorBB::decode_field_and_next(&boards, &id_board);
+        assert_eq!(after.next_puyos, actual.remaining_next_puyos);
+        for tracked in actual.surviving_puyos.iter() {
+            let puyo = after.field[tracked.coord.y as usize][tracked.coord.x as usize].unwrap();
+            assert_eq!(puyo.id, tracked.id);
+        }
+        assert_eq!(
+            after.field[5].map(|c| c.map(|p| p.puyo_type)),
+            [
+                Some(g),
+                None,
+                None,
+                None,
+                Some(r),
+                Some(h),
+                Some(h),
+                Some(h)
+            ]
         );
     }
 
@@ -2496,9 +3555,13 @@ mod tests {
             is_chance_mode: false,
             minimum_puyo_num_for_popping: 4,
             max_trace_num: 9,
+            min_trace_num: 0,
             trace_mode: TraceMode::ToBlue,
             popping_leverage: 5.0,
             chain_leverage: 10.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
         };
         let simulator = SimulatorBB {
             environment: &environment,
@@ -2566,7 +3629,8 @@ mod tests {
                     ),
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2585,7 +3649,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2604,7 +3669,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2624,6 +3690,7 @@ mod tests {
                 ),]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2642,7 +3709,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2661,7 +3729,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2680,7 +3749,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2699,7 +3769,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2718,7 +3789,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
     }
@@ -1,4 +1,7 @@
-use crate::{chain::Chain, puyo_coord::PuyoCoord};
+use crate::{
+    chain::Chain, next_expectation::ValueStatistics, puyo::FieldAndNext, puyo_coord::PuyoCoord,
+    puyo_tracking::PuyoTracking, team::TeamDamage, trace_mode::TraceMode,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +19,9 @@ pub struct SolutionState {
 
     /** 次のなぞり候補となりうる座標のリスト。 */
     next_candidate_coords: Vec<PuyoCoord>,
+
+    /** なぞっている座標の集合。48ビットそれぞれがフィールドの位置を表す。 */
+    trace_field_bits: u64,
 }
 
 impl SolutionState {
@@ -25,7 +31,39 @@ impl SolutionState {
             forbidden_field_bits: (1 << forbidden_indexes_before) - 1,
             trace_coords: Vec::new(),
             next_candidate_coords: Vec::new(),
+            trace_field_bits: 0,
+        }
+    }
+
+    /** 座標の集合を、48ビットそれぞれがフィールドの位置を表すビット列にする。 */
+    pub fn coords_to_field_bits<'a, I>(coords: I) -> u64
+    where
+        I: Iterator<Item = &'a PuyoCoord>,
+    {
+        coords.fold(0, |acc, c| acc | (1 << c.index()))
+    }
+
+    /** なぞってはいけない座標を禁止集合に加える。なぞり始める前に呼ぶこと。 */
+    pub fn forbid(&mut self, field_bits: u64) {
+        self.forbidden_field_bits |= field_bits;
+    }
+
+    /** 指定の座標を全てなぞっているかどうか */
+    pub fn includes_all(&self, field_bits: u64) -> bool {
+        field_bits & !self.trace_field_bits == 0
+    }
+
+    /**
+     * これからなぞりを伸ばして、指定の座標を全てなぞれる可能性があるかどうか。
+     * まだなぞっていない座標が禁止集合に入っているか、残りのなぞり数で足りなければ false。
+     */
+    pub fn can_include_all(&self, field_bits: u64, max_trace_num: u32) -> bool {
+        let missing = field_bits & !self.trace_field_bits;
+        if missing & self.forbidden_field_bits != 0 {
+            return false;
         }
+        let rest = (max_trace_num as usize).saturating_sub(self.trace_coords.len());
+        missing.count_ones() as usize <= rest
     }
 
     pub fn __get_forbidden_field_bits(&self) -> u64 {
@@ -94,6 +132,7 @@ impl SolutionState {
             self.next_candidate_coords = new_candidate_coords;
         }
 
+        self.trace_field_bits |= 1 << coord_index;
         self.trace_coords.push(coord);
     }
 }
@@ -127,6 +166,34 @@ pub struct SolutionResult {
     pub popped_kata_num: u32,
     /** 全消しされたかどうか */
     pub is_all_cleared: bool,
+    /** ハートによる回復量 */
+    pub recovery: f64,
+    /** 連鎖数 */
+    pub chain_count: u32,
+    /** 最大の同時消し数 */
+    pub max_simultaneous_num: u32,
+    /** 全ての連鎖で消えたぷよの総数 */
+    pub total_popped_count: u32,
+    /** ぷよのIDの追跡結果。探索中は計算せず、最適解として返すものだけに付与される。 */
+    pub puyo_tracking: Option<PuyoTracking>,
+    /** 連鎖後に残った盤面。探索中は計算せず、最適解として返すものだけに付与される。 */
+    pub board_after_chains: Option<FieldAndNext>,
+    /**
+     * 不明なネクストぷよを補った各盤面での value の統計量。期待値で評価したときだけ付与され、value はその期待値になる。
+     * 期待値で評価するときも、chains と弾けた数、is_all_cleared、recovery、chain_count、max_simultaneous_num、
+     * total_popped_count、puyo_tracking、board_after_chains は不明なマスを空として計算した確定的な値で、
+     * value 以外の優先順位による比較にはこれらを使う。
+     * value と objective_values、team_damage、探索対象の条件の判定は補った盤面での期待値を使う。
+     */
+    pub value_statistics: Option<ValueStatistics>,
+    /** デッキのダメージ。探索対象にデッキが指定されたときに、最適解として返すものだけに付与される。 */
+    pub team_damage: Option<TeamDamage>,
+    /** パレート最適解を求めるときの各目的の値。インデックスは ExplorationTarget::pareto_objectives のインデックスに対応する。 */
+    pub objective_values: Option<Vec<f64>>,
+    /** なぞる順番。ExplorationTarget::drawable_path_only が true のときだけ付与される。 */
+    pub drawing_order: Option<Vec<PuyoCoord>>,
+    /** なぞったときのトレースモード */
+    pub trace_mode: TraceMode,
 }
 
 /** 探索結果 */
@@ -136,6 +203,20 @@ pub struct ExplorationResult {
     pub candidates_num: u64,
     /** 最適解リスト。インデックスが小さい要素ほど最善 */
     pub optimal_solutions: Vec<SolutionResult>,
+    /** 条件ごとの、その条件を満たした候補数。インデックスは ExplorationTarget::constraints のインデックスに対応する。 */
+    pub constraint_passed_nums: Vec<u64>,
+    /** 探索を途中で打ち切ったかどうか。true なら最適解はそれまでに探索した候補の中でのもの。 */
+    #[serde(default)]
+    pub is_partial: bool,
+}
+
+/** 探索の進捗 */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ExplorationProgress {
+    /** これまでに探索した候補数 */
+    pub visited_candidates_num: u64,
+    /** 見積もった全体の候補数 */
+    pub estimated_candidates_num: u64,
 }
 
 #[cfg(test)]
@@ -154,6 +235,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_coords_to_field_bits() {
+        let coords = [PuyoCoord { x: 1, y: 0 }, PuyoCoord { x: 0, y: 1 }];
+        assert_eq!(
+            SolutionState::coords_to_field_bits(coords.iter()),
+            0b1_0000_0010
+        );
+    }
+
+    #[test]
+    fn test_forbid() {
+        // Arrange
+        let mut s = SolutionState::new(0);
+        s.forbid(1 << PuyoCoord { x: 1, y: 0 }.index());
+
+        // Act & Assert
+        assert!(!s.check_if_addable_coord(&PuyoCoord { x: 1, y: 0 }, 5));
+        s.add_trace_coord(PuyoCoord { x: 0, y: 0 });
+        assert_eq!(
+            *s.get_next_candidate_coords(),
+            [PuyoCoord { x: 0, y: 1 }, PuyoCoord { x: 1, y: 1 }]
+        );
+    }
+
+    #[test]
+    fn test_includes_all_and_can_include_all() {
+        // Arrange
+        let required = SolutionState::coords_to_field_bits(
+            [PuyoCoord { x: 1, y: 1 }, PuyoCoord { x: 1, y: 2 }].iter(),
+        );
+        let mut s = SolutionState::new(9);
+
+        // Act & Assert
+        s.add_trace_coord(PuyoCoord { x: 1, y: 1 });
+        assert!(!s.includes_all(required));
+        assert!(s.can_include_all(required, 2));
+        assert!(!s.can_include_all(required, 1));
+
+        // 候補リストで (1, 2) より後ろにある (2, 2) をなぞると (1, 2) は禁止集合に入り、もうなぞれない
+        let mut other = s.clone();
+        other.add_trace_coord(PuyoCoord { x: 2, y: 2 });
+        assert!(!other.can_include_all(required, 5));
+
+        s.add_trace_coord(PuyoCoord { x: 1, y: 2 });
+        assert!(s.includes_all(required));
+        assert!(s.can_include_all(required, 2));
+    }
+
     #[test]
     fn test_get_trace_coords() {
         let mut s = SolutionState::new(0);
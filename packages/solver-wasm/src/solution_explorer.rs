@@ -1,23 +1,38 @@
 use crate::{
+    board_transform::apply_board_transforms,
     chain::Chain,
     chain_helper::{
-        sum_attr_popped_count, sum_colored_attr_damage, sum_puyo_tsukai_count, sum_wild_damage,
+        count_chains, max_simultaneous_num, sum_attr_popped_count, sum_colored_attr_damage,
+        sum_pure_attr_damage, sum_puyo_tsukai_count, sum_simultaneous_num, sum_wild_damage,
     },
+    exploration_shard::{partition_by_weights, ExplorationShard, ShardUnit},
     exploration_target::{
-        CountingBonusType, ExplorationCategory, ExplorationTarget, PreferenceKind,
+        BucketWidth, CountingBonusType, ExplorationCategory, ExplorationTarget, PreferenceKind,
     },
-    puyo::{Field, NextPuyos},
+    next_expectation::{calc_value_statistics, complete_unknown_next},
+    pareto::{compare_objective_values, ParetoObjective},
+    puyo::{Field, FieldAndNext, NextPuyos},
     puyo_attr::PuyoAttr,
     puyo_coord::PuyoCoord,
     puyo_type::is_traceable_type,
+    recovery::calc_recovery,
+    scoring::ScoringMetric,
     simulation_environment::SimulationEnvironment,
-    simulator_bb::{BitBoards, SimulatorBB},
-    solution::{ExplorationResult, SolutionResult, SolutionState},
+    simulator_bb::{BitBoards, PuyoIdBoard, SimulatorBB},
+    solution::{ExplorationProgress, ExplorationResult, SolutionResult, SolutionState},
+    team::{calc_team_damage, calc_team_damage_against, TeamDamage},
+    trace_path::{find_drawing_order, validate_trace},
 };
+use rayon::prelude::*;
 use std::{
+    borrow::Cow,
     cmp,
     collections::{HashMap, HashSet},
-    sync::OnceLock,
+    iter,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
 };
 
 fn better_solution_by_bigger_value<'a>(
@@ -236,6 +251,19 @@ fn better_solution_by_more_ojama_pop<'a>(
     return None;
 }
 
+fn better_solution_by_more_recovery<'a>(
+    s1: &'a SolutionResult,
+    s2: &'a SolutionResult,
+) -> Option<&'a SolutionResult> {
+    if s2.recovery > s1.recovery {
+        Some(s2)
+    } else if s2.recovery < s1.recovery {
+        Some(s1)
+    } else {
+        None
+    }
+}
+
 fn better_solution_by_less_chance_pop<'a>(
     s1: &'a SolutionResult,
     s2: &'a SolutionResult,
@@ -264,12 +292,124 @@ fn better_solution_by_less_ojama_pop<'a>(
     return the_other(s1, s2, better_solution_by_more_ojama_pop(s1, s2));
 }
 
+fn better_solution_by_less_recovery<'a>(
+    s1: &'a SolutionResult,
+    s2: &'a SolutionResult,
+) -> Option<&'a SolutionResult> {
+    the_other(s1, s2, better_solution_by_more_recovery(s1, s2))
+}
+
+fn better_solution_by_more_chain_count<'a>(
+    s1: &'a SolutionResult,
+    s2: &'a SolutionResult,
+) -> Option<&'a SolutionResult> {
+    match s2.chain_count.cmp(&s1.chain_count) {
+        cmp::Ordering::Greater => Some(s2),
+        cmp::Ordering::Less => Some(s1),
+        cmp::Ordering::Equal => None,
+    }
+}
+
+fn better_solution_by_more_max_simultaneous_num<'a>(
+    s1: &'a SolutionResult,
+    s2: &'a SolutionResult,
+) -> Option<&'a SolutionResult> {
+    match s2.max_simultaneous_num.cmp(&s1.max_simultaneous_num) {
+        cmp::Ordering::Greater => Some(s2),
+        cmp::Ordering::Less => Some(s1),
+        cmp::Ordering::Equal => None,
+    }
+}
+
+fn better_solution_by_more_total_popped_count<'a>(
+    s1: &'a SolutionResult,
+    s2: &'a SolutionResult,
+) -> Option<&'a SolutionResult> {
+    match s2.total_popped_count.cmp(&s1.total_popped_count) {
+        cmp::Ordering::Greater => Some(s2),
+        cmp::Ordering::Less => Some(s1),
+        cmp::Ordering::Equal => None,
+    }
+}
+
+fn better_solution_by_less_chain_count<'a>(
+    s1: &'a SolutionResult,
+    s2: &'a SolutionResult,
+) -> Option<&'a SolutionResult> {
+    the_other(s1, s2, better_solution_by_more_chain_count(s1, s2))
+}
+
+fn better_solution_by_less_max_simultaneous_num<'a>(
+    s1: &'a SolutionResult,
+    s2: &'a SolutionResult,
+) -> Option<&'a SolutionResult> {
+    the_other(s1, s2, better_solution_by_more_max_simultaneous_num(s1, s2))
+}
+
+fn better_solution_by_less_total_popped_count<'a>(
+    s1: &'a SolutionResult,
+    s2: &'a SolutionResult,
+) -> Option<&'a SolutionResult> {
+    the_other(s1, s2, better_solution_by_more_total_popped_count(s1, s2))
+}
+
 type BetterFn = for<'a> fn(&'a SolutionResult, &'a SolutionResult) -> Option<&'a SolutionResult>;
 
 static BETTER_METHOD_MAP: OnceLock<HashMap<PreferenceKind, BetterFn>> = OnceLock::new();
 
+/// 区間の幅を指定できる好みについて、比べる値と大きい方が良いかどうかを返す。
+fn preference_metric(kind: PreferenceKind, s: &SolutionResult) -> Option<(f64, bool)> {
+    match kind {
+        PreferenceKind::BiggerValue => Some((s.value, true)),
+        PreferenceKind::SmallerValue => Some((s.value, false)),
+        PreferenceKind::SmallerTraceNum => Some((s.trace_coords.len() as f64, false)),
+        PreferenceKind::BiggerTraceNum => Some((s.trace_coords.len() as f64, true)),
+        PreferenceKind::MoreChancePop => Some((s.popped_chance_num as f64, true)),
+        PreferenceKind::LessChancePop => Some((s.popped_chance_num as f64, false)),
+        PreferenceKind::MorePrismPop => Some((s.popped_prism_num as f64, true)),
+        PreferenceKind::LessPrismPop => Some((s.popped_prism_num as f64, false)),
+        PreferenceKind::MoreHeartPop => Some((s.popped_heart_num as f64, true)),
+        PreferenceKind::LessHeartPop => Some((s.popped_heart_num as f64, false)),
+        PreferenceKind::MoreOjamaPop => {
+            Some(((s.popped_ojama_num + s.popped_kata_num) as f64, true))
+        }
+        PreferenceKind::LessOjamaPop => {
+            Some(((s.popped_ojama_num + s.popped_kata_num) as f64, false))
+        }
+        PreferenceKind::MoreRecovery => Some((s.recovery, true)),
+        PreferenceKind::LessRecovery => Some((s.recovery, false)),
+        PreferenceKind::MoreChainCount => Some((s.chain_count as f64, true)),
+        PreferenceKind::LessChainCount => Some((s.chain_count as f64, false)),
+        PreferenceKind::MoreMaxSimultaneousNum => Some((s.max_simultaneous_num as f64, true)),
+        PreferenceKind::LessMaxSimultaneousNum => Some((s.max_simultaneous_num as f64, false)),
+        PreferenceKind::MoreTotalPoppedCount => Some((s.total_popped_count as f64, true)),
+        PreferenceKind::LessTotalPoppedCount => Some((s.total_popped_count as f64, false)),
+        _ => None,
+    }
+}
+
+/// 値を区間に区切って好みで2つの解を比べる。同じ区間に入るなら None を返す。
+fn better_solution_with_bucket_width<'a>(
+    kind: PreferenceKind,
+    bucket_width: &BucketWidth,
+    s1: &'a SolutionResult,
+    s2: &'a SolutionResult,
+) -> Option<&'a SolutionResult> {
+    let (v1, bigger_is_better) = preference_metric(kind, s1)?;
+    let (v2, _) = preference_metric(kind, s2)?;
+    if bucket_width.is_same_bucket(v1, v2) {
+        None
+    } else if (v2 > v1) == bigger_is_better {
+        Some(s2)
+    } else {
+        Some(s1)
+    }
+}
+
+/// 好みの優先度順に2つの解を比べて良い方を返す。全ての好みで同等なら s1 を返す。
 fn better_solution<'a>(
     preference_priorities: &Vec<PreferenceKind>,
+    preference_bucket_widths: &HashMap<PreferenceKind, BucketWidth>,
     s1: &'a SolutionResult,
     s2: &'a SolutionResult,
 ) -> &'a SolutionResult {
@@ -347,6 +487,10 @@ fn better_solution<'a>(
                 PreferenceKind::MoreOjamaPop,
                 better_solution_by_more_ojama_pop as BetterFn,
             ),
+            (
+                PreferenceKind::MoreRecovery,
+                better_solution_by_more_recovery as BetterFn,
+            ),
             (
                 PreferenceKind::LessChancePop,
                 better_solution_by_less_chance_pop as BetterFn,
@@ -363,10 +507,46 @@ fn better_solution<'a>(
                 PreferenceKind::LessOjamaPop,
                 better_solution_by_less_ojama_pop as BetterFn,
             ),
+            (
+                PreferenceKind::LessRecovery,
+                better_solution_by_less_recovery as BetterFn,
+            ),
+            (
+                PreferenceKind::MoreChainCount,
+                better_solution_by_more_chain_count as BetterFn,
+            ),
+            (
+                PreferenceKind::MoreMaxSimultaneousNum,
+                better_solution_by_more_max_simultaneous_num as BetterFn,
+            ),
+            (
+                PreferenceKind::MoreTotalPoppedCount,
+                better_solution_by_more_total_popped_count as BetterFn,
+            ),
+            (
+                PreferenceKind::LessChainCount,
+                better_solution_by_less_chain_count as BetterFn,
+            ),
+            (
+                PreferenceKind::LessMaxSimultaneousNum,
+                better_solution_by_less_max_simultaneous_num as BetterFn,
+            ),
+            (
+                PreferenceKind::LessTotalPoppedCount,
+                better_solution_by_less_total_popped_count as BetterFn,
+            ),
         ]);
     });
 
     for pref in preference_priorities {
+        if let Some(bucket_width) = preference_bucket_widths.get(pref) {
+            if pref.accepts_bucket_width() {
+                if let Some(s) = better_solution_with_bucket_width(*pref, bucket_width, s1, s2) {
+                    return s;
+                }
+                continue;
+            }
+        }
         if let Some(method) = table.get(pref) {
             if let Some(s) = method(s1, s2) {
                 return s;
@@ -380,8 +560,18 @@ pub struct SolutionExplorer<'a> {
     exploration_target: &'a ExplorationTarget,
     environment: &'a SimulationEnvironment,
     boost_area: u64,
-    field: &'a Field,
+    /** 盤面変換を適用した後のフィールド */
+    field: Field,
     boards: BitBoards,
+    id_board: PuyoIdBoard,
+    /** 不明なネクストぷよを補った盤面とその確率のリスト。期待値で評価しないときは空。 */
+    next_samples: Cow<'a, [(BitBoards, f64)]>,
+    /** 回復量の計算に使う回復力。デッキが指定されていればその合計、無ければ1。 */
+    recovery_stat: f64,
+    /** なぞりに必ず含める座標の集合 (48ビットそれぞれがフィールドの位置を表す) */
+    required_field_bits: u64,
+    /** なぞってはいけない座標の集合 (48ビットそれぞれがフィールドの位置を表す) */
+    forbidden_field_bits: u64,
 }
 
 impl<'a> SolutionExplorer<'a> {
@@ -393,6 +583,12 @@ impl<'a> SolutionExplorer<'a> {
         next_puyos: &'a NextPuyos,
     ) -> SolutionExplorer<'a> {
         let boost_area = SimulatorBB::coords_to_board(boost_area_coord_set.iter());
+        let mut board = FieldAndNext {
+            field: *field,
+            next_puyos: *next_puyos,
+        };
+        apply_board_transforms(&exploration_target.board_transforms, &mut board);
+        let FieldAndNext { field, next_puyos } = board;
         let boards = SimulatorBB::create_bit_boards(
             &field.map(|row| {
                 row.map(|c| match c {
@@ -405,27 +601,57 @@ impl<'a> SolutionExplorer<'a> {
                 None => None,
             }),
         );
+        let id_board = SimulatorBB::create_puyo_id_board(&field, &next_puyos);
+        let next_samples = match &exploration_target.next_expectation {
+            Some(expectation) => {
+                let field_types = field.map(|row| row.map(|c| c.map(|p| p.puyo_type)));
+                complete_unknown_next(expectation, &next_puyos.map(|c| c.map(|p| p.puyo_type)))
+                    .into_iter()
+                    .map(|(next, probability)| {
+                        (
+                            SimulatorBB::create_bit_boards(&field_types, &next),
+                            probability,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .into()
+            }
+            None => Cow::Owned(Vec::new()),
+        };
+        let recovery_stat = match &exploration_target.team {
+            Some(team) => team.cards.iter().map(|card| card.recovery).sum(),
+            None => 1.0,
+        };
         return SolutionExplorer {
             exploration_target,
             environment,
             boost_area,
             field,
             boards,
+            id_board,
+            next_samples,
+            recovery_stat,
+            required_field_bits: SolutionState::coords_to_field_bits(
+                exploration_target.required_coords.iter(),
+            ),
+            forbidden_field_bits: SolutionState::coords_to_field_bits(
+                exploration_target.forbidden_coords.iter(),
+            ),
         };
     }
 
     pub fn solve_all_traces(&self) -> ExplorationResult {
-        let mut result = ExplorationResult {
-            candidates_num: 0,
-            optimal_solutions: Vec::new(),
-        };
-        for y in 0..PuyoCoord::Y_NUM {
-            for x in 0..PuyoCoord::X_NUM {
-                let coord = PuyoCoord::xy_to_coord(x, y).unwrap();
-                let state = SolutionState::new(coord.index());
-                self.advance_trace(&state, coord, &mut result);
+        let mut result = self.new_exploration_result();
+        self.for_each_trace_mode(|explorer| {
+            for y in 0..PuyoCoord::Y_NUM {
+                for x in 0..PuyoCoord::X_NUM {
+                    let coord = PuyoCoord::xy_to_coord(x, y).unwrap();
+                    let state = explorer.new_solution_state(coord.index());
+                    explorer.advance_trace(&state, coord, &mut result, None);
+                }
             }
-        }
+        });
+        self.complete_optimal_solutions(&mut result);
         return result;
     }
 
@@ -433,74 +659,642 @@ impl<'a> SolutionExplorer<'a> {
         match PuyoCoord::index_to_coord(coord_index) {
             None => None,
             Some(coord) => {
-                let mut result = ExplorationResult {
-                    candidates_num: 0,
-                    optimal_solutions: Vec::new(),
-                };
-                let state = SolutionState::new(coord_index);
-                self.advance_trace(&state, coord, &mut result);
+                let mut result = self.new_exploration_result();
+                self.for_each_trace_mode(|explorer| {
+                    let state = explorer.new_solution_state(coord_index);
+                    explorer.advance_trace(&state, coord, &mut result, None);
+                });
+                self.complete_optimal_solutions(&mut result);
                 return Some(result);
             }
         }
     }
 
+    /// solve_all_traces を rayon で並列に解く。
+    /// なぞり始めの座標ごと、さらにその次になぞる座標ごとに探索を分けて並列に解き、
+    /// 部分結果を直列に探索したときと同じ順に merge_exploration_result で併合するので、結果は solve_all_traces と一致する。
+    pub fn solve_all_traces_in_parallel(&self) -> ExplorationResult {
+        let mut result = self.new_exploration_result();
+        self.for_each_trace_mode(|explorer| {
+            let parts: Vec<Vec<ExplorationResult>> = (0..PuyoCoord::X_NUM * PuyoCoord::Y_NUM)
+                .into_par_iter()
+                .map(|coord_index| explorer.solve_traces_from_index_in_parallel(coord_index))
+                .collect();
+            for part in parts.into_iter().flatten() {
+                merge_exploration_result(self.exploration_target, &mut result, part);
+            }
+        });
+        self.complete_optimal_solutions(&mut result);
+        result
+    }
+
+    /// 指定のインデックスからなぞり始める探索を、次になぞる座標ごとに分けて並列に解き、
+    /// 1個だけなぞった候補の結果と、次の座標ごとの結果を探索順に並べて返す。
+    fn solve_traces_from_index_in_parallel(&self, coord_index: u8) -> Vec<ExplorationResult> {
+        let coord = PuyoCoord::index_to_coord(coord_index).unwrap();
+        let mut first = self.new_exploration_result();
+        let state = self.new_solution_state(coord_index);
+        let Some(st) = self.add_and_evaluate(&state, coord, &mut first) else {
+            return Vec::from([first]);
+        };
+        let rest: Vec<ExplorationResult> = st
+            .get_next_candidate_coords()
+            .par_iter()
+            .map(|next_coord| {
+                let mut result = self.new_exploration_result();
+                self.advance_trace(&st, *next_coord, &mut result, None);
+                result
+            })
+            .collect();
+        iter::once(first).chain(rest).collect()
+    }
+
+    /// 探索を、見積もった候補数がなるべく等しい shard_num 個の分担に分ける。
+    /// 候補数はこの盤面で実際に探索して (評価はせずに) 数える。
+    /// 各分担を solve_shard で解き、結果を分担のインデックス順に merge_exploration_results で併合すると、
+    /// solve_all_traces と同じ結果になる。
+    pub fn split_into_shards(&self, shard_num: u32) -> Vec<ExplorationShard> {
+        let units = self.enumerate_shard_units();
+        let weights: Vec<u64> = units.iter().map(|(_, count)| *count).collect();
+        partition_by_weights(&weights, shard_num)
+            .into_iter()
+            .enumerate()
+            .map(|(shard_index, range)| ExplorationShard {
+                shard_index: shard_index as u32,
+                units: units[range.clone()].iter().map(|(unit, _)| *unit).collect(),
+                estimated_candidates_num: weights[range].iter().sum(),
+            })
+            .collect()
+    }
+
+    /// 探索の単位を直列に探索する順に、見積もった候補数とともに列挙する。
+    fn enumerate_shard_units(&self) -> Vec<(ShardUnit, u64)> {
+        self.enumerate_shard_units_with_progress(None, |_| {})
+    }
+
+    /// enumerate_shard_units と同じく列挙しながら、なぞり始めの座標を1つ数え終えるごとに、
+    /// それまでに数えた全トレースモード分の候補数を on_counted に通知する。
+    /// cancel_flag が立てば数えるのをやめ、途中までの列挙を返す。
+    fn enumerate_shard_units_with_progress<F: FnMut(u64)>(
+        &self,
+        cancel_flag: Option<&AtomicBool>,
+        mut on_counted: F,
+    ) -> Vec<(ShardUnit, u64)> {
+        let trace_modes = match &self.exploration_target.trace_modes {
+            Some(trace_modes) => trace_modes.clone(),
+            None => Vec::from([self.environment.trace_mode]),
+        };
+
+        // 候補数はトレースモードによらないので、なぞり始めの座標ごとに一度だけ数える
+        let mut units_per_trace_mode: Vec<(ShardUnit, u64)> = Vec::new();
+        let mut counted_num = 0;
+        for start_index in 0..PuyoCoord::X_NUM * PuyoCoord::Y_NUM {
+            if is_cancelled(cancel_flag) {
+                break;
+            }
+            let coord = PuyoCoord::index_to_coord(start_index).unwrap();
+            let state = self.new_solution_state(start_index);
+            let Some(st) = self.add_coord(&state, coord) else {
+                continue;
+            };
+            let unit = ShardUnit {
+                trace_mode: self.environment.trace_mode,
+                start_index,
+                next_candidate_index: None,
+            };
+            let count = if self.check_candidate(&st).is_some() {
+                1
+            } else {
+                0
+            };
+            units_per_trace_mode.push((unit, count));
+            counted_num += count;
+            for (i, next_coord) in st.get_next_candidate_coords().iter().enumerate() {
+                let unit = ShardUnit {
+                    next_candidate_index: Some(i as u8),
+                    ..unit
+                };
+                let count = self.count_candidates(&st, *next_coord, cancel_flag);
+                units_per_trace_mode.push((unit, count));
+                counted_num += count;
+            }
+            on_counted(counted_num * trace_modes.len() as u64);
+        }
+
+        trace_modes
+            .iter()
+            .flat_map(|trace_mode| {
+                units_per_trace_mode.iter().map(|(unit, count)| {
+                    (
+                        ShardUnit {
+                            trace_mode: *trace_mode,
+                            ..*unit
+                        },
+                        *count,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// split_into_shards で分けた分担の1つを解く。最適解には追跡結果なども付与する。
+    pub fn solve_shard(&self, shard: &ExplorationShard) -> ExplorationResult {
+        let mut result = self.new_exploration_result();
+        for unit in shard.units.iter() {
+            self.solve_shard_unit(unit, &mut result, None);
+        }
+        self.complete_optimal_solutions(&mut result);
+        result
+    }
+
+    /// solve_all_traces と同じ探索をしながら、進捗を on_progress に通知する。
+    /// 全体の候補数は split_into_shards と同じく、探索の前に評価をせずに数えて見積もる。
+    /// 数えている間は、なぞり始めの座標を1つ数え終えるごとに visited_candidates_num を 0、
+    /// estimated_candidates_num をそれまでに数えた候補数として通知し、探索を始めてからは探索の単位を1つ終えるごとに通知する。
+    /// 数えている間も探索している間も cancel_flag を確かめ、立っていればそこで打ち切り、
+    /// それまでに探索した候補の中での最適解を is_partial を true にして返す。
+    pub fn solve_all_traces_with_progress<F: FnMut(&ExplorationProgress)>(
+        &self,
+        cancel_flag: &AtomicBool,
+        mut on_progress: F,
+    ) -> ExplorationResult {
+        let mut result = self.new_exploration_result();
+        let units = self.enumerate_shard_units_with_progress(Some(cancel_flag), |counted_num| {
+            on_progress(&ExplorationProgress {
+                visited_candidates_num: 0,
+                estimated_candidates_num: counted_num,
+            })
+        });
+        let estimated_candidates_num = units.iter().map(|(_, count)| *count).sum();
+        for (unit, _) in units.iter() {
+            if is_cancelled(Some(cancel_flag)) {
+                break;
+            }
+            self.solve_shard_unit(unit, &mut result, Some(cancel_flag));
+            on_progress(&ExplorationProgress {
+                visited_candidates_num: result.candidates_num,
+                estimated_candidates_num,
+            });
+        }
+        // 最後の単位を探索し終えた後に立った場合も、打ち切ったかどうか区別できないので途中までの結果として扱う
+        result.is_partial = is_cancelled(Some(cancel_flag));
+        self.complete_optimal_solutions(&mut result);
+        result
+    }
+
+    fn solve_shard_unit(
+        &self,
+        unit: &ShardUnit,
+        exploration_result: &mut ExplorationResult,
+        cancel_flag: Option<&AtomicBool>,
+    ) {
+        if unit.trace_mode == self.environment.trace_mode {
+            self.solve_shard_unit_in_trace_mode(unit, exploration_result, cancel_flag);
+        } else {
+            let environment = SimulationEnvironment {
+                trace_mode: unit.trace_mode,
+                ..self.environment.clone()
+            };
+            self.with_environment(&environment)
+                .solve_shard_unit_in_trace_mode(unit, exploration_result, cancel_flag);
+        }
+    }
+
+    fn solve_shard_unit_in_trace_mode(
+        &self,
+        unit: &ShardUnit,
+        exploration_result: &mut ExplorationResult,
+        cancel_flag: Option<&AtomicBool>,
+    ) {
+        let Some(coord) = PuyoCoord::index_to_coord(unit.start_index) else {
+            return;
+        };
+        let state = self.new_solution_state(unit.start_index);
+        match unit.next_candidate_index {
+            None => {
+                self.add_and_evaluate(&state, coord, exploration_result);
+            }
+            Some(i) => {
+                let Some(st) = self.add_coord(&state, coord) else {
+                    return;
+                };
+                if let Some(next_coord) = st.get_next_candidate_coords().get(i as usize) {
+                    self.advance_trace(&st, *next_coord, exploration_result, cancel_flag);
+                }
+            }
+        }
+    }
+
+    /// 探索対象に trace_modes が指定されていれば、トレースモードごとに環境を差し替えた探索器で f を呼ぶ。
+    /// 指定されていなければ自身で1回だけ呼ぶ。
+    fn for_each_trace_mode<F>(&self, mut f: F)
+    where
+        F: FnMut(&SolutionExplorer),
+    {
+        match &self.exploration_target.trace_modes {
+            Some(trace_modes) => {
+                for trace_mode in trace_modes {
+                    let environment = SimulationEnvironment {
+                        trace_mode: *trace_mode,
+                        ..self.environment.clone()
+                    };
+                    f(&self.with_environment(&environment));
+                }
+            }
+            None => f(self),
+        }
+    }
+
+    /// 環境だけを差し替えた探索器を作る。盤面など環境に依存しない準備済みのデータはそのまま使い回す。
+    fn with_environment<'b>(
+        &'b self,
+        environment: &'b SimulationEnvironment,
+    ) -> SolutionExplorer<'b> {
+        SolutionExplorer {
+            exploration_target: self.exploration_target,
+            environment,
+            boost_area: self.boost_area,
+            field: self.field,
+            boards: self.boards.clone(),
+            id_board: self.id_board.clone(),
+            next_samples: Cow::Borrowed(&self.next_samples),
+            recovery_stat: self.recovery_stat,
+            required_field_bits: self.required_field_bits,
+            forbidden_field_bits: self.forbidden_field_bits,
+        }
+    }
+
+    /// 指定したなぞりを1つだけシミュレーションして、連鎖情報や追跡結果などを全て付与した解を返す。
+    /// なぞりが正しくなければ理由を表すエラーを返す。
+    pub fn simulate_trace(&self, trace_coords: &[PuyoCoord]) -> Result<SolutionResult, String> {
+        self.validate_trace(trace_coords)?;
+        let drawing_order = if self.exploration_target.drawable_path_only {
+            match find_drawing_order(trace_coords) {
+                Some(order) => Some(order),
+                None => return Err("trace cannot be drawn as a single path".to_string()),
+            }
+        } else {
+            None
+        };
+
+        let (mut solution, _) = self.calc_solution_result(trace_coords.to_vec());
+        solution.drawing_order = drawing_order;
+        self.complete_solution(&mut solution);
+        Ok(solution)
+    }
+
+    /// なぞりが正しいか検証する。盤面に対して正しいなぞりであることに加えて、
+    /// なぞり数の下限と、探索対象の必須の座標と禁止の座標の条件を満たすか確かめる。
+    fn validate_trace(&self, trace_coords: &[PuyoCoord]) -> Result<(), String> {
+        validate_trace(&self.field, trace_coords, self.get_actual_max_trace_num())?;
+        if trace_coords.len() < self.environment.min_trace_num as usize {
+            return Err(format!(
+                "trace must include at least {} puyos",
+                self.environment.min_trace_num
+            ));
+        }
+        if let Some(coord) = trace_coords
+            .iter()
+            .find(|coord| self.exploration_target.forbidden_coords.contains(coord))
+        {
+            return Err(format!("({}, {}) is forbidden", coord.x, coord.y));
+        }
+        if let Some(coord) = self
+            .exploration_target
+            .required_coords
+            .iter()
+            .filter(|coord| !trace_coords.contains(coord))
+            .min_by_key(|coord| coord.index())
+        {
+            return Err(format!("({}, {}) is required", coord.x, coord.y));
+        }
+        Ok(())
+    }
+
+    fn new_solution_state(&self, forbidden_indexes_before: u8) -> SolutionState {
+        let mut state = SolutionState::new(forbidden_indexes_before);
+        state.forbid(self.forbidden_field_bits);
+        state
+    }
+
+    fn new_exploration_result(&self) -> ExplorationResult {
+        ExplorationResult {
+            candidates_num: 0,
+            optimal_solutions: Vec::new(),
+            constraint_passed_nums: vec![0; self.exploration_target.constraints.len()],
+            is_partial: false,
+        }
+    }
+
+    /// なぞりに座標を加えて評価し、その先を深さ優先で探索する。
+    /// cancel_flag が立っていれば、その時点で探索をやめて戻る。
     fn advance_trace(
         &self,
         state: &SolutionState,
         coord: PuyoCoord,
         exploration_result: &mut ExplorationResult,
+        cancel_flag: Option<&AtomicBool>,
     ) {
-        if let Some(p) = self.field[coord.y as usize][coord.x as usize] {
-            if !is_traceable_type(p.puyo_type) {
-                return;
-            }
-            if !state.check_if_addable_coord(&coord, self.get_actual_max_trace_num()) {
-                return;
+        if is_cancelled(cancel_flag) {
+            return;
+        }
+        if let Some(st) = self.add_and_evaluate(state, coord, exploration_result) {
+            for next_coord in st.get_next_candidate_coords() {
+                self.advance_trace(&st, *next_coord, exploration_result, cancel_flag);
             }
+        }
+    }
 
-            let mut st = state.clone();
-            st.add_trace_coord(coord);
+    /// なぞりに座標を加えて、候補になるなら評価する。
+    /// 座標を加えられなかったり、この先を探索する必要が無ければ None を返す。
+    fn add_and_evaluate(
+        &self,
+        state: &SolutionState,
+        coord: PuyoCoord,
+        exploration_result: &mut ExplorationResult,
+    ) -> Option<SolutionState> {
+        let st = self.add_coord(state, coord)?;
 
-            let solution_result = self.calc_solution_result(st.get_trace_coords().clone());
+        // 一筆書きでなぞれない集合は候補にしないが、座標を増やせばなぞれるようになることがあるので探索は続ける
+        if let Some(drawing_order) = self.check_candidate(&st) {
+            let (mut solution_result, constraint_values) =
+                self.calc_solution_result(st.get_trace_coords().clone());
+            solution_result.drawing_order = drawing_order;
 
-            self.update_exploration_result(solution_result, exploration_result);
+            self.update_exploration_result(solution_result, &constraint_values, exploration_result);
+        }
 
-            for next_coord in st.get_next_candidate_coords() {
-                self.advance_trace(&st, *next_coord, exploration_result);
+        Some(st)
+    }
+
+    /// なぞりに座標を加えた状態を返す。
+    /// 座標を加えられなかったり、この先を探索する必要が無ければ None を返す。
+    fn add_coord(&self, state: &SolutionState, coord: PuyoCoord) -> Option<SolutionState> {
+        let p = self.field[coord.y as usize][coord.x as usize]?;
+        if !is_traceable_type(p.puyo_type) {
+            return None;
+        }
+        if !state.check_if_addable_coord(&coord, self.get_actual_max_trace_num()) {
+            return None;
+        }
+
+        let mut st = state.clone();
+        st.add_trace_coord(coord);
+
+        // 必須の座標をなぞれる見込みが無ければ、この先は探索しない
+        if !st.can_include_all(self.required_field_bits, self.get_actual_max_trace_num()) {
+            return None;
+        }
+
+        Some(st)
+    }
+
+    /// なぞり数と必須の座標の条件を満たし、一筆書きに限るときは一筆書きでなぞれて、評価する候補になるかどうかを調べる。
+    /// 候補になるなら、一筆書きに限るときはなぞる順序を、そうでなければ None を Some に包んで返す。
+    fn check_candidate(&self, state: &SolutionState) -> Option<Option<Vec<PuyoCoord>>> {
+        if state.get_trace_coords().len() < self.environment.min_trace_num as usize
+            || !state.includes_all(self.required_field_bits)
+        {
+            return None;
+        }
+        if !self.exploration_target.drawable_path_only {
+            return Some(None);
+        }
+        find_drawing_order(state.get_trace_coords()).map(Some)
+    }
+
+    /// 評価せずに探索だけして候補数を数える。候補になるかどうかは add_and_evaluate と同じく check_candidate で決める。
+    /// cancel_flag が立っていれば、その時点で数えるのをやめて途中までの数を返す。
+    fn count_candidates(
+        &self,
+        state: &SolutionState,
+        coord: PuyoCoord,
+        cancel_flag: Option<&AtomicBool>,
+    ) -> u64 {
+        if is_cancelled(cancel_flag) {
+            return 0;
+        }
+        match self.add_coord(state, coord) {
+            Some(st) => {
+                let count = if self.check_candidate(&st).is_some() {
+                    1
+                } else {
+                    0
+                };
+                count
+                    + st.get_next_candidate_coords()
+                        .iter()
+                        .map(|next_coord| self.count_candidates(&st, *next_coord, cancel_flag))
+                        .sum::<u64>()
             }
+            None => 0,
         }
     }
 
     fn get_actual_max_trace_num(&self) -> u32 {
-        if self.environment.is_chance_mode {
-            5
-        } else {
-            self.environment.max_trace_num
-        }
+        self.environment.get_actual_max_trace_num()
     }
 
-    fn calc_solution_result(&self, trace_coords: Vec<PuyoCoord>) -> SolutionResult {
-        let chains = self.do_chains_bb(&trace_coords);
+    /// なぞりを評価して解を計算し、探索対象の条件ごとの指標の値と合わせて返す。
+    /// 不明なネクストぷよを補う場合は、value と objective_values と条件の指標を、補った盤面それぞれでの値の期待値
+    /// (全消しのような真偽の指標では確率) とする。
+    fn calc_solution_result(&self, trace_coords: Vec<PuyoCoord>) -> (SolutionResult, Vec<f64>) {
+        let chains = self.do_chains_bb(&self.boards, &trace_coords);
         let popped_chance_num = chains.iter().map(|c| c.popped_chance_num).sum();
         let popped_heart_num = sum_attr_popped_count(&chains, PuyoAttr::Heart);
         let popped_prism_num = sum_attr_popped_count(&chains, PuyoAttr::Prism);
         let popped_ojama_num = sum_attr_popped_count(&chains, PuyoAttr::Ojama);
         let popped_kata_num = sum_attr_popped_count(&chains, PuyoAttr::Kata);
         let is_all_cleared = chains.iter().any(|c| c.is_all_cleared);
+        let recovery = calc_recovery(self.environment, self.recovery_stat, &chains);
+        let chain_count = count_chains(&chains);
+        let max_simultaneous_num = max_simultaneous_num(&chains);
+        let total_popped_count = sum_simultaneous_num(&chains);
+        let trace_num = trace_coords.len();
+
+        let sample_chains: Vec<(Vec<Chain>, f64)> = self
+            .next_samples
+            .iter()
+            .map(|(boards, probability)| (self.do_chains_bb(boards, &trace_coords), *probability))
+            .collect();
+
+        // 不明なネクストぷよを補う場合は、補った盤面での値の期待値を value とする
+        let (value, value_statistics) = if sample_chains.is_empty() {
+            (self.calc_value(&chains, trace_num), None)
+        } else {
+            let mut samples: Vec<(f64, f64)> = sample_chains
+                .iter()
+                .map(|(sample, probability)| (self.calc_value(sample, trace_num), *probability))
+                .collect();
+            let statistics = calc_value_statistics(&mut samples);
+            (statistics.mean, Some(statistics))
+        };
+
+        let objective_values =
+            self.exploration_target
+                .pareto_objectives
+                .as_ref()
+                .map(|objectives| {
+                    objectives
+                        .iter()
+                        .map(|objective| {
+                            calc_expectation(&chains, &sample_chains, |c| {
+                                self.calc_objective_value(*objective, c, trace_num)
+                            })
+                        })
+                        .collect()
+                });
+
+        let constraint_values = self
+            .exploration_target
+            .constraints
+            .iter()
+            .map(|constraint| {
+                calc_expectation(&chains, &sample_chains, |c| {
+                    self.calc_metric(constraint.metric, constraint.attr, c, trace_num)
+                })
+            })
+            .collect();
+
+        let solution = SolutionResult {
+            trace_coords,
+            chains,
+            value,
+            popped_chance_num,
+            popped_heart_num,
+            popped_prism_num,
+            popped_ojama_num,
+            popped_kata_num,
+            is_all_cleared,
+            recovery,
+            chain_count,
+            max_simultaneous_num,
+            total_popped_count,
+            puyo_tracking: None,
+            board_after_chains: None,
+            value_statistics,
+            team_damage: None,
+            objective_values,
+            drawing_order: None,
+            trace_mode: self.environment.trace_mode,
+        };
+
+        (solution, constraint_values)
+    }
+
+    /// パレート最適解を求めるときの目的の値を連鎖情報から計算する。
+    fn calc_objective_value(
+        &self,
+        objective: ParetoObjective,
+        chains: &Vec<Chain>,
+        trace_num: usize,
+    ) -> f64 {
+        match objective {
+            ParetoObjective::Value => self.calc_value(chains, trace_num),
+            ParetoObjective::Damage => {
+                self.calc_category_value(ExplorationCategory::Damage, chains)
+            }
+            ParetoObjective::SkillPuyoCount => {
+                self.calc_category_value(ExplorationCategory::SkillPuyoCount, chains)
+            }
+            ParetoObjective::PuyotsukaiCount => sum_puyo_tsukai_count(chains) as f64,
+            ParetoObjective::Recovery => {
+                calc_recovery(self.environment, self.recovery_stat, chains)
+            }
+            ParetoObjective::ChainCount => count_chains(chains) as f64,
+            ParetoObjective::ChancePop => {
+                chains.iter().map(|c| c.popped_chance_num).sum::<u32>() as f64
+            }
+            ParetoObjective::PrismPop => sum_attr_popped_count(chains, PuyoAttr::Prism) as f64,
+            ParetoObjective::HeartPop => sum_attr_popped_count(chains, PuyoAttr::Heart) as f64,
+            ParetoObjective::OjamaPop => {
+                (sum_attr_popped_count(chains, PuyoAttr::Ojama)
+                    + sum_attr_popped_count(chains, PuyoAttr::Kata)) as f64
+            }
+            ParetoObjective::AllClear => {
+                if chains.iter().any(|c| c.is_all_cleared) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            ParetoObjective::TraceNum => trace_num as f64,
+        }
+    }
+
+    /// 探索カテゴリーに応じた値 (採点式が指定されていればその値) を連鎖情報から計算する。
+    fn calc_value(&self, chains: &Vec<Chain>, trace_num: usize) -> f64 {
+        match &self.exploration_target.scoring {
+            Some(scoring) => scoring
+                .calc_score(|term| self.calc_metric(term.metric, term.attr, chains, trace_num)),
+            None => self.calc_category_value(self.exploration_target.category, chains),
+        }
+    }
+
+    /// 採点式や条件に使う指標の値を連鎖情報から計算する。
+    fn calc_metric(
+        &self,
+        metric: ScoringMetric,
+        attr: Option<PuyoAttr>,
+        chains: &Vec<Chain>,
+        trace_num: usize,
+    ) -> f64 {
+        match metric {
+            ScoringMetric::CategoryValue => {
+                self.calc_category_value(self.exploration_target.category, chains)
+            }
+            ScoringMetric::Damage => self.calc_category_value(ExplorationCategory::Damage, chains),
+            ScoringMetric::SkillPuyoCount => {
+                self.calc_category_value(ExplorationCategory::SkillPuyoCount, chains)
+            }
+            ScoringMetric::PuyotsukaiCount => sum_puyo_tsukai_count(chains) as f64,
+            ScoringMetric::Recovery => calc_recovery(self.environment, self.recovery_stat, chains),
+            ScoringMetric::ChainCount => count_chains(chains) as f64,
+            ScoringMetric::MaxSimultaneousNum => max_simultaneous_num(chains) as f64,
+            ScoringMetric::TotalPoppedCount => sum_simultaneous_num(chains) as f64,
+            ScoringMetric::ChancePop => {
+                chains.iter().map(|c| c.popped_chance_num).sum::<u32>() as f64
+            }
+            ScoringMetric::PrismPop => sum_attr_popped_count(chains, PuyoAttr::Prism) as f64,
+            ScoringMetric::HeartPop => sum_attr_popped_count(chains, PuyoAttr::Heart) as f64,
+            ScoringMetric::OjamaPop => {
+                (sum_attr_popped_count(chains, PuyoAttr::Ojama)
+                    + sum_attr_popped_count(chains, PuyoAttr::Kata)) as f64
+            }
+            ScoringMetric::AllClear => {
+                if chains.iter().any(|c| c.is_all_cleared) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            ScoringMetric::TraceNum => trace_num as f64,
+            ScoringMetric::AttrPoppedCount => match attr {
+                Some(attr) => sum_attr_popped_count(chains, attr) as f64,
+                None => 0.0,
+            },
+            ScoringMetric::AttrDamage => match attr {
+                Some(attr) => sum_pure_attr_damage(chains, attr),
+                None => 0.0,
+            },
+        }
+    }
 
+    /// 指定した探索カテゴリーの値を連鎖情報から計算する。
+    fn calc_category_value(&self, category: ExplorationCategory, chains: &Vec<Chain>) -> f64 {
         let value: f64;
 
-        match self.exploration_target.category {
+        match category {
             ExplorationCategory::Damage => {
                 if let Some(main_attr) = self.exploration_target.main_attr {
-                    let main_value = sum_colored_attr_damage(&chains, main_attr);
+                    let main_value = sum_colored_attr_damage(chains, main_attr);
                     let main_sub_ratio = match self.exploration_target.main_sub_ratio {
                         Some(ratio) => ratio,
                         None => 0.0,
                     };
                     let sub_value = match self.exploration_target.sub_attr {
                         Some(sub_attr) => {
-                            sum_colored_attr_damage(&chains, sub_attr) * main_sub_ratio
+                            sum_colored_attr_damage(chains, sub_attr) * main_sub_ratio
                         }
                         None => 0.0,
                     };
@@ -508,19 +1302,19 @@ impl<'a> SolutionExplorer<'a> {
                 }
                 // ワイルド
                 else {
-                    value = sum_wild_damage(&chains);
+                    value = sum_wild_damage(chains);
                 }
             }
             ExplorationCategory::SkillPuyoCount => {
                 if let Some(main_attr) = self.exploration_target.main_attr {
-                    let main_value = sum_attr_popped_count(&chains, main_attr);
+                    let main_value = sum_attr_popped_count(chains, main_attr);
                     let mut bonus_value: u32 = 0;
                     if let Some(counting_bonus) = &self.exploration_target.counting_bonus {
                         if counting_bonus.bonus_type == CountingBonusType::Step {
                             let height = counting_bonus
                                 .target_attrs
                                 .iter()
-                                .fold(0, |acc, attr| acc + sum_attr_popped_count(&chains, *attr));
+                                .fold(0, |acc, attr| acc + sum_attr_popped_count(chains, *attr));
                             let mut steps = height / counting_bonus.step_height as u32;
                             if !counting_bonus.repeat {
                                 steps = cmp::min(1, steps);
@@ -534,79 +1328,403 @@ impl<'a> SolutionExplorer<'a> {
                 }
             }
             ExplorationCategory::PuyotsukaiCount => {
-                value = sum_puyo_tsukai_count(&chains) as f64;
+                value = sum_puyo_tsukai_count(chains) as f64;
             }
-        }
-
-        return SolutionResult {
-            trace_coords,
-            chains,
-            value,
-            popped_chance_num,
-            popped_heart_num,
-            popped_prism_num,
-            popped_ojama_num,
-            popped_kata_num,
-            is_all_cleared,
-        };
+            ExplorationCategory::EffectiveDamage => {
+                // 敵が無い場合は ExplorationTarget::validate で弾く
+                if let Some(enemy) = &self.exploration_target.enemy {
+                    let damage = if let Some(team) = &self.exploration_target.team {
+                        calc_team_damage_against(team, self.environment, chains, enemy).total_damage
+                    } else if let Some(main_attr) = self.exploration_target.main_attr {
+                        let main_sub_ratio = self.exploration_target.main_sub_ratio.unwrap_or(0.0);
+                        let sub_damage = match self.exploration_target.sub_attr {
+                            Some(sub_attr) => {
+                                enemy.calc_attr_damage(chains, sub_attr) * main_sub_ratio
+                            }
+                            None => 0.0,
+                        };
+                        enemy.calc_attr_damage(chains, main_attr) + sub_damage
+                    }
+                    // ワイルド
+                    else {
+                        enemy.calc_wild_damage(chains)
+                    };
+                    value = enemy.apply_defense(damage);
+                } else {
+                    value = 0 as f64;
+                }
+            }
+            ExplorationCategory::Recovery => {
+                value = calc_recovery(self.environment, self.recovery_stat, chains);
+            }
+            ExplorationCategory::ChainCount => {
+                value = count_chains(chains) as f64;
+            }
+            ExplorationCategory::MaxSimultaneousNum => {
+                value = max_simultaneous_num(chains) as f64;
+            }
+            ExplorationCategory::TotalPoppedCount => {
+                value = sum_simultaneous_num(chains) as f64;
+            }
+        }
+
+        value
     }
 
     /// Bitboardを使ったシミュレーターで連鎖させる。
-    fn do_chains_bb(&self, trace_coords: &Vec<PuyoCoord>) -> Vec<Chain> {
+    fn do_chains_bb(&self, boards: &BitBoards, trace_coords: &Vec<PuyoCoord>) -> Vec<Chain> {
         let sim = SimulatorBB {
             environment: self.environment,
             boost_area: self.boost_area,
         };
         return sim.do_chains(
-            &mut self.boards.clone(),
+            &mut boards.clone(),
             SimulatorBB::coords_to_board(trace_coords.iter()),
         );
     }
 
+    /// 最適解それぞれに追跡結果などを付与する。
+    fn complete_optimal_solutions(&self, exploration_result: &mut ExplorationResult) {
+        for solution in exploration_result.optimal_solutions.iter_mut() {
+            if solution.trace_mode == self.environment.trace_mode {
+                self.complete_solution(solution);
+            } else {
+                let environment = SimulationEnvironment {
+                    trace_mode: solution.trace_mode,
+                    ..self.environment.clone()
+                };
+                self.with_environment(&environment)
+                    .complete_solution(solution);
+            }
+        }
+    }
+
+    /// 解について、ぷよのIDを追跡しながら連鎖させ直して、
+    /// 追跡結果と連鎖後の盤面 (とデッキが指定されていればそのダメージ) を付与する。
+    /// 不明なネクストぷよを補う場合は、ダメージは補った盤面それぞれでのダメージの期待値とする。
+    fn complete_solution(&self, solution: &mut SolutionResult) {
+        let sim = SimulatorBB {
+            environment: self.environment,
+            boost_area: self.boost_area,
+        };
+        let mut boards = self.boards.clone();
+        let mut id_board = self.id_board.clone();
+        let (_, tracking) = sim.do_chains_with_tracking(
+            &mut boards,
+            &mut id_board,
+            SimulatorBB::coords_to_board(solution.trace_coords.iter()),
+        );
+        solution.puyo_tracking = Some(tracking);
+        solution.board_after_chains = Some(SimulatorBB::decode_field_and_next(&boards, &id_board));
+        if let Some(team) = &self.exploration_target.team {
+            solution.team_damage = Some(if self.next_samples.is_empty() {
+                calc_team_damage(team, self.environment, &solution.chains)
+            } else {
+                let samples: Vec<(TeamDamage, f64)> = self
+                    .next_samples
+                    .iter()
+                    .map(|(boards, probability)| {
+                        let chains = self.do_chains_bb(boards, &solution.trace_coords);
+                        (
+                            calc_team_damage(team, self.environment, &chains),
+                            *probability,
+                        )
+                    })
+                    .collect();
+                average_team_damages(&samples)
+            });
+        }
+    }
+
     fn update_exploration_result(
         &self,
         solution_result: SolutionResult,
+        constraint_values: &[f64],
         exploration_result: &mut ExplorationResult,
     ) {
         exploration_result.candidates_num += 1;
 
-        let max = self.exploration_target.optimal_solution_count as usize;
-
-        if max == 0 {
+        if !self.satisfies_constraints(constraint_values, exploration_result) {
             return;
         }
 
-        let len = exploration_result.optimal_solutions.len();
-        let preference_priorities = &self.exploration_target.preference_priorities;
+        insert_optimal_solution(self.exploration_target, solution_result, exploration_result);
+    }
 
-        let mut i = len;
-        for s in exploration_result.optimal_solutions.iter().rev() {
-            let better_s = better_solution(preference_priorities, s, &solution_result);
-            if better_s as *const _ == s as *const _ {
-                break;
+    /// 条件ごとの指標の値が全ての条件を満たすかどうかを調べ、条件ごとに満たした候補数を数える。
+    fn satisfies_constraints(
+        &self,
+        constraint_values: &[f64],
+        exploration_result: &mut ExplorationResult,
+    ) -> bool {
+        let mut satisfies_all = true;
+        for ((constraint, value), passed_num) in self
+            .exploration_target
+            .constraints
+            .iter()
+            .zip(constraint_values.iter())
+            .zip(exploration_result.constraint_passed_nums.iter_mut())
+        {
+            if constraint.is_satisfied(*value) {
+                *passed_num += 1;
+            } else {
+                satisfies_all = false;
             }
-            i -= 1;
         }
-        if i == max {
-            return;
+        satisfies_all
+    }
+}
+
+/// 不明なネクストぷよを補った盤面それぞれの連鎖情報があれば、そこから計算した値の確率で重み付けした期待値を返す。
+/// 無ければ盤面そのままの連鎖情報から計算した値を返す。
+fn calc_expectation(
+    chains: &Vec<Chain>,
+    sample_chains: &[(Vec<Chain>, f64)],
+    f: impl Fn(&Vec<Chain>) -> f64,
+) -> f64 {
+    if sample_chains.is_empty() {
+        return f(chains);
+    }
+    let total: f64 = sample_chains
+        .iter()
+        .map(|(_, probability)| probability)
+        .sum();
+    sample_chains
+        .iter()
+        .map(|(sample, probability)| f(sample) * probability)
+        .sum::<f64>()
+        / total
+}
+
+/// 不明なネクストぷよを補った盤面それぞれでのデッキのダメージを、確率で重み付けして平均する。
+fn average_team_damages(samples: &[(TeamDamage, f64)]) -> TeamDamage {
+    let total: f64 = samples.iter().map(|(_, probability)| probability).sum();
+    let mut average = samples[0].0.clone();
+    for (i, card_damage) in average.card_damages.iter_mut().enumerate() {
+        card_damage.main_damage = samples
+            .iter()
+            .map(|(damage, probability)| damage.card_damages[i].main_damage * probability)
+            .sum::<f64>()
+            / total;
+        card_damage.sub_damage = samples
+            .iter()
+            .map(|(damage, probability)| damage.card_damages[i].sub_damage * probability)
+            .sum::<f64>()
+            / total;
+    }
+    average.total_damage = samples
+        .iter()
+        .map(|(damage, probability)| damage.total_damage * probability)
+        .sum::<f64>()
+        / total;
+    average
+}
+
+/// 打ち切りを求めるフラグが立っているかどうか。フラグが無ければ常に false。
+fn is_cancelled(cancel_flag: Option<&AtomicBool>) -> bool {
+    cancel_flag.is_some_and(|flag| flag.load(Ordering::Relaxed))
+}
+
+/// 部分的な探索結果を、探索した順に並べたリストの順に併合する。
+/// 同じ部分結果のリストからは常に同じ結果になる。
+/// 部分結果の形が探索対象と合わなければ (条件ごとの候補数や目的の値の数が違えば) エラーを返す。
+pub fn merge_exploration_results(
+    exploration_target: &ExplorationTarget,
+    parts: Vec<ExplorationResult>,
+) -> Result<ExplorationResult, String> {
+    for part in parts.iter() {
+        validate_exploration_result_part(exploration_target, part)?;
+    }
+    let mut result = ExplorationResult {
+        candidates_num: 0,
+        optimal_solutions: Vec::new(),
+        constraint_passed_nums: vec![0; exploration_target.constraints.len()],
+        is_partial: false,
+    };
+    for part in parts {
+        merge_exploration_result(exploration_target, &mut result, part);
+    }
+    Ok(result)
+}
+
+/// 併合する部分結果が、同じ探索対象を解いた結果の形になっているか検証する。
+fn validate_exploration_result_part(
+    exploration_target: &ExplorationTarget,
+    part: &ExplorationResult,
+) -> Result<(), String> {
+    let constraint_num = exploration_target.constraints.len();
+    if part.constraint_passed_nums.len() != constraint_num {
+        return Err(format!(
+            "constraint_passed_nums of exploration results must have {} elements",
+            constraint_num
+        ));
+    }
+    if let Some(objectives) = &exploration_target.pareto_objectives {
+        if !part.optimal_solutions.iter().all(|s| {
+            s.objective_values
+                .as_ref()
+                .is_some_and(|values| values.len() == objectives.len())
+        }) {
+            return Err(format!(
+                "objective_values of optimal solutions must have {} elements",
+                objectives.len()
+            ));
         }
-        exploration_result
-            .optimal_solutions
-            .insert(i, solution_result);
-        if len == max {
-            exploration_result.optimal_solutions.pop();
+    }
+    Ok(())
+}
+
+/// 部分的な探索結果 part を result に併合する。part の候補は result の候補より後に探索したものとして扱う。
+fn merge_exploration_result(
+    exploration_target: &ExplorationTarget,
+    result: &mut ExplorationResult,
+    part: ExplorationResult,
+) {
+    result.candidates_num += part.candidates_num;
+    result.is_partial |= part.is_partial;
+    for (passed_num, part_passed_num) in result
+        .constraint_passed_nums
+        .iter_mut()
+        .zip(part.constraint_passed_nums)
+    {
+        *passed_num += part_passed_num;
+    }
+    for solution_result in part.optimal_solutions {
+        insert_optimal_solution(exploration_target, solution_result, result);
+    }
+}
+
+/// 条件を満たした解を、最適解リストの好みの順になる位置に挿入する (パレート最適解を求めるときは集合を更新する)。
+fn insert_optimal_solution(
+    exploration_target: &ExplorationTarget,
+    solution_result: SolutionResult,
+    exploration_result: &mut ExplorationResult,
+) {
+    if let Some(objectives) = &exploration_target.pareto_objectives {
+        update_pareto_front(
+            exploration_target,
+            objectives,
+            solution_result,
+            exploration_result,
+        );
+        return;
+    }
+
+    let max = exploration_target.optimal_solution_count as usize;
+
+    if max == 0 {
+        return;
+    }
+
+    let len = exploration_result.optimal_solutions.len();
+    let preference_priorities = &exploration_target.preference_priorities;
+    let preference_bucket_widths = &exploration_target.preference_bucket_widths;
+
+    let mut i = len;
+    for s in exploration_result.optimal_solutions.iter().rev() {
+        let better_s = better_solution(
+            preference_priorities,
+            preference_bucket_widths,
+            s,
+            &solution_result,
+        );
+        if better_s as *const _ == s as *const _ {
+            break;
+        }
+        i -= 1;
+    }
+    if i == max {
+        return;
+    }
+    exploration_result
+        .optimal_solutions
+        .insert(i, solution_result);
+    if len == max {
+        exploration_result.optimal_solutions.pop();
+    }
+}
+
+/// 最適解リストをパレート最適解の集合として更新する。
+/// 新しい解が既存のどれかに支配されるか、目的の値が全て等しい既存の解の方が好ましければ捨てる。
+/// そうでなければ新しい解に支配される解を取り除いてから、preference_priorities の順になる位置に挿入する。
+/// 解は全て目的の値を持つこと (外部から受け取った部分結果は validate_exploration_result_part で検証する)。
+fn update_pareto_front(
+    exploration_target: &ExplorationTarget,
+    objectives: &[ParetoObjective],
+    solution_result: SolutionResult,
+    exploration_result: &mut ExplorationResult,
+) {
+    let preference_priorities = &exploration_target.preference_priorities;
+    let preference_bucket_widths = &exploration_target.preference_bucket_widths;
+    let front = &mut exploration_result.optimal_solutions;
+    let values = solution_result
+        .objective_values
+        .as_ref()
+        .expect("objective_values must be set when pareto_objectives is set");
+
+    let mut i = 0;
+    while i < front.len() {
+        let s = &front[i];
+        let s_values = s
+            .objective_values
+            .as_ref()
+            .expect("objective_values must be set when pareto_objectives is set");
+        let ordering = compare_objective_values(objectives, s_values, values);
+        match ordering {
+            Some(cmp::Ordering::Greater) => return,
+            Some(cmp::Ordering::Equal) => {
+                if std::ptr::eq(
+                    better_solution(
+                        preference_priorities,
+                        preference_bucket_widths,
+                        s,
+                        &solution_result,
+                    ),
+                    s,
+                ) {
+                    return;
+                }
+                front.remove(i);
+            }
+            Some(cmp::Ordering::Less) => {
+                front.remove(i);
+            }
+            None => i += 1,
         }
     }
+
+    let i = front
+        .iter()
+        .position(|s| {
+            !std::ptr::eq(
+                better_solution(
+                    preference_priorities,
+                    preference_bucket_widths,
+                    s,
+                    &solution_result,
+                ),
+                s,
+            )
+        })
+        .unwrap_or(front.len());
+    front.insert(i, solution_result);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
+        board_transform::{BoardTransform, BoardTransformKind},
         chain::{AttributeChain, Chain},
-        puyo::Puyo,
+        constraint::SolutionConstraint,
+        enemy::Enemy,
+        next_expectation::{NextExpectation, NextExpectationMethod},
+        puyo::{FieldAndNext, Puyo},
         puyo_attr::PuyoAttr,
+        puyo_tracking::{PuyoTracking, TrackedPuyo},
         puyo_type::PuyoType,
+        scoring::{Scoring, ScoringTerm},
+        team::{Card, DamageMode, Team},
         trace_mode::TraceMode,
     };
     use std::collections::HashSet;
@@ -621,8 +1739,50 @@ mod tests {
         popped_ojama_num: 0,
         popped_kata_num: 0,
         is_all_cleared: false,
+        recovery: 0.0,
+        chain_count: 0,
+        max_simultaneous_num: 0,
+        total_popped_count: 0,
+        puyo_tracking: None,
+        board_after_chains: None,
+        value_statistics: None,
+        team_damage: None,
+        objective_values: None,
+        drawing_order: None,
+        trace_mode: TraceMode::Normal,
     };
 
+    fn environment(max_trace_num: u32) -> SimulationEnvironment {
+        SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        }
+    }
+
+    fn board(types: [[Option<PuyoType>; 8]; 6], next: [Option<PuyoType>; 8]) -> FieldAndNext {
+        let mut id_counter = 0;
+        let mut to_puyo = |option: Option<PuyoType>| {
+            option.map(|puyo_type| {
+                id_counter += 1;
+                Puyo {
+                    id: id_counter,
+                    puyo_type,
+                }
+            })
+        };
+        let field: Field = types.map(|row| row.map(&mut to_puyo));
+        let next_puyos = next.map(&mut to_puyo);
+        FieldAndNext { field, next_puyos }
+    }
+
     #[test]
     fn test_better_solution_by_bigger_value_s1() {
         let s1 = SolutionResult { value: 2.0, ..S };
@@ -1547,6 +2707,350 @@ mod tests {
         assert!(better_solution_by_less_ojama_pop(&s1, &s2).is_none());
     }
 
+    #[test]
+    fn test_better_solution_by_more_recovery() {
+        let s1 = SolutionResult { recovery: 2.0, ..S };
+        let s2 = SolutionResult { recovery: 1.0, ..S };
+        assert_eq!(better_solution_by_more_recovery(&s1, &s2).unwrap(), &s1);
+        assert_eq!(better_solution_by_more_recovery(&s2, &s1).unwrap(), &s1);
+        assert!(better_solution_by_more_recovery(&s1, &s1).is_none());
+    }
+
+    #[test]
+    fn test_better_solution_by_less_recovery() {
+        let s1 = SolutionResult { recovery: 2.0, ..S };
+        let s2 = SolutionResult { recovery: 1.0, ..S };
+        assert_eq!(better_solution_by_less_recovery(&s1, &s2).unwrap(), &s2);
+        assert_eq!(better_solution_by_less_recovery(&s2, &s1).unwrap(), &s2);
+        assert!(better_solution_by_less_recovery(&s1, &s1).is_none());
+    }
+
+    #[test]
+    fn test_better_solution_by_chain_scale() {
+        let s1 = SolutionResult {
+            chain_count: 3,
+            max_simultaneous_num: 5,
+            total_popped_count: 15,
+            ..S
+        };
+        let s2 = SolutionResult {
+            chain_count: 2,
+            max_simultaneous_num: 8,
+            total_popped_count: 15,
+            ..S
+        };
+        assert_eq!(better_solution_by_more_chain_count(&s1, &s2).unwrap(), &s1);
+        assert_eq!(better_solution_by_less_chain_count(&s1, &s2).unwrap(), &s2);
+        assert_eq!(
+            better_solution_by_more_max_simultaneous_num(&s1, &s2).unwrap(),
+            &s2
+        );
+        assert_eq!(
+            better_solution_by_less_max_simultaneous_num(&s1, &s2).unwrap(),
+            &s1
+        );
+        assert!(better_solution_by_more_total_popped_count(&s1, &s2).is_none());
+        assert!(better_solution_by_less_total_popped_count(&s1, &s2).is_none());
+    }
+
+    #[test]
+    fn test_better_solution_with_bucket_width() {
+        // Arrange
+        let preference_priorities =
+            Vec::from([PreferenceKind::BiggerValue, PreferenceKind::AllClear]);
+        let s1 = SolutionResult {
+            value: 100.0001,
+            ..S
+        };
+        let s2 = SolutionResult {
+            value: 100.0,
+            is_all_cleared: true,
+            ..S
+        };
+        let bucket_widths = |bucket_width: BucketWidth| {
+            HashMap::from([(PreferenceKind::BiggerValue, bucket_width)])
+        };
+
+        // Act & Assert
+        // 区間の幅が無ければわずかな差でも値が大きい方
+        assert_eq!(
+            better_solution(&preference_priorities, &HashMap::new(), &s1, &s2),
+            &s1
+        );
+        // 同じ区間に入るなら次の好み (全消し) で決まる
+        let absolute = bucket_widths(BucketWidth {
+            absolute: 0.001,
+            relative: 0.0,
+        });
+        assert_eq!(
+            better_solution(&preference_priorities, &absolute, &s1, &s2),
+            &s2
+        );
+        assert_eq!(
+            better_solution(&preference_priorities, &absolute, &s2, &s1),
+            &s2
+        );
+        // 相対誤差では隣の区間との比が 1.05 の区間に区切る (100 と 102 は同じ区間で、104 から次の区間)
+        let relative = bucket_widths(BucketWidth {
+            absolute: 0.0,
+            relative: 0.05,
+        });
+        let s3 = SolutionResult { value: 102.0, ..S };
+        let s4 = SolutionResult { value: 104.0, ..S };
+        let s5 = SolutionResult { value: 108.0, ..S };
+        assert_eq!(
+            better_solution(&preference_priorities, &relative, &s3, &s2),
+            &s2
+        );
+        assert_eq!(
+            better_solution(&preference_priorities, &relative, &s4, &s2),
+            &s4
+        );
+        assert_eq!(
+            better_solution(&preference_priorities, &relative, &s3, &s4),
+            &s4
+        );
+        // 全ての好みで同等なら s1
+        assert_eq!(
+            better_solution(&preference_priorities, &relative, &s4, &s5),
+            &s4
+        );
+        assert_eq!(
+            better_solution(&preference_priorities, &relative, &s5, &s4),
+            &s5
+        );
+    }
+
+    #[test]
+    fn test_better_solution_with_bucket_width_for_count() {
+        let preference_priorities = Vec::from([
+            PreferenceKind::MoreHeartPop,
+            PreferenceKind::SmallerTraceNum,
+        ]);
+        // 幅2の区間に区切るので、2個と3個は同等で、1個は別の区間
+        let bucket_widths = HashMap::from([(
+            PreferenceKind::MoreHeartPop,
+            BucketWidth {
+                absolute: 2.0,
+                relative: 0.0,
+            },
+        )]);
+        let s1 = SolutionResult {
+            popped_heart_num: 3,
+            trace_coords: Vec::from([PuyoCoord { x: 0, y: 0 }, PuyoCoord { x: 1, y: 0 }]),
+            ..S
+        };
+        let s2 = SolutionResult {
+            popped_heart_num: 2,
+            trace_coords: Vec::from([PuyoCoord { x: 0, y: 0 }]),
+            ..S
+        };
+        let s3 = SolutionResult {
+            popped_heart_num: 1,
+            trace_coords: Vec::from([PuyoCoord { x: 0, y: 0 }]),
+            ..S
+        };
+        assert_eq!(
+            better_solution(&preference_priorities, &bucket_widths, &s1, &s2),
+            &s2
+        );
+        assert_eq!(
+            better_solution(&preference_priorities, &bucket_widths, &s1, &s3),
+            &s1
+        );
+    }
+
+    #[test]
+    fn test_bucket_width_is_same_bucket() {
+        let absolute = BucketWidth {
+            absolute: 1.0,
+            relative: 0.0,
+        };
+        assert!(absolute.is_same_bucket(100.0, 100.4));
+        assert!(absolute.is_same_bucket(100.4, 100.8));
+        assert!(absolute.is_same_bucket(100.0, 100.8));
+        assert!(!absolute.is_same_bucket(100.8, 101.2));
+        assert!(absolute.is_same_bucket(-0.5, -0.1));
+        assert!(!absolute.is_same_bucket(-0.1, 0.1));
+        // 差が幅よりずっと小さくても、区間の境界をまたぐ値は別の区間
+        assert!(absolute.is_same_bucket(0.0001, 0.9999));
+        assert!(!absolute.is_same_bucket(0.9999, 1.0001));
+
+        // 絶対値が 10 / 0.1 = 100 未満は幅 10、それ以上は比が 1.1 の区間
+        let mixed = BucketWidth {
+            absolute: 10.0,
+            relative: 0.1,
+        };
+        assert!(mixed.is_same_bucket(0.0, 9.9));
+        assert!(!mixed.is_same_bucket(9.9, 10.0));
+        assert!(mixed.is_same_bucket(100.0, 109.0));
+        assert!(!mixed.is_same_bucket(109.0, 111.0));
+        assert!(mixed.is_same_bucket(-100.0, -109.0));
+        assert!(!mixed.is_same_bucket(-100.0, 100.0));
+
+        let relative = BucketWidth {
+            absolute: 0.0,
+            relative: 0.05,
+        };
+        assert!(relative.is_same_bucket(0.0, 0.0));
+        assert!(!relative.is_same_bucket(0.0, 1e-9));
+        assert!(relative.is_same_bucket(100.0, 102.0));
+
+        let none = BucketWidth::default();
+        assert!(none.is_same_bucket(1.0, 1.0));
+        assert!(!none.is_same_bucket(1.0, 1.0 + 1e-9));
+    }
+
+    #[test]
+    fn test_insert_optimal_solution_with_bucket_width_is_order_independent() {
+        // Arrange
+        // A と B、B と C は差が区間の幅より小さいが、A と C の差は区間の幅より大きい。
+        // 2つの値の差だけで同等かを決めると、挿入する順序で結果が変わる
+        let exploration_target = |optimal_solution_count: u32| ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([
+                PreferenceKind::BiggerValue,
+                PreferenceKind::SmallerTraceNum,
+            ]),
+            preference_bucket_widths: HashMap::from([(
+                PreferenceKind::BiggerValue,
+                BucketWidth {
+                    absolute: 1.0,
+                    relative: 0.0,
+                },
+            )]),
+            optimal_solution_count,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+        };
+        let solution = |value: f64, trace_num: u8| SolutionResult {
+            value,
+            trace_coords: (0..trace_num).map(|x| PuyoCoord { x, y: 0 }).collect(),
+            ..S
+        };
+        let (a, b, c, d) = ((100.0, 1), (100.6, 2), (101.2, 3), (98.0, 1));
+        let orders = [
+            [a, b, c, d],
+            [a, c, b, d],
+            [b, a, c, d],
+            [b, c, a, d],
+            [c, a, b, d],
+            [c, b, a, d],
+            [d, a, b, c],
+            [d, c, b, a],
+            [b, d, c, a],
+        ];
+
+        for optimal_solution_count in 1..=4 {
+            let target = exploration_target(optimal_solution_count);
+            // C だけが別の区間で、同じ区間の A と B はなぞり数で決まる
+            let expected: Vec<f64> = [101.2, 100.0, 100.6, 98.0]
+                .into_iter()
+                .take(optimal_solution_count as usize)
+                .collect();
+
+            for order in orders.iter() {
+                // Act
+                let mut result = ExplorationResult {
+                    candidates_num: 0,
+                    optimal_solutions: Vec::new(),
+                    constraint_passed_nums: Vec::new(),
+                    is_partial: false,
+                };
+                for (value, trace_num) in order {
+                    insert_optimal_solution(&target, solution(*value, *trace_num), &mut result);
+                }
+
+                // Assert
+                let actual: Vec<f64> = result.optimal_solutions.iter().map(|s| s.value).collect();
+                assert_eq!(actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_preference_bucket_widths() {
+        let target = |kind: PreferenceKind, absolute: f64| ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([kind]),
+            preference_bucket_widths: HashMap::from([(
+                kind,
+                BucketWidth {
+                    absolute,
+                    relative: 0.0,
+                },
+            )]),
+            optimal_solution_count: 1,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+        };
+        assert!(target(PreferenceKind::BiggerValue, 0.5).validate().is_ok());
+        assert!(target(PreferenceKind::BiggerValue, -0.5)
+            .validate()
+            .is_err());
+        assert!(target(PreferenceKind::AllClear, 0.5).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_effective_damage_requires_enemy() {
+        let target = |enemy: Option<Enemy>| ExplorationTarget {
+            category: ExplorationCategory::EffectiveDamage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count: 1,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+        };
+        let enemy = |damage_reduction: f64| Enemy {
+            attr: PuyoAttr::Green,
+            hp: None,
+            damage_reduction,
+            damage_cap: None,
+        };
+        assert!(target(Some(enemy(0.2))).validate().is_ok());
+        assert!(target(None).validate().is_err());
+        assert!(target(Some(enemy(1.2))).validate().is_err());
+    }
+
     #[test]
     fn test_solve_all_traces_special_rule_1_1_modified() {
         // Arrange
@@ -1564,14 +3068,30 @@ mod tests {
             sub_attr: None,
             main_sub_ratio: None,
             counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
         };
         let environment = SimulationEnvironment {
             is_chance_mode: false,
             minimum_puyo_num_for_popping: 3,
             max_trace_num: 3,
+            min_trace_num: 0,
             trace_mode: TraceMode::Normal,
             popping_leverage: 1.0,
             chain_leverage: 7.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
         };
         let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
         let r = PuyoType::Red;
@@ -1644,7 +3164,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 3,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1663,7 +3184,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1682,7 +3204,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1701,7 +3224,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1720,7 +3244,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1749,7 +3274,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1768,7 +3294,8 @@ mod tests {
                     },
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1797,7 +3324,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1816,7 +3344,8 @@ mod tests {
                     },
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1845,7 +3374,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1864,7 +3394,8 @@ mod tests {
                     },
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1883,7 +3414,8 @@ mod tests {
                     },
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             },
         );
         assert_eq!(
@@ -1912,7 +3444,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1931,7 +3464,8 @@ mod tests {
                     },
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(s0.value, 108.99999999999999);
@@ -1968,7 +3502,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 3,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -1987,7 +3522,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2006,7 +3542,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2025,7 +3562,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2044,7 +3582,8 @@ mod tests {
                     }
                 )]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2073,7 +3612,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2092,7 +3632,8 @@ mod tests {
                     },
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2121,7 +3662,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2140,7 +3682,8 @@ mod tests {
                     },
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2169,7 +3712,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2188,7 +3732,8 @@ mod tests {
                     },
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2207,7 +3752,8 @@ mod tests {
                     },
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             },
         );
         assert_eq!(
@@ -2236,7 +3782,8 @@ mod tests {
                     )
                 ]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(
@@ -2255,7 +3802,8 @@ mod tests {
                     },
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(s0.value, 108.99999999999999);
@@ -2284,14 +3832,30 @@ mod tests {
             sub_attr: None,
             main_sub_ratio: None,
             counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
         };
         let environment = SimulationEnvironment {
             is_chance_mode: false,
             minimum_puyo_num_for_popping: 4,
             max_trace_num: 5,
+            min_trace_num: 0,
             trace_mode: TraceMode::ToBlue,
             popping_leverage: 1.0,
             chain_leverage: 10.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
         };
         let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
         let r = PuyoType::Red;
@@ -2380,6 +3944,7 @@ mod tests {
                 ]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2399,6 +3964,7 @@ mod tests {
                 ),]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2418,6 +3984,7 @@ mod tests {
                 ),]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2437,6 +4004,7 @@ mod tests {
                 ),]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2456,6 +4024,7 @@ mod tests {
                 ),]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2475,6 +4044,7 @@ mod tests {
                 ),]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2494,6 +4064,7 @@ mod tests {
                 ),]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2513,6 +4084,7 @@ mod tests {
                 ),]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2532,6 +4104,7 @@ mod tests {
                 ),]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2551,6 +4124,7 @@ mod tests {
                 ),]),
                 popped_chance_num: 0,
                 is_all_cleared: false,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2570,6 +4144,7 @@ mod tests {
                 ),]),
                 popped_chance_num: 0,
                 is_all_cleared: true,
+                targeted_special_strengths: HashMap::new(),
             }
         );
         assert_eq!(
@@ -2588,7 +4163,8 @@ mod tests {
                     }
                 ),]),
                 popped_chance_num: 0,
-                is_all_cleared: false
+                is_all_cleared: false,
+                targeted_special_strengths: HashMap::new()
             }
         );
         assert_eq!(solution.value, 68.80000000000001);
@@ -2601,30 +4177,2205 @@ mod tests {
     }
 
     #[test]
-    fn test_solve_all_traces_chance_mode_for_wild_preferring_all_clear() {
+    fn test_solve_all_traces_with_exhaustive_next_expectation() {
         // Arrange
         let exploration_target = ExplorationTarget {
             category: ExplorationCategory::Damage,
-            preference_priorities: Vec::from([
-                PreferenceKind::AllClear,
-                PreferenceKind::BiggerValue,
-                PreferenceKind::ChancePop,
-                PreferenceKind::PrismPop,
-                PreferenceKind::SmallerTraceNum,
-            ]),
-            optimal_solution_count: 2,
-            main_attr: None,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            optimal_solution_count: 1,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
+            next_expectation: Some(NextExpectation {
+                method: NextExpectationMethod::Exhaustive,
+                attr_weights: HashMap::from([(PuyoAttr::Red, 1.0), (PuyoAttr::Blue, 1.0)]),
+                sample_num: 0,
+                seed: 0,
+            }),
+        };
+        let environment = environment(1);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let o = Some(PuyoType::Ojama);
+        let e: Option<PuyoType> = None;
+        // 左端のネクストだけが不明
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [b, r, r, r, o, o, o, e],
+                [o, o, o, o, o, o, o, e],
+                [o, o, o, o, o, o, o, o],
+                [o, o, o, o, o, o, o, o],
+                [o, o, o, o, o, o, o, o],
+                [o, o, o, o, o, o, o, o],
+            ],
+            [e, g, g, g, g, g, g, g],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let explorer = SolutionExplorer::new(
+            &exploration_target,
+            &environment,
+            &boost_area_coord_set,
+            &field,
+            &next_puyos,
+        );
+
+        // Act
+        let actual = explorer.solve_all_traces();
+
+        // Assert
+        // 左上の青をなぞると、落ちてくるネクストが赤のとき (確率1/2) だけ赤4つが繋がって消える
+        assert_eq!(actual.candidates_num, 4);
+        let solution = &actual.optimal_solutions[0];
+        assert_eq!(solution.trace_coords, Vec::from([PuyoCoord { x: 0, y: 0 }]));
+        assert!(solution.chains.is_empty());
+        let statistics = solution.value_statistics.as_ref().unwrap();
+        assert!(statistics.max > 0.0);
+        assert_relative_eq!(statistics.mean, statistics.max / 2.0);
+        assert_relative_eq!(statistics.variance, statistics.mean * statistics.mean);
+        assert_eq!(statistics.min, 0.0);
+        assert_eq!(statistics.median, 0.0);
+        assert_eq!(statistics.p90, statistics.max);
+        assert_eq!(solution.value, statistics.mean);
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_constraint_on_next_expectation() {
+        // Arrange
+        let exploration_target = ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            optimal_solution_count: 1,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: Some(Vec::from([ParetoObjective::ChainCount])),
+            scoring: None,
+            constraints: Vec::from([SolutionConstraint {
+                metric: ScoringMetric::ChainCount,
+                attr: None,
+                min: Some(0.5),
+                max: None,
+            }]),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
+            next_expectation: Some(NextExpectation {
+                method: NextExpectationMethod::Exhaustive,
+                attr_weights: HashMap::from([(PuyoAttr::Red, 1.0), (PuyoAttr::Blue, 1.0)]),
+                sample_num: 0,
+                seed: 0,
+            }),
+        };
+        let environment = environment(1);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let o = Some(PuyoType::Ojama);
+        let e: Option<PuyoType> = None;
+        // 左端のネクストだけが不明
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [b, r, r, r, o, o, o, e],
+                [o, o, o, o, o, o, o, e],
+                [o, o, o, o, o, o, o, o],
+                [o, o, o, o, o, o, o, o],
+                [o, o, o, o, o, o, o, o],
+                [o, o, o, o, o, o, o, o],
+            ],
+            [e, g, g, g, g, g, g, g],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let explorer = SolutionExplorer::new(
+            &exploration_target,
+            &environment,
+            &boost_area_coord_set,
+            &field,
+            &next_puyos,
+        );
+
+        // Act
+        let actual = explorer.solve_all_traces();
+
+        // Assert
+        // 左上の青をなぞったときだけ、落ちてくるネクストが赤なら (確率1/2) 連鎖が起きるので、連鎖数の期待値は 0.5 になる
+        assert_eq!(actual.candidates_num, 4);
+        assert_eq!(actual.constraint_passed_nums, Vec::from([1]));
+        assert_eq!(actual.optimal_solutions.len(), 1);
+        let solution = &actual.optimal_solutions[0];
+        assert_eq!(solution.trace_coords, Vec::from([PuyoCoord { x: 0, y: 0 }]));
+        // 不明なマスを空とした確定的な連鎖数は 0 のまま
+        assert_eq!(solution.chain_count, 0);
+        assert_eq!(solution.objective_values, Some(Vec::from([0.5])));
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_monte_carlo_next_expectation() {
+        // Arrange
+        let exploration_target = ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            optimal_solution_count: 1,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
+            next_expectation: Some(NextExpectation {
+                method: NextExpectationMethod::MonteCarlo,
+                attr_weights: HashMap::from([(PuyoAttr::Red, 1.0), (PuyoAttr::Blue, 3.0)]),
+                sample_num: 400,
+                seed: 3,
+            }),
+        };
+        let environment = environment(1);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let y = Some(PuyoType::Yellow);
+        let o = Some(PuyoType::Ojama);
+        let e: Option<PuyoType> = None;
+        // 右端のネクストだけが不明
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, o, o, o, r, r, r, b],
+                [e, o, o, o, o, o, o, o],
+                [o, o, o, o, o, o, o, o],
+                [o, o, o, o, o, o, o, o],
+                [o, o, o, o, o, o, o, o],
+                [o, o, o, o, o, o, o, o],
+            ],
+            [y, y, y, y, y, y, y, e],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let explorer = SolutionExplorer::new(
+            &exploration_target,
+            &environment,
+            &boost_area_coord_set,
+            &field,
+            &next_puyos,
+        );
+
+        // Act
+        let actual = explorer.solve_all_traces();
+
+        // Assert
+        // 右上の青をなぞると、落ちてくるネクストが赤のとき (確率1/4) だけ赤4つが繋がって消える
+        let solution = &actual.optimal_solutions[0];
+        assert_eq!(solution.trace_coords, Vec::from([PuyoCoord { x: 7, y: 0 }]));
+        let statistics = solution.value_statistics.as_ref().unwrap();
+        let ratio = statistics.mean / statistics.max;
+        assert!(ratio > 0.2 && ratio < 0.3);
+        assert_eq!(statistics.p90, statistics.max);
+        // 同じシードなら同じ結果になる
+        assert_eq!(
+            actual.optimal_solutions,
+            explorer.solve_all_traces().optimal_solutions
+        );
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_team() {
+        // Arrange
+        let exploration_target = ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            optimal_solution_count: 1,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: Some(Team {
+                cards: Vec::from([
+                    Card {
+                        attack: 1000.0,
+                        main_attr: PuyoAttr::Red,
+                        sub_attr: None,
+                        main_sub_ratio: 0.0,
+                        leader_multiplier: 2.0,
+                        skill_multiplier: 1.0,
+                        recovery: 0.0,
+                    },
+                    Card {
+                        attack: 3000.0,
+                        main_attr: PuyoAttr::Blue,
+                        sub_attr: Some(PuyoAttr::Red),
+                        main_sub_ratio: 1.0 / 3.0,
+                        leader_multiplier: 1.0,
+                        skill_multiplier: 1.0,
+                        recovery: 0.0,
+                    },
+                ]),
+                combination_multiplier: 1.5,
+                damage_mode: DamageMode::Float,
+            }),
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
+        };
+        let environment = environment(1);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let e: Option<PuyoType> = None;
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, b, e, e, e, e],
+                [e, e, e, r, b, b, b, e],
+                [r, r, r, y, g, g, g, e],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let explorer = SolutionExplorer::new(
+            &exploration_target,
+            &environment,
+            &boost_area_coord_set,
+            &field,
+            &next_puyos,
+        );
+
+        // Act
+        let actual = explorer.solve_all_traces();
+
+        // Assert
+        // 黄色をなぞると赤4つと青4つが同時に消える (それぞれ同時消し数8の倍率1.6)
+        let solution = &actual.optimal_solutions[0];
+        assert_eq!(solution.trace_coords, Vec::from([PuyoCoord { x: 3, y: 5 }]));
+        assert_relative_eq!(solution.value, 1.6);
+        let team_damage = solution.team_damage.as_ref().unwrap();
+        // 攻撃力1000 × リーダー倍率2.0 × コンビネーション倍率1.5 × 赤1.6
+        assert_relative_eq!(team_damage.card_damages[0].main_damage, 4800.0);
+        assert_relative_eq!(team_damage.card_damages[0].sub_damage, 0.0);
+        // 攻撃力3000 × コンビネーション倍率1.5 × 青1.6
+        assert_relative_eq!(team_damage.card_damages[1].main_damage, 7200.0);
+        // 攻撃力3000 × 副属性比率1/3 × コンビネーション倍率1.5 × 赤1.6
+        assert_relative_eq!(team_damage.card_damages[1].sub_damage, 2400.0);
+        assert_relative_eq!(team_damage.total_damage, 14400.0);
+    }
+
+    #[test]
+    fn test_solve_all_traces_for_effective_damage() {
+        // Arrange
+        let damage_target = ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            optimal_solution_count: 1,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
+        };
+        let enemy = Enemy {
+            attr: PuyoAttr::Green,
+            hp: None,
+            damage_reduction: 0.2,
+            damage_cap: None,
+        };
+        let effective_target = ExplorationTarget {
+            category: ExplorationCategory::EffectiveDamage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            optimal_solution_count: 1,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: Some(enemy.clone()),
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
+        };
+        let hp_target = ExplorationTarget {
+            category: ExplorationCategory::EffectiveDamage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            optimal_solution_count: 1,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: Some(Enemy {
+                hp: Some(1.0),
+                ..enemy
+            }),
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
+        };
+        let environment = environment(1);
+        let r = Some(PuyoType::Red);
+        let y = Some(PuyoType::Yellow);
+        let w = Some(PuyoType::Prism);
+        let e: Option<PuyoType> = None;
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [r, e, e, e, e, e, e, e],
+                [r, e, e, e, e, e, e, e],
+                [r, e, e, e, e, e, e, e],
+                [y, w, e, e, e, e, e, e],
+                [r, w, e, e, e, e, e, e],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let solve = |target: &ExplorationTarget| {
+            SolutionExplorer::new(
+                target,
+                &environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            )
+            .solve_all_traces()
+        };
+
+        // Act
+        let damage = solve(&damage_target);
+        let effective = solve(&effective_target);
+        let capped = solve(&hp_target);
+
+        // Assert
+        // 黄色をなぞると赤4つとプリズム2つが消える
+        // 赤のダメージ1.3 + プリズム2つのダメージ6.0
+        assert_relative_eq!(damage.optimal_solutions[0].value, 7.3);
+        // 赤は緑に強いので2倍だが、プリズムには相性が掛からない。軽減率20%
+        let solution = &effective.optimal_solutions[0];
+        assert_eq!(solution.trace_coords, Vec::from([PuyoCoord { x: 0, y: 4 }]));
+        assert_eq!(solution.popped_prism_num, 2);
+        assert_relative_eq!(solution.value, (1.3 * 2.0 + 6.0) * 0.8);
+        // 体力を超える分は区別しない
+        assert_eq!(capped.optimal_solutions[0].value, 1.0);
+    }
+
+    #[test]
+    fn test_solve_all_traces_for_recovery() {
+        // Arrange
+        let card = |recovery: f64| Card {
+            attack: 1000.0,
+            main_attr: PuyoAttr::Red,
+            sub_attr: None,
+            main_sub_ratio: 0.0,
+            leader_multiplier: 1.0,
+            skill_multiplier: 1.0,
+            recovery,
+        };
+        let exploration_target = ExplorationTarget {
+            category: ExplorationCategory::Recovery,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            optimal_solution_count: 1,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: Some(Team {
+                cards: Vec::from([card(300.0), card(200.0)]),
+                combination_multiplier: 1.0,
+                damage_mode: DamageMode::Float,
+            }),
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
+        };
+        let environment = environment(1);
+        let r = Some(PuyoType::Red);
+        let y = Some(PuyoType::Yellow);
+        let h = Some(PuyoType::Heart);
+        let e: Option<PuyoType> = None;
+        // 弾ける赤の上にハートを置く
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, h, h, r, e, e, e, e],
+                [r, r, r, y, e, e, e, e],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let explorer = SolutionExplorer::new(
+            &exploration_target,
+            &environment,
+            &boost_area_coord_set,
+            &field,
+            &next_puyos,
+        );
+
+        // Act
+        let actual = explorer.solve_all_traces();
+
+        // Assert
+        // 回復力 (300 + 200) × ハート2個 × 1連鎖目の倍率1.0
+        let solution = &actual.optimal_solutions[0];
+        assert_eq!(solution.trace_coords, Vec::from([PuyoCoord { x: 3, y: 5 }]));
+        assert_eq!(solution.popped_heart_num, 2);
+        assert_relative_eq!(solution.value, 1000.0);
+        assert_relative_eq!(solution.recovery, 1000.0);
+    }
+
+    #[test]
+    fn test_solve_all_traces_for_chain_scale() {
+        // Arrange
+        let target = |category: ExplorationCategory| ExplorationTarget {
+            category,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            optimal_solution_count: 1,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
+        };
+        let environment = environment(1);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let e: Option<PuyoType> = None;
+        // 左下の黄色をなぞると5個消しからの2連鎖、右の黄色をなぞると6個同時消しの1連鎖
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, g, e, e, e],
+                [b, e, b, e, g, e, e, e],
+                [r, b, r, b, g, e, e, e],
+                [y, r, r, b, y, g, g, g],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let solve = |category: ExplorationCategory| {
+            SolutionExplorer::new(
+                &target(category),
+                &environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            )
+            .solve_all_traces()
+            .optimal_solutions
+            .remove(0)
+        };
+
+        // Act
+        let chain_count = solve(ExplorationCategory::ChainCount);
+        let max_simultaneous_num = solve(ExplorationCategory::MaxSimultaneousNum);
+        let total_popped_count = solve(ExplorationCategory::TotalPoppedCount);
+
+        // Assert
+        assert_eq!(
+            chain_count.trace_coords,
+            Vec::from([PuyoCoord { x: 0, y: 5 }])
+        );
+        assert_eq!(chain_count.value, 2.0);
+        assert_eq!(chain_count.chain_count, 2);
+        assert_eq!(
+            max_simultaneous_num.trace_coords,
+            Vec::from([PuyoCoord { x: 4, y: 5 }])
+        );
+        assert_eq!(max_simultaneous_num.value, 6.0);
+        assert_eq!(max_simultaneous_num.max_simultaneous_num, 6);
+        assert_eq!(max_simultaneous_num.chain_count, 1);
+        // 連鎖すると同時消し数は少なくても合計の消去数は多くなる
+        assert_eq!(
+            total_popped_count.trace_coords,
+            Vec::from([PuyoCoord { x: 0, y: 5 }])
+        );
+        assert_eq!(total_popped_count.value, 9.0);
+        assert_eq!(total_popped_count.total_popped_count, 9);
+        assert_eq!(total_popped_count.max_simultaneous_num, 5);
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_constraints() {
+        // Arrange
+        let constraint =
+            |metric: ScoringMetric, min: Option<f64>, max: Option<f64>| SolutionConstraint {
+                metric,
+                attr: None,
+                min,
+                max,
+            };
+        let target = |constraints: Vec<SolutionConstraint>| ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count: 100,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints,
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+        };
+        let chain_target = target(Vec::from([
+            constraint(ScoringMetric::ChainCount, Some(2.0), None),
+            constraint(ScoringMetric::HeartPop, None, Some(0.0)),
+            constraint(ScoringMetric::TraceNum, None, Some(3.0)),
+        ]));
+        let all_clear_target = target(Vec::from([constraint(
+            ScoringMetric::AllClear,
+            Some(1.0),
+            None,
+        )]));
+        let prism_target = target(Vec::from([constraint(
+            ScoringMetric::PrismPop,
+            Some(1.0),
+            None,
+        )]));
+        let environment = environment(5);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let y = Some(PuyoType::Yellow);
+        let h = Some(PuyoType::Heart);
+        let e: Option<PuyoType> = None;
+        // 2連鎖になるなぞり方は3通りあり、そのうち2つはハートも消える
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [b, b, e, e, e, e, e, e],
+                [r, r, b, b, e, e, e, e],
+                [y, r, r, b, h, e, e, e],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let solve = |target: &ExplorationTarget| {
+            SolutionExplorer::new(
+                target,
+                &environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            )
+            .solve_all_traces()
+        };
+
+        // Act
+        let chain = solve(&chain_target);
+        let all_clear = solve(&all_clear_target);
+        let prism = solve(&prism_target);
+
+        // Assert
+        assert_eq!(chain.optimal_solutions.len(), 1);
+        let solution = &chain.optimal_solutions[0];
+        assert_eq!(solution.trace_coords, Vec::from([PuyoCoord { x: 4, y: 5 }]));
+        assert_eq!(solution.chain_count, 2);
+        assert_eq!(solution.popped_heart_num, 0);
+        let passed_nums = &chain.constraint_passed_nums;
+        assert_eq!(passed_nums.len(), 3);
+        assert_eq!(passed_nums[0], 3);
+        assert_eq!(chain.candidates_num, 401);
+        // ハートが消える23通りを除く
+        assert_eq!(passed_nums[1], 401 - 23);
+        assert!(0 < passed_nums[2] && passed_nums[2] < chain.candidates_num);
+
+        assert_eq!(all_clear.candidates_num, chain.candidates_num);
+        assert_eq!(all_clear.constraint_passed_nums, Vec::from([2]));
+        assert_eq!(all_clear.optimal_solutions.len(), 2);
+        assert!(all_clear.optimal_solutions.iter().all(|s| s.is_all_cleared));
+        assert_eq!(
+            all_clear.optimal_solutions[0].trace_coords,
+            Vec::from([PuyoCoord { x: 0, y: 5 }])
+        );
+
+        // 条件を満たす候補が無ければ最適解も無い
+        assert_eq!(prism.candidates_num, chain.candidates_num);
+        assert_eq!(prism.constraint_passed_nums, Vec::from([0]));
+        assert!(prism.optimal_solutions.is_empty());
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_required_and_forbidden_coords() {
+        // Arrange
+        let required = PuyoCoord { x: 6, y: 5 };
+        let forbidden = PuyoCoord { x: 0, y: 5 };
+        let target = |required_coords: HashSet<PuyoCoord>, forbidden_coords: HashSet<PuyoCoord>| {
+            ExplorationTarget {
+                category: ExplorationCategory::Damage,
+                preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+                preference_bucket_widths: HashMap::new(),
+                optimal_solution_count: 10000,
+                main_attr: None,
+                sub_attr: None,
+                main_sub_ratio: None,
+                counting_bonus: None,
+                next_expectation: None,
+                team: None,
+                enemy: None,
+                pareto_objectives: None,
+                scoring: None,
+                constraints: Vec::new(),
+                required_coords,
+                forbidden_coords,
+                drawable_path_only: false,
+                trace_modes: None,
+                board_transforms: Vec::new(),
+            }
+        };
+        let restricted_target = target(HashSet::from([required]), HashSet::from([forbidden]));
+        let free_target = target(HashSet::new(), HashSet::new());
+        let environment = environment(3);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let e: Option<PuyoType> = None;
+        // 左下の黄色をなぞると赤5つ、右下の緑をなぞると青4つが消える
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [r, e, e, e, e, e, e, e],
+                [r, e, e, e, e, b, b, e],
+                [y, r, r, r, e, b, g, b],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let solve = |target: &ExplorationTarget| {
+            SolutionExplorer::new(
+                target,
+                &environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            )
+            .solve_all_traces()
+        };
+
+        // Act
+        let restricted = solve(&restricted_target);
+        let free = solve(&free_target);
+
+        // Assert
+        assert!(restricted_target.validate().is_ok());
+        assert!(free.optimal_solutions.len() < 10000);
+        let expected: Vec<&SolutionResult> = free
+            .optimal_solutions
+            .iter()
+            .filter(|s| s.trace_coords.contains(&required) && !s.trace_coords.contains(&forbidden))
+            .collect();
+        assert_eq!(
+            free.optimal_solutions[0].trace_coords,
+            Vec::from([forbidden])
+        );
+        assert!(!expected.is_empty());
+        assert_eq!(restricted.candidates_num, expected.len() as u64);
+        assert_eq!(restricted.optimal_solutions.len(), expected.len());
+        assert_eq!(
+            restricted.optimal_solutions[0].trace_coords,
+            Vec::from([required])
+        );
+        assert_eq!(restricted.optimal_solutions[0].value, expected[0].value);
+        assert!(restricted
+            .optimal_solutions
+            .iter()
+            .all(|s| s.trace_coords.contains(&required) && !s.trace_coords.contains(&forbidden)));
+
+        // 必須と禁止が重なる指定は不正
+        assert!(target(HashSet::from([required]), HashSet::from([required]))
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_drawable_path_only() {
+        // Arrange
+        let target = |drawable_path_only: bool| ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count: 100000,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+        };
+        let drawable_target = target(true);
+        let free_target = target(false);
+        let environment = environment(4);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let p = Some(PuyoType::Purple);
+        let e: Option<PuyoType> = None;
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [b, p, p, r, r, e, e, e],
+                [g, y, r, b, p, e, e, e],
+                [r, r, p, p, g, e, e, e],
+                [y, g, p, g, g, e, e, e],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let solve = |target: &ExplorationTarget| {
+            SolutionExplorer::new(
+                target,
+                &environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            )
+            .solve_all_traces()
+        };
+
+        // Act
+        let drawable = solve(&drawable_target);
+        let free = solve(&free_target);
+
+        // Assert
+        assert!(free
+            .optimal_solutions
+            .iter()
+            .all(|s| s.drawing_order.is_none()));
+        let expected: Vec<&SolutionResult> = free
+            .optimal_solutions
+            .iter()
+            .filter(|s| find_drawing_order(&s.trace_coords).is_some())
+            .collect();
+        // 4個以下でも一筆書きできない集合 (中心から3方向に伸びる形など) はある
+        assert!(expected.len() < free.optimal_solutions.len());
+        assert_eq!(drawable.candidates_num, expected.len() as u64);
+        // 2連鎖になるなぞり方は一筆書きできないので、1連鎖の6個消しが最適になる
+        assert_eq!(
+            free.optimal_solutions[0].trace_coords,
+            Vec::from([
+                PuyoCoord { x: 1, y: 3 },
+                PuyoCoord { x: 2, y: 4 },
+                PuyoCoord { x: 3, y: 3 },
+                PuyoCoord { x: 3, y: 5 },
+            ])
+        );
+        assert_eq!(free.optimal_solutions[0].chain_count, 2);
+        let solution = &drawable.optimal_solutions[0];
+        assert_eq!(solution.chain_count, 1);
+        assert_eq!(solution.max_simultaneous_num, 6);
+        assert_eq!(solution.value, expected[0].value);
+        for s in drawable.optimal_solutions.iter() {
+            let order = s.drawing_order.as_ref().unwrap();
+            assert_eq!(order.len(), s.trace_coords.len());
+            assert!(s.trace_coords.iter().all(|c| order.contains(c)));
+            assert!(order
+                .windows(2)
+                .all(|pair| pair[0].adjacent_coords().contains(&pair[1])));
+        }
+    }
+
+    #[test]
+    fn test_simulate_trace() {
+        // Arrange
+        let exploration_target = ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count: 1,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+        };
+        let environment = environment(3);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let y = Some(PuyoType::Yellow);
+        let o = Some(PuyoType::Ojama);
+        let e: Option<PuyoType> = None;
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, o, e, e, e, e, e],
+                [r, b, y, b, e, e, e, e],
+                [y, r, r, r, b, e, e, e],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let explorer = SolutionExplorer::new(
+            &exploration_target,
+            &environment,
+            &boost_area_coord_set,
+            &field,
+            &next_puyos,
+        );
+        let best = explorer.solve_all_traces().optimal_solutions.remove(0);
+        let coords = |xys: &[(u8, u8)]| -> Vec<PuyoCoord> {
+            xys.iter()
+                .map(|(x, y)| PuyoCoord { x: *x, y: *y })
+                .collect()
+        };
+
+        // Act
+        let actual = explorer.simulate_trace(&best.trace_coords);
+
+        // Assert
+        assert_eq!(best.trace_coords, coords(&[(1, 4), (2, 4), (0, 5)]));
+        assert_eq!(actual, Ok(best));
+        // 黄色だけをなぞると赤4つが消える
+        let single = explorer.simulate_trace(&coords(&[(0, 5)])).unwrap();
+        assert_eq!(single.max_simultaneous_num, 4);
+        assert_eq!(single.popped_ojama_num, 0);
+        assert!(explorer.simulate_trace(&[]).is_err());
+        // 最大なぞり数を超える
+        assert!(explorer
+            .simulate_trace(&coords(&[(0, 4), (1, 4), (2, 4), (3, 4)]))
+            .is_err());
+        // 盤面の外
+        assert!(explorer.simulate_trace(&coords(&[(7, 5), (8, 5)])).is_err());
+        // 重複
+        assert!(explorer.simulate_trace(&coords(&[(0, 5), (0, 5)])).is_err());
+        // 空のマス
+        assert!(explorer.simulate_trace(&coords(&[(0, 3), (0, 4)])).is_err());
+        // おじゃまぷよはなぞれない
+        assert!(explorer.simulate_trace(&coords(&[(2, 3), (2, 4)])).is_err());
+        // つながっていない
+        assert!(explorer.simulate_trace(&coords(&[(0, 5), (2, 5)])).is_err());
+        assert!(explorer.simulate_trace(&coords(&[(0, 5), (1, 4)])).is_ok());
+    }
+
+    #[test]
+    fn test_simulate_trace_with_trace_conditions() {
+        // Arrange
+        let exploration_target = ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count: 1,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::from([PuyoCoord { x: 1, y: 5 }, PuyoCoord { x: 2, y: 5 }]),
+            forbidden_coords: HashSet::from([PuyoCoord { x: 0, y: 5 }]),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+        };
+        let environment = SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 5,
+            min_trace_num: 3,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        };
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let r = PuyoType::Red;
+        let b = PuyoType::Blue;
+        let g = PuyoType::Green;
+        let y = PuyoType::Yellow;
+        let mut id_counter = 0;
+        let field = [
+            [b, g, y, b, g, y, b, g],
+            [g, y, b, g, y, b, g, y],
+            [y, b, g, y, b, g, y, b],
+            [b, g, y, b, g, y, b, g],
+            [r, r, b, g, y, b, g, y],
+            [r, b, g, y, b, g, y, b],
+        ]
+        .map(|row| {
+            row.map(|puyo_type| {
+                id_counter += 1;
+                Some(Puyo {
+                    id: id_counter,
+                    puyo_type,
+                })
+            })
+        });
+        let next_puyos = [r, r, r, r, r, r, r, r].map(|puyo_type| {
+            id_counter += 1;
+            Some(Puyo {
+                id: id_counter,
+                puyo_type,
+            })
+        });
+        let explorer = SolutionExplorer::new(
+            &exploration_target,
+            &environment,
+            &boost_area_coord_set,
+            &field,
+            &next_puyos,
+        );
+        let coords = |xys: &[(u8, u8)]| -> Vec<PuyoCoord> {
+            xys.iter()
+                .map(|(x, y)| PuyoCoord { x: *x, y: *y })
+                .collect()
+        };
+
+        // Act & Assert
+        // なぞり数の下限より少ない
+        assert_eq!(
+            explorer.simulate_trace(&coords(&[(1, 5), (2, 5)])),
+            Err("trace must include at least 3 puyos".to_string())
+        );
+        // 禁止の座標をなぞる
+        assert_eq!(
+            explorer.simulate_trace(&coords(&[(0, 5), (1, 5), (2, 5)])),
+            Err("(0, 5) is forbidden".to_string())
+        );
+        // 必須の座標をなぞらない
+        assert_eq!(
+            explorer.simulate_trace(&coords(&[(1, 4), (1, 5), (2, 4)])),
+            Err("(2, 5) is required".to_string())
+        );
+        // 全ての条件を満たすなぞりは、探索で見つかる最適解と同じ結果になる
+        let best = explorer.solve_all_traces().optimal_solutions.remove(0);
+        assert_eq!(explorer.simulate_trace(&best.trace_coords), Ok(best));
+        assert!(explorer
+            .simulate_trace(&coords(&[(1, 4), (1, 5), (2, 5)]))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_trace_modes() {
+        // Arrange
+        let target = |trace_modes: Option<Vec<TraceMode>>| ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count: 10,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes,
+            board_transforms: Vec::new(),
+        };
+        let trace_modes = [TraceMode::Normal, TraceMode::ToRed, TraceMode::ToBlue];
+        let sweep_target = target(Some(trace_modes.to_vec()));
+        let single_target = target(None);
+        let environment = environment(3);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let e: Option<PuyoType> = None;
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [r, r, e, e, e, e, e, e],
+                [g, g, r, r, b, b, y, y],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let solve = |target: &ExplorationTarget, environment: &SimulationEnvironment| {
+            SolutionExplorer::new(
+                target,
+                environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            )
+            .solve_all_traces()
+        };
+
+        // Act
+        let sweep = solve(&sweep_target, &environment);
+        let singles: Vec<ExplorationResult> = trace_modes
+            .iter()
+            .map(|trace_mode| {
+                let environment = SimulationEnvironment {
+                    trace_mode: *trace_mode,
+                    ..environment.clone()
+                };
+                solve(&single_target, &environment)
+            })
+            .collect();
+
+        // Assert
+        assert_eq!(
+            sweep.candidates_num,
+            singles.iter().map(|r| r.candidates_num).sum::<u64>()
+        );
+        let mut expected_values: Vec<f64> = singles
+            .iter()
+            .flat_map(|r| r.optimal_solutions.iter().map(|s| s.value))
+            .collect();
+        expected_values.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        expected_values.truncate(10);
+        let actual_values: Vec<f64> = sweep.optimal_solutions.iter().map(|s| s.value).collect();
+        assert_eq!(actual_values, expected_values);
+        // 緑2つを消すと赤4つが繋がるが、赤に変えてなぞれば赤6つが繋がる
+        assert_eq!(singles[0].optimal_solutions[0].max_simultaneous_num, 4);
+        assert_eq!(sweep.optimal_solutions[0].trace_mode, TraceMode::ToRed);
+        assert_eq!(sweep.optimal_solutions[0].max_simultaneous_num, 6);
+        assert!(sweep.optimal_solutions[0].value > singles[0].optimal_solutions[0].value);
+        // 追跡結果などはそのトレースモードで連鎖させ直したもの
+        for s in sweep.optimal_solutions.iter() {
+            let single = &singles[trace_modes.iter().position(|m| *m == s.trace_mode).unwrap()];
+            let same = single
+                .optimal_solutions
+                .iter()
+                .find(|t| t.trace_coords == s.trace_coords)
+                .unwrap();
+            assert_eq!(s, same);
+        }
+
+        assert!(target(Some(Vec::new())).validate().is_err());
+        assert!(
+            target(Some(Vec::from([TraceMode::ToRed, TraceMode::ToRed])))
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_board_transforms() {
+        // Arrange
+        let target = |board_transforms: Vec<BoardTransform>| ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count: 5,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms,
+        };
+        let transforms = Vec::from([
+            BoardTransform {
+                kind: BoardTransformKind::RowToAttr,
+                index: Some(5),
+                from_attr: None,
+                to_attr: Some(PuyoAttr::Red),
+                coords: Vec::new(),
+                count: 0,
+                seed: 0,
+            },
+            BoardTransform {
+                kind: BoardTransformKind::RandomToPlus,
+                index: None,
+                from_attr: None,
+                to_attr: None,
+                coords: Vec::new(),
+                count: 4,
+                seed: 99,
+            },
+        ]);
+        let transformed_target = target(transforms.clone());
+        let plain_target = target(Vec::new());
+        let environment = environment(3);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let e: Option<PuyoType> = None;
+        // 最下段を赤に変えると、その上の赤2つと合わせて10個が繋がる
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [r, g, b, y, r, g, b, y],
+                [g, b, y, r, b, y, g, r],
+            ],
+            [None; 8],
+        );
+        let mut transformed_board = FieldAndNext { field, next_puyos };
+        apply_board_transforms(&transforms, &mut transformed_board);
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let solve = |target: &ExplorationTarget, field: &Field, next_puyos: &NextPuyos| {
+            SolutionExplorer::new(
+                target,
+                &environment,
+                &boost_area_coord_set,
+                field,
+                next_puyos,
+            )
+            .solve_all_traces()
+        };
+
+        // Act
+        let actual = solve(&transformed_target, &field, &next_puyos);
+        let expected = solve(
+            &plain_target,
+            &transformed_board.field,
+            &transformed_board.next_puyos,
+        );
+        let untransformed = solve(&plain_target, &field, &next_puyos);
+
+        // Assert
+        assert!(transformed_target.validate().is_ok());
+        assert_eq!(actual.candidates_num, expected.candidates_num);
+        assert_eq!(actual.optimal_solutions, expected.optimal_solutions);
+        // 変換しなければ赤は4つも繋がらない
+        assert_eq!(untransformed.optimal_solutions[0].value, 0.0);
+        assert!(actual.optimal_solutions[0].value > 0.0);
+        assert!(actual.optimal_solutions[0].chains[0].simultaneous_num >= 10);
+    }
+
+    #[test]
+    fn test_solve_all_traces_in_parallel() {
+        // Arrange
+        let target = |optimal_solution_count: u32,
+                      pareto_objectives: Option<Vec<ParetoObjective>>,
+                      trace_modes: Option<Vec<TraceMode>>| ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([
+                PreferenceKind::BiggerValue,
+                PreferenceKind::SmallerTraceNum,
+            ]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives,
+            scoring: None,
+            constraints: Vec::from([SolutionConstraint {
+                metric: ScoringMetric::ChainCount,
+                attr: None,
+                min: Some(1.0),
+                max: None,
+            }]),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes,
+            board_transforms: Vec::new(),
+        };
+        let targets = [
+            target(1, None, None),
+            target(100, None, None),
+            target(
+                0,
+                Some(Vec::from([
+                    ParetoObjective::Value,
+                    ParetoObjective::ChainCount,
+                    ParetoObjective::TraceNum,
+                ])),
+                None,
+            ),
+            target(
+                10,
+                None,
+                Some(Vec::from([TraceMode::Normal, TraceMode::ToRed])),
+            ),
+        ];
+        let environment = environment(4);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let e: Option<PuyoType> = None;
+        // 最大の値になるなぞり方が複数あり、3連鎖になるなぞり方もある
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [r, g, r, g, r, g, g, g],
+                [g, b, y, y, g, y, b, b],
+                [y, y, b, b, r, g, r, y],
+                [r, g, b, g, y, y, y, b],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+
+        for target in targets.iter() {
+            let explorer = SolutionExplorer::new(
+                target,
+                &environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            );
+
+            // Act
+            let serial = explorer.solve_all_traces();
+            let parallel = explorer.solve_all_traces_in_parallel();
+
+            // Assert
+            assert!(!serial.optimal_solutions.is_empty());
+            assert_eq!(parallel.candidates_num, serial.candidates_num);
+            assert_eq!(
+                parallel.constraint_passed_nums,
+                serial.constraint_passed_nums
+            );
+            assert_eq!(parallel.optimal_solutions, serial.optimal_solutions);
+        }
+    }
+
+    #[test]
+    fn test_solve_all_traces_in_parallel_with_bucket_widths() {
+        // Arrange
+        // 同じ形の並びを繰り返した盤面で、値が等しい解や同じ区間に入る解が多数ある
+        let target = |preference_priorities: Vec<PreferenceKind>,
+                      absolute: f64,
+                      relative: f64,
+                      optimal_solution_count: u32| ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities,
+            preference_bucket_widths: HashMap::from([(
+                PreferenceKind::BiggerValue,
+                BucketWidth { absolute, relative },
+            )]),
+            optimal_solution_count,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+        };
+        let by_value = || Vec::from([PreferenceKind::BiggerValue]);
+        let by_value_and_trace_num =
+            || Vec::from([PreferenceKind::BiggerValue, PreferenceKind::SmallerTraceNum]);
+        // 2つの値の差だけで同等かを決めると、直列と並列で結果が変わる組み合わせを含む
+        let targets = [
+            target(by_value(), 0.5, 0.0, 1),
+            target(by_value(), 0.5, 0.0, 3),
+            target(by_value(), 1.5, 0.0, 1),
+            target(by_value(), 1.5, 0.1, 1),
+            target(by_value_and_trace_num(), 0.0, 0.2, 2),
+            target(by_value_and_trace_num(), 1.0, 0.1, 3),
+        ];
+        let environment = SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 3,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        };
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let r = PuyoType::Red;
+        let b = PuyoType::Blue;
+        let g = PuyoType::Green;
+        let y = PuyoType::Yellow;
+        let mut id_counter = 0;
+        let field = [
+            [r, r, b, b, g, g, y, y],
+            [b, b, g, g, y, y, r, r],
+            [r, r, b, b, g, g, y, y],
+            [b, b, g, g, y, y, r, r],
+            [g, g, y, y, r, r, b, b],
+            [y, y, r, r, b, b, g, g],
+        ]
+        .map(|row| {
+            row.map(|puyo_type| {
+                id_counter += 1;
+                Some(Puyo {
+                    id: id_counter,
+                    puyo_type,
+                })
+            })
+        });
+        let next_puyos = [r, b, g, y, r, b, g, y].map(|puyo_type| {
+            id_counter += 1;
+            Some(Puyo {
+                id: id_counter,
+                puyo_type,
+            })
+        });
+
+        for target in targets.iter() {
+            let explorer = SolutionExplorer::new(
+                target,
+                &environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            );
+
+            // Act
+            let serial = explorer.solve_all_traces();
+            let parallel = explorer.solve_all_traces_in_parallel();
+
+            // Assert
+            assert_eq!(
+                serial.optimal_solutions.len(),
+                target.optimal_solution_count as usize
+            );
+            assert_eq!(parallel.candidates_num, serial.candidates_num);
+            assert_eq!(parallel.optimal_solutions, serial.optimal_solutions);
+        }
+    }
+
+    #[test]
+    fn test_solve_shards_and_merge() {
+        // Arrange
+        let target =
+            |trace_modes: Option<Vec<TraceMode>>, drawable_path_only: bool| ExplorationTarget {
+                category: ExplorationCategory::Damage,
+                preference_priorities: Vec::from([
+                    PreferenceKind::BiggerValue,
+                    PreferenceKind::SmallerTraceNum,
+                ]),
+                preference_bucket_widths: HashMap::new(),
+                optimal_solution_count: 100,
+                main_attr: None,
+                sub_attr: None,
+                main_sub_ratio: None,
+                counting_bonus: None,
+                next_expectation: None,
+                team: None,
+                enemy: None,
+                pareto_objectives: None,
+                scoring: None,
+                constraints: Vec::from([SolutionConstraint {
+                    metric: ScoringMetric::ChainCount,
+                    attr: None,
+                    min: Some(1.0),
+                    max: None,
+                }]),
+                required_coords: HashSet::new(),
+                forbidden_coords: HashSet::new(),
+                drawable_path_only,
+                trace_modes,
+                board_transforms: Vec::new(),
+            };
+        // 一筆書きに限るときも、見積もりの候補数は実際に評価した候補数と一致する
+        let targets = [
+            target(None, false),
+            target(
+                Some(Vec::from([TraceMode::Normal, TraceMode::ToRed])),
+                false,
+            ),
+            target(None, true),
+        ];
+        let environment = environment(4);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let p = Some(PuyoType::Purple);
+        let e: Option<PuyoType> = None;
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [p, g, p, b, r, p, p, b],
+                [g, r, p, r, p, r, p, b],
+                [y, p, y, g, y, p, y, g],
+                [g, b, b, b, r, p, g, p],
+                [y, g, y, g, p, r, r, p],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+
+        for target in targets.iter() {
+            let explorer = SolutionExplorer::new(
+                target,
+                &environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            );
+            let serial = explorer.solve_all_traces();
+
+            for shard_num in [1, 3, 7] {
+                // Act
+                let shards = explorer.split_into_shards(shard_num);
+                let parts: Vec<ExplorationResult> = shards
+                    .iter()
+                    .map(|shard| explorer.solve_shard(shard))
+                    .collect();
+                let merged = merge_exploration_results(target, parts).unwrap();
+
+                // Assert
+                assert_eq!(shards.len(), shard_num as usize);
+                let estimated_total: u64 = shards.iter().map(|s| s.estimated_candidates_num).sum();
+                assert_eq!(estimated_total, serial.candidates_num);
+                // 見積もりの最大の分担でも、均等に分けた量の1.5倍を超えない
+                let max_estimated = shards
+                    .iter()
+                    .map(|s| s.estimated_candidates_num)
+                    .max()
+                    .unwrap();
+                assert!(max_estimated * 2 <= estimated_total * 3 / shard_num as u64);
+
+                assert_eq!(merged.candidates_num, serial.candidates_num);
+                assert_eq!(merged.constraint_passed_nums, serial.constraint_passed_nums);
+                assert_eq!(merged.optimal_solutions, serial.optimal_solutions);
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_exploration_results_rejects_mismatched_parts() {
+        // Arrange
+        let target = ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count: 10,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: Some(Vec::from([
+                ParetoObjective::Value,
+                ParetoObjective::TraceNum,
+            ])),
+            scoring: None,
+            constraints: Vec::from([SolutionConstraint {
+                metric: ScoringMetric::ChainCount,
+                attr: None,
+                min: Some(1.0),
+                max: None,
+            }]),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+        };
+        let part = |constraint_passed_nums: Vec<u64>, objective_values: Option<Vec<f64>>| {
+            ExplorationResult {
+                candidates_num: 1,
+                optimal_solutions: Vec::from([SolutionResult {
+                    objective_values,
+                    ..S
+                }]),
+                constraint_passed_nums,
+                is_partial: false,
+            }
+        };
+        let merge = |parts: Vec<ExplorationResult>| merge_exploration_results(&target, parts);
+
+        // Act & Assert
+        let merged = merge(Vec::from([
+            part(Vec::from([1]), Some(Vec::from([1.0, 1.0]))),
+            part(Vec::from([1]), Some(Vec::from([2.0, 2.0]))),
+        ]))
+        .unwrap();
+        assert_eq!(merged.candidates_num, 2);
+        assert_eq!(merged.constraint_passed_nums, Vec::from([2]));
+        assert_eq!(merged.optimal_solutions.len(), 2);
+
+        // 条件ごとの候補数の数が条件の数と違えば、黙って切り詰めずにエラーにする
+        let expected_passed_nums_error =
+            "constraint_passed_nums of exploration results must have 1 elements";
+        assert_eq!(
+            merge(Vec::from([part(Vec::new(), Some(Vec::from([1.0, 2.0])))])).unwrap_err(),
+            expected_passed_nums_error
+        );
+        assert_eq!(
+            merge(Vec::from([part(
+                Vec::from([1, 1]),
+                Some(Vec::from([1.0, 2.0]))
+            )]))
+            .unwrap_err(),
+            expected_passed_nums_error
+        );
+
+        // パレート最適解を求めるときは、目的の値が無いか数が違う解があればエラーにする
+        let expected_objective_values_error =
+            "objective_values of optimal solutions must have 2 elements";
+        assert_eq!(
+            merge(Vec::from([
+                part(Vec::from([1]), Some(Vec::from([1.0, 2.0]))),
+                part(Vec::from([1]), None),
+            ]))
+            .unwrap_err(),
+            expected_objective_values_error
+        );
+        assert_eq!(
+            merge(Vec::from([part(Vec::from([1]), Some(Vec::from([1.0])))])).unwrap_err(),
+            expected_objective_values_error
+        );
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_progress() {
+        // Arrange
+        let exploration_target = |drawable_path_only: bool| ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([
+                PreferenceKind::BiggerValue,
+                PreferenceKind::SmallerTraceNum,
+            ]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count: 10,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only,
+            trace_modes: Some(Vec::from([TraceMode::Normal, TraceMode::ToRed])),
+            board_transforms: Vec::new(),
+        };
+        let environment = environment(4);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let e: Option<PuyoType> = None;
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [b, g, y, y, b, b, r, y],
+                [r, r, b, r, g, g, b, r],
+                [g, b, g, y, g, r, g, b],
+                [r, r, b, y, y, g, y, r],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        for drawable_path_only in [false, true] {
+            let exploration_target = exploration_target(drawable_path_only);
+            let explorer = SolutionExplorer::new(
+                &exploration_target,
+                &environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            );
+            let serial = explorer.solve_all_traces();
+
+            // Act
+            let mut progresses: Vec<ExplorationProgress> = Vec::new();
+            let actual = explorer
+                .solve_all_traces_with_progress(&AtomicBool::new(false), |progress| {
+                    progresses.push(*progress)
+                });
+
+            // Assert
+            assert!(!actual.is_partial);
+            assert_eq!(actual.candidates_num, serial.candidates_num);
+            assert_eq!(actual.optimal_solutions, serial.optimal_solutions);
+            // 候補数を数えている間も、探索を始める前から通知される
+            let counting_num = progresses
+                .iter()
+                .take_while(|p| p.visited_candidates_num == 0)
+                .count();
+            assert!(counting_num > 1);
+            assert!(progresses[..counting_num]
+                .windows(2)
+                .all(|p| p[0].estimated_candidates_num <= p[1].estimated_candidates_num));
+            assert!(progresses
+                .windows(2)
+                .all(|p| p[0].visited_candidates_num <= p[1].visited_candidates_num));
+            // 一筆書きに限るときも、見積もりの候補数は実際に評価した候補数と一致する
+            let last = progresses.last().unwrap();
+            assert_eq!(last.visited_candidates_num, serial.candidates_num);
+            assert_eq!(last.estimated_candidates_num, serial.candidates_num);
+        }
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_progress_cancelled() {
+        // Arrange
+        let exploration_target = ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count: 1,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+        };
+        let environment = environment(4);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let p = Some(PuyoType::Purple);
+        // 探索の単位の途中で打ち切れるように、左上から隙間なく埋める
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [g, p, p, p, r, g, r, p],
+                [g, y, b, y, b, p, r, y],
+                [p, g, r, y, r, b, b, y],
+                [g, g, r, g, g, r, p, r],
+                [r, y, p, y, y, g, b, p],
+                [r, y, b, r, y, p, g, p],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let explorer = SolutionExplorer::new(
+            &exploration_target,
+            &environment,
+            &boost_area_coord_set,
+            &field,
+            &next_puyos,
+        );
+        let cancel_flag = AtomicBool::new(false);
+        let mut estimated_candidates_num = 0;
+
+        // Act
+        // 全体の1割ほど探索したところで打ち切る
+        let actual = explorer.solve_all_traces_with_progress(&cancel_flag, |progress| {
+            estimated_candidates_num = progress.estimated_candidates_num;
+            if progress.visited_candidates_num > 0
+                && progress.visited_candidates_num * 10 >= progress.estimated_candidates_num
+            {
+                cancel_flag.store(true, Ordering::Relaxed);
+            }
+        });
+
+        // Assert
+        assert!(actual.is_partial);
+        assert!(actual.candidates_num > 0);
+        assert!(actual.candidates_num < estimated_candidates_num);
+        // 打ち切るまでに探索した候補の中での最適解は、その範囲だけを探索したものと同じ
+        assert_eq!(actual.optimal_solutions.len(), 1);
+        let solution = &actual.optimal_solutions[0];
+        let simulated = explorer.simulate_trace(&solution.trace_coords).unwrap();
+        assert_eq!(simulated.value, solution.value);
+
+        // 候補数を数えている間に打ち切ると、探索せずに返す
+        let cancel_flag = AtomicBool::new(false);
+        let mut progress_num = 0;
+        let actual = explorer.solve_all_traces_with_progress(&cancel_flag, |_| {
+            progress_num += 1;
+            cancel_flag.store(true, Ordering::Relaxed);
+        });
+        assert!(actual.is_partial);
+        assert_eq!(actual.candidates_num, 0);
+        assert_eq!(progress_num, 1);
+
+        // 探索の単位の途中でも打ち切る
+        let unit = ShardUnit {
+            trace_mode: TraceMode::Normal,
+            start_index: 0,
+            next_candidate_index: Some(0),
+        };
+        let mut result = explorer.new_exploration_result();
+        explorer.solve_shard_unit(&unit, &mut result, Some(&AtomicBool::new(false)));
+        assert!(result.candidates_num > 1);
+        let mut result = explorer.new_exploration_result();
+        explorer.solve_shard_unit(&unit, &mut result, Some(&AtomicBool::new(true)));
+        assert_eq!(result.candidates_num, 0);
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_min_trace_num() {
+        // Arrange
+        let exploration_target = ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            preference_bucket_widths: HashMap::new(),
+            optimal_solution_count: 10000,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+        };
+        let mut environment = environment(3);
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let e: Option<PuyoType> = None;
+        // 左下の黄色1つをなぞると赤5つが消えるが、3個なぞるなら右下の3つをなぞって青4つを消すしかない
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [r, e, e, e, e, e, e, e],
+                [r, e, e, e, e, e, e, e],
+                [r, e, e, e, b, b, b, e],
+                [y, r, r, e, y, g, y, b],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let solve = |environment: &SimulationEnvironment| {
+            SolutionExplorer::new(
+                &exploration_target,
+                environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            )
+            .solve_all_traces()
+        };
+        let free = solve(&environment);
+        // ちょうど3個なぞる
+        environment.min_trace_num = 3;
+
+        // Act
+        let exact = solve(&environment);
+
+        // Assert
+        let expected: Vec<&SolutionResult> = free
+            .optimal_solutions
+            .iter()
+            .filter(|s| s.trace_coords.len() == 3)
+            .collect();
+        assert!(!expected.is_empty() && expected.len() < free.optimal_solutions.len());
+        assert_eq!(exact.candidates_num, expected.len() as u64);
+        assert!(exact
+            .optimal_solutions
+            .iter()
+            .all(|s| s.trace_coords.len() == 3));
+        assert_eq!(
+            free.optimal_solutions[0].trace_coords,
+            Vec::from([PuyoCoord { x: 0, y: 5 }])
+        );
+        assert_eq!(
+            exact.optimal_solutions[0].trace_coords,
+            Vec::from([
+                PuyoCoord { x: 4, y: 5 },
+                PuyoCoord { x: 5, y: 5 },
+                PuyoCoord { x: 6, y: 5 },
+            ])
+        );
+        assert_eq!(exact.optimal_solutions[0].value, expected[0].value);
+    }
+
+    #[test]
+    fn test_solve_all_traces_for_pareto_front() {
+        // Arrange
+        let target = |pareto_objectives: Option<Vec<ParetoObjective>>| ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([
+                PreferenceKind::BiggerValue,
+                PreferenceKind::SmallerTraceNum,
+            ]),
+            optimal_solution_count: 1,
+            main_attr: None,
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
+        };
+        let environment = SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 5,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        };
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let r = Some(PuyoType::Red);
+        let b = Some(PuyoType::Blue);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let p = Some(PuyoType::Purple);
+        let e: Option<PuyoType> = None;
+        let mut id_counter = 0;
+        let field = [
+            [p, b, e, g, g, g, e, e],
+            [p, g, p, p, r, r, r, y],
+            [g, p, g, b, p, b, y, b],
+            [b, g, b, p, b, r, b, r],
+            [y, b, y, b, r, p, r, r],
+            [y, y, g, r, b, b, y, y],
+        ]
+        .map(|row| {
+            row.map(|option| {
+                option.map(|puyo_type| {
+                    id_counter += 1;
+                    Puyo {
+                        id: id_counter,
+                        puyo_type,
+                    }
+                })
+            })
+        });
+        let next_puyos: [Option<Puyo>; 8] = [None; 8];
+        let objectives = Vec::from([ParetoObjective::Value, ParetoObjective::TraceNum]);
+        let lexicographic_target = target(None);
+        let pareto_target = target(Some(objectives.clone()));
+        let solve = |target: &ExplorationTarget| {
+            SolutionExplorer::new(
+                target,
+                &environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            )
+            .solve_all_traces()
+        };
+
+        // Act
+        let lexicographic = solve(&lexicographic_target);
+        let pareto = solve(&pareto_target);
+
+        // Assert
+        assert_eq!(pareto.candidates_num, lexicographic.candidates_num);
+        let front = &pareto.optimal_solutions;
+        assert!(front.len() > 1);
+        assert_eq!(
+            front[0].trace_coords,
+            lexicographic.optimal_solutions[0].trace_coords
+        );
+        for s in front.iter() {
+            assert_eq!(
+                s.objective_values,
+                Some(Vec::from([s.value, s.trace_coords.len() as f64]))
+            );
+        }
+        for pair in front.windows(2) {
+            // 価値が下がる代わりになぞり数が減っていく
+            assert!(pair[0].value > pair[1].value);
+            assert!(pair[0].trace_coords.len() > pair[1].trace_coords.len());
+            assert_eq!(
+                compare_objective_values(
+                    &objectives,
+                    pair[0].objective_values.as_ref().unwrap(),
+                    pair[1].objective_values.as_ref().unwrap()
+                ),
+                None
+            );
+        }
+        assert!(front.iter().all(|s| s.puyo_tracking.is_some()));
+    }
+
+    #[test]
+    fn test_solve_all_traces_with_scoring() {
+        // Arrange
+        let target = |scoring: Option<Scoring>| ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([PreferenceKind::BiggerValue]),
+            optimal_solution_count: 2,
+            main_attr: Some(PuyoAttr::Red),
+            sub_attr: None,
+            main_sub_ratio: None,
+            counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
+        };
+        let term = |metric: ScoringMetric, weight: f64| ScoringTerm {
+            metric,
+            attr: None,
+            weight,
+        };
+        // ダメージ + 5000 × ハート消し数 − 1000 × なぞり数
+        let scoring_target = target(Some(Scoring {
+            terms: Vec::from([
+                term(ScoringMetric::Damage, 1.0),
+                term(ScoringMetric::HeartPop, 5000.0),
+                term(ScoringMetric::TraceNum, -1000.0),
+            ]),
+        }));
+        let damage_target = target(None);
+        let environment = environment(2);
+        let r = Some(PuyoType::Red);
+        let g = Some(PuyoType::Green);
+        let y = Some(PuyoType::Yellow);
+        let h = Some(PuyoType::Heart);
+        let e: Option<PuyoType> = None;
+        let FieldAndNext { field, next_puyos } = board(
+            [
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, e, e, e, e],
+                [e, e, e, e, r, e, e, e],
+                [e, h, e, r, r, e, e, e],
+                [r, r, r, y, g, r, r, r],
+            ],
+            [None; 8],
+        );
+        let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
+        let solve = |target: &ExplorationTarget| {
+            SolutionExplorer::new(
+                target,
+                &environment,
+                &boost_area_coord_set,
+                &field,
+                &next_puyos,
+            )
+            .solve_all_traces()
+        };
+
+        // Act
+        let damage = solve(&damage_target);
+        let scored = solve(&scoring_target);
+
+        // Assert
+        assert!(scoring_target.validate().is_ok());
+        // ダメージだけなら黄色と緑をなぞるのが最大
+        let two_traces = Vec::from([PuyoCoord { x: 3, y: 5 }, PuyoCoord { x: 4, y: 5 }]);
+        let damage_solution = &damage.optimal_solutions[0];
+        assert_eq!(damage_solution.trace_coords, two_traces);
+        assert_relative_eq!(damage_solution.value, 1.75);
+        // どちらもハートを1つ消すので、なぞり数が少ない方が上になる
+        let solution = &scored.optimal_solutions[0];
+        assert_eq!(solution.trace_coords, Vec::from([PuyoCoord { x: 3, y: 5 }]));
+        assert_eq!(solution.popped_heart_num, 1);
+        assert_relative_eq!(solution.value, 1.0 + 5000.0 - 1000.0);
+        let second = &scored.optimal_solutions[1];
+        assert_eq!(second.trace_coords, two_traces);
+        assert_relative_eq!(second.value, 1.75 + 5000.0 - 2000.0);
+    }
+
+    #[test]
+    fn test_solve_all_traces_chance_mode_for_wild_preferring_all_clear() {
+        // Arrange
+        let exploration_target = ExplorationTarget {
+            category: ExplorationCategory::Damage,
+            preference_priorities: Vec::from([
+                PreferenceKind::AllClear,
+                PreferenceKind::BiggerValue,
+                PreferenceKind::ChancePop,
+                PreferenceKind::PrismPop,
+                PreferenceKind::SmallerTraceNum,
+            ]),
+            optimal_solution_count: 2,
+            main_attr: None,
             sub_attr: None,
             main_sub_ratio: None,
             counting_bonus: None,
+            next_expectation: None,
+            team: None,
+            enemy: None,
+            pareto_objectives: None,
+            scoring: None,
+            constraints: Vec::new(),
+            required_coords: HashSet::new(),
+            forbidden_coords: HashSet::new(),
+            drawable_path_only: false,
+            trace_modes: None,
+            board_transforms: Vec::new(),
+            preference_bucket_widths: HashMap::new(),
         };
         let environment = SimulationEnvironment {
             is_chance_mode: true,
             minimum_puyo_num_for_popping: 4,
             max_trace_num: 48,
+            min_trace_num: 0,
             trace_mode: TraceMode::Normal,
             popping_leverage: 5.0,
             chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
         };
         let boost_area_coord_set: HashSet<PuyoCoord> = HashSet::new();
         let r = Some(PuyoType::Red);
@@ -2669,6 +6420,10 @@ mod tests {
         let actual = explorer.solve_all_traces();
 
         // Arrange
+        let t = |id: i32, x: u8, y: u8| TrackedPuyo {
+            id,
+            coord: PuyoCoord { x, y },
+        };
         assert_eq!(actual.candidates_num, 13507);
         assert_eq!(actual.optimal_solutions.len(), 2);
         assert_eq!(
@@ -2707,6 +6462,7 @@ mod tests {
                         ]),
                         popped_chance_num: 0,
                         is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new(),
                     },
                     Chain {
                         chain_num: 2,
@@ -2732,7 +6488,8 @@ mod tests {
                             )
                         ]),
                         popped_chance_num: 0,
-                        is_all_cleared: false
+                        is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new()
                     },
                     Chain {
                         chain_num: 3,
@@ -2758,7 +6515,8 @@ mod tests {
                             )
                         ]),
                         popped_chance_num: 0,
-                        is_all_cleared: false
+                        is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new()
                     },
                     Chain {
                         chain_num: 4,
@@ -2784,7 +6542,8 @@ mod tests {
                             )
                         ]),
                         popped_chance_num: 0,
-                        is_all_cleared: true
+                        is_all_cleared: true,
+                        targeted_special_strengths: HashMap::new()
                     }
                 ]),
                 value: 66.35,
@@ -2794,6 +6553,80 @@ mod tests {
                 popped_ojama_num: 0,
                 popped_kata_num: 0,
                 is_all_cleared: true,
+                recovery: 0.0,
+                chain_count: 4,
+                max_simultaneous_num: 12,
+                total_popped_count: 40,
+                puyo_tracking: Some(PuyoTracking {
+                    traced_puyos: Vec::from([
+                        t(17, 3, 2),
+                        t(26, 4, 3),
+                        t(33, 3, 4),
+                        t(35, 5, 4),
+                        t(40, 2, 5)
+                    ]),
+                    popped_puyos: Vec::from([
+                        Vec::from([
+                            t(29, 7, 3),
+                            t(30, 0, 4),
+                            t(34, 4, 4),
+                            t(27, 5, 4),
+                            t(36, 6, 4),
+                            t(37, 7, 4),
+                            t(38, 0, 5),
+                            t(39, 1, 5),
+                            t(32, 2, 5)
+                        ]),
+                        Vec::from([
+                            t(1, 0, 2),
+                            t(6, 0, 3),
+                            t(15, 1, 3),
+                            t(8, 2, 3),
+                            t(9, 3, 3),
+                            t(25, 3, 4),
+                            t(18, 4, 4),
+                            t(19, 5, 4),
+                            t(28, 6, 4),
+                            t(21, 7, 4),
+                            t(42, 4, 5),
+                            t(43, 5, 5)
+                        ]),
+                        Vec::from([
+                            t(7, 1, 3),
+                            t(14, 0, 4),
+                            t(23, 1, 4),
+                            t(16, 2, 4),
+                            t(3, 3, 4),
+                            t(4, 4, 4),
+                            t(5, 5, 4),
+                            t(20, 6, 4),
+                            t(13, 7, 4),
+                            t(44, 6, 5),
+                            t(45, 7, 5)
+                        ]),
+                        Vec::from([
+                            t(2, 1, 4),
+                            t(22, 0, 5),
+                            t(31, 1, 5),
+                            t(24, 2, 5),
+                            t(41, 3, 5),
+                            t(10, 4, 5),
+                            t(11, 5, 5),
+                            t(12, 6, 5)
+                        ]),
+                    ]),
+                    surviving_puyos: Vec::new(),
+                    remaining_next_puyos: [None; 8],
+                }),
+                board_after_chains: Some(FieldAndNext {
+                    field: [[None; 8]; 6],
+                    next_puyos: [None; 8],
+                }),
+                value_statistics: None,
+                team_damage: None,
+                objective_values: None,
+                drawing_order: None,
+                trace_mode: TraceMode::Normal,
             }
         );
         assert_eq!(
@@ -2821,7 +6654,8 @@ mod tests {
                             }
                         )]),
                         popped_chance_num: 0,
-                        is_all_cleared: false
+                        is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new()
                     },
                     Chain {
                         chain_num: 2,
@@ -2837,7 +6671,8 @@ mod tests {
                             }
                         )]),
                         popped_chance_num: 0,
-                        is_all_cleared: false
+                        is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new()
                     },
                     Chain {
                         chain_num: 3,
@@ -2853,7 +6688,8 @@ mod tests {
                             }
                         )]),
                         popped_chance_num: 0,
-                        is_all_cleared: false
+                        is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new()
                     },
                     Chain {
                         chain_num: 4,
@@ -2869,7 +6705,8 @@ mod tests {
                             }
                         )]),
                         popped_chance_num: 0,
-                        is_all_cleared: false
+                        is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new()
                     },
                     Chain {
                         chain_num: 5,
@@ -2885,7 +6722,8 @@ mod tests {
                             }
                         )]),
                         popped_chance_num: 0,
-                        is_all_cleared: false
+                        is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new()
                     },
                     Chain {
                         chain_num: 6,
@@ -2901,7 +6739,8 @@ mod tests {
                             },
                         )]),
                         popped_chance_num: 0,
-                        is_all_cleared: false
+                        is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new()
                     },
                     Chain {
                         chain_num: 7,
@@ -2917,7 +6756,8 @@ mod tests {
                             },
                         ),]),
                         popped_chance_num: 0,
-                        is_all_cleared: false
+                        is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new()
                     },
                     Chain {
                         chain_num: 8,
@@ -2933,7 +6773,8 @@ mod tests {
                             },
                         )]),
                         popped_chance_num: 0,
-                        is_all_cleared: false
+                        is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new()
                     },
                     Chain {
                         chain_num: 9,
@@ -2949,7 +6790,8 @@ mod tests {
                             },
                         ),]),
                         popped_chance_num: 0,
-                        is_all_cleared: false
+                        is_all_cleared: false,
+                        targeted_special_strengths: HashMap::new()
                     },
                     Chain {
                         chain_num: 10,
@@ -2965,7 +6807,8 @@ mod tests {
                             },
                         ),]),
                         popped_chance_num: 0,
-                        is_all_cleared: true
+                        is_all_cleared: true,
+                        targeted_special_strengths: HashMap::new()
                     },
                 ]),
                 value: 22.3,
@@ -2974,7 +6817,43 @@ mod tests {
                 popped_prism_num: 0,
                 popped_ojama_num: 0,
                 popped_kata_num: 0,
-                is_all_cleared: true
+                is_all_cleared: true,
+                recovery: 0.0,
+                chain_count: 10,
+                max_simultaneous_num: 4,
+                total_popped_count: 40,
+                puyo_tracking: Some(PuyoTracking {
+                    traced_puyos: Vec::from([
+                        t(10, 4, 1),
+                        t(17, 3, 2),
+                        t(26, 4, 3),
+                        t(33, 3, 4),
+                        t(42, 4, 5)
+                    ]),
+                    popped_puyos: Vec::from([
+                        Vec::from([t(9, 3, 3), t(25, 3, 4), t(18, 4, 4), t(35, 5, 4)]),
+                        Vec::from([t(29, 7, 3), t(27, 5, 4), t(36, 6, 4), t(37, 7, 4)]),
+                        Vec::from([t(19, 5, 4), t(28, 6, 4), t(21, 7, 4), t(43, 5, 5)]),
+                        Vec::from([t(20, 6, 4), t(13, 7, 4), t(44, 6, 5), t(45, 7, 5)]),
+                        Vec::from([t(41, 3, 5), t(34, 4, 5), t(11, 5, 5), t(12, 6, 5)]),
+                        Vec::from([t(40, 2, 5), t(3, 3, 5), t(4, 4, 5), t(5, 5, 5)]),
+                        Vec::from([t(30, 0, 4), t(38, 0, 5), t(39, 1, 5), t(32, 2, 5)]),
+                        Vec::from([t(1, 0, 2), t(6, 0, 3), t(15, 1, 3), t(8, 2, 3)]),
+                        Vec::from([t(7, 1, 3), t(14, 0, 4), t(23, 1, 4), t(16, 2, 4)]),
+                        Vec::from([t(2, 1, 4), t(22, 0, 5), t(31, 1, 5), t(24, 2, 5)]),
+                    ]),
+                    surviving_puyos: Vec::new(),
+                    remaining_next_puyos: [None; 8],
+                }),
+                board_after_chains: Some(FieldAndNext {
+                    field: [[None; 8]; 6],
+                    next_puyos: [None; 8],
+                }),
+                value_statistics: None,
+                team_damage: None,
+                objective_values: None,
+                drawing_order: None,
+                trace_mode: TraceMode::Normal,
             }
         );
     }
@@ -0,0 +1,517 @@
+use num_derive::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+use crate::{
+    chain::Chain,
+    chain_helper::sum_colored_attr_damage_with_affinity,
+    enemy::Enemy,
+    exact_damage::{calc_exact_attr_damage_with_affinity, mul_floor, to_ratio},
+    puyo_attr::{is_colored_attr, PuyoAttr},
+    simulation_environment::SimulationEnvironment,
+};
+
+/** デッキに入れられるカードの最大枚数 */
+pub const MAX_CARD_NUM: usize = 5;
+
+fn default_multiplier() -> f64 {
+    1.0
+}
+
+/** ダメージの計算方法 */
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+    FromPrimitive,
+    ToPrimitive,
+    Serialize_repr,
+    Deserialize_repr,
+)]
+#[repr(u8)]
+pub enum DamageMode {
+    /** 浮動小数点のまま計算する */
+    #[default]
+    Float = 1,
+    /** ゲームと同じように各段階で小数点以下を切り捨てて整数で計算する */
+    Exact = 2,
+}
+
+/** デッキのカード1枚分の攻撃に関する情報 */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Card {
+    /** 攻撃力 */
+    pub attack: f64,
+    /** 主属性 (5色のいずれか) */
+    pub main_attr: PuyoAttr,
+    /** 副属性 */
+    pub sub_attr: Option<PuyoAttr>,
+    /** 副属性 / 主属性 のダメージ率 (1/3か1) */
+    #[serde(default)]
+    pub main_sub_ratio: f64,
+    /** リーダースキルによる攻撃倍率 */
+    #[serde(default = "default_multiplier")]
+    pub leader_multiplier: f64,
+    /** スキル (自分や他のカードのもの) による攻撃倍率 */
+    #[serde(default = "default_multiplier")]
+    pub skill_multiplier: f64,
+    /** 回復力 */
+    #[serde(default)]
+    pub recovery: f64,
+}
+
+/** デッキ (最大5枚のカード) */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Team {
+    /** カードのリスト */
+    pub cards: Vec<Card>,
+    /** コンビネーションボーナスによる攻撃倍率 (全カード共通) */
+    #[serde(default = "default_multiplier")]
+    pub combination_multiplier: f64,
+    /** ダメージの計算方法 */
+    #[serde(default)]
+    pub damage_mode: DamageMode,
+}
+
+impl Team {
+    /// カードの枚数や属性、攻撃力や倍率が正しいか検証する。
+    /// DamageMode::Exact では倍率を有理数として扱えること (exact_damage::to_ratio を参照)。
+    pub fn validate(&self) -> Result<(), String> {
+        let is_valid = |v: f64| v >= 0.0 && v.is_finite();
+        if self.cards.len() > MAX_CARD_NUM {
+            return Err(format!("cards must not exceed {}", MAX_CARD_NUM));
+        }
+        if !is_valid(self.combination_multiplier) {
+            return Err("combination_multiplier must be non-negative and finite".to_string());
+        }
+        for card in self.cards.iter() {
+            if !is_colored_attr(card.main_attr) || !card.sub_attr.is_none_or(is_colored_attr) {
+                return Err(
+                    "main_attr and sub_attr of cards must be colored attributes".to_string()
+                );
+            }
+            if ![
+                card.attack,
+                card.main_sub_ratio,
+                card.leader_multiplier,
+                card.skill_multiplier,
+                card.recovery,
+            ]
+            .into_iter()
+            .all(is_valid)
+            {
+                return Err(
+                    "attack, multipliers and recovery of cards must be non-negative and finite"
+                        .to_string(),
+                );
+            }
+        }
+        if self.damage_mode == DamageMode::Exact {
+            for card in self.cards.iter() {
+                for multiplier in [
+                    card.main_sub_ratio,
+                    card.leader_multiplier,
+                    card.skill_multiplier,
+                    self.combination_multiplier,
+                ] {
+                    to_ratio(multiplier)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/** カード1枚分のダメージ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardDamage {
+    /** 主属性による攻撃のダメージ */
+    pub main_damage: f64,
+    /** 副属性による攻撃のダメージ */
+    pub sub_damage: f64,
+}
+
+/** デッキ全体のダメージ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamDamage {
+    /** 各カードのダメージ。インデックスは Team::cards のインデックスに対応する。 */
+    pub card_damages: Vec<CardDamage>,
+    /** 全カードの総ダメージ */
+    pub total_damage: f64,
+}
+
+/// 連鎖情報からデッキのダメージを計算する。
+/// 連鎖情報の strength は攻撃力1のときのダメージなので、それに各カードの攻撃力と倍率を掛ける。
+/// DamageMode::Exact のときは連鎖情報と環境から各倍率を求め直し、段階ごとに切り捨てた整数で計算する。
+pub fn calc_team_damage(
+    team: &Team,
+    environment: &SimulationEnvironment,
+    chains: &Vec<Chain>,
+) -> TeamDamage {
+    calc_team_damage_with_affinity(team, environment, chains, &|_| 1.0)
+}
+
+/// 敵との相性を考慮してデッキのダメージを計算する。相性は各属性の攻撃の、色属性による部分にだけ掛ける。
+/// 軽減率や上限は考慮しない。
+pub fn calc_team_damage_against(
+    team: &Team,
+    environment: &SimulationEnvironment,
+    chains: &Vec<Chain>,
+    enemy: &Enemy,
+) -> TeamDamage {
+    calc_team_damage_with_affinity(team, environment, chains, &|attr| enemy.calc_affinity(attr))
+}
+
+fn calc_team_damage_with_affinity(
+    team: &Team,
+    environment: &SimulationEnvironment,
+    chains: &Vec<Chain>,
+    affinity: &dyn Fn(PuyoAttr) -> f64,
+) -> TeamDamage {
+    let card_damages: Vec<CardDamage> = team
+        .cards
+        .iter()
+        .map(|card| match team.damage_mode {
+            DamageMode::Float => calc_card_damage(team, card, chains, affinity),
+            DamageMode::Exact => calc_exact_card_damage(team, card, environment, chains, affinity)
+                .expect(
+                    "factors are validated by SimulationEnvironment::validate and Team::validate",
+                ),
+        })
+        .collect();
+    let total_damage = card_damages
+        .iter()
+        .map(|d| d.main_damage + d.sub_damage)
+        .sum();
+
+    TeamDamage {
+        card_damages,
+        total_damage,
+    }
+}
+
+fn calc_card_damage(
+    team: &Team,
+    card: &Card,
+    chains: &Vec<Chain>,
+    affinity: &dyn Fn(PuyoAttr) -> f64,
+) -> CardDamage {
+    let attack =
+        card.attack * card.leader_multiplier * card.skill_multiplier * team.combination_multiplier;
+    let attr_damage =
+        |attr: PuyoAttr| sum_colored_attr_damage_with_affinity(chains, attr, affinity(attr));
+    let main_damage = attack * attr_damage(card.main_attr);
+    let sub_damage = match card.sub_attr {
+        Some(sub_attr) => attack * card.main_sub_ratio * attr_damage(sub_attr),
+        None => 0.0,
+    };
+    CardDamage {
+        main_damage,
+        sub_damage,
+    }
+}
+
+fn calc_exact_card_damage(
+    team: &Team,
+    card: &Card,
+    environment: &SimulationEnvironment,
+    chains: &Vec<Chain>,
+    affinity: &dyn Fn(PuyoAttr) -> f64,
+) -> Result<CardDamage, String> {
+    let mut attack = card.attack.floor() as i64;
+    for multiplier in [
+        card.leader_multiplier,
+        card.skill_multiplier,
+        team.combination_multiplier,
+    ] {
+        attack = mul_floor(attack, to_ratio(multiplier)?);
+    }
+    let attr_damage = |attr: PuyoAttr| {
+        calc_exact_attr_damage_with_affinity(environment, attack, chains, attr, affinity(attr))
+    };
+    let main_damage = attr_damage(card.main_attr)?;
+    let sub_damage = match card.sub_attr {
+        Some(sub_attr) => mul_floor(attr_damage(sub_attr)?, to_ratio(card.main_sub_ratio)?),
+        None => 0,
+    };
+    Ok(CardDamage {
+        main_damage: main_damage as f64,
+        sub_damage: sub_damage as f64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chain::AttributeChain, trace_mode::TraceMode};
+    use std::collections::HashMap;
+
+    fn environment() -> SimulationEnvironment {
+        SimulationEnvironment {
+            is_chance_mode: false,
+            minimum_puyo_num_for_popping: 4,
+            max_trace_num: 5,
+            min_trace_num: 0,
+            trace_mode: TraceMode::Normal,
+            popping_leverage: 1.0,
+            chain_leverage: 1.0,
+            popping_coefficient: None,
+            chain_coefficient_table: None,
+            special_puyo_strengths: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_calc_team_damage() {
+        // Arrange
+        let chains: Vec<Chain> = Vec::from([Chain {
+            chain_num: 1,
+            simultaneous_num: 8,
+            boost_count: 0,
+            puyo_tsukai_count: 8,
+            attributes: HashMap::from([
+                (
+                    PuyoAttr::Red,
+                    AttributeChain {
+                        strength: 1.5,
+                        popped_count: 4,
+                        separated_blocks_num: 1,
+                    },
+                ),
+                (
+                    PuyoAttr::Blue,
+                    AttributeChain {
+                        strength: 2.0,
+                        popped_count: 4,
+                        separated_blocks_num: 1,
+                    },
+                ),
+            ]),
+            popped_chance_num: 0,
+            is_all_cleared: false,
+            targeted_special_strengths: HashMap::new(),
+        }]);
+        let team = Team {
+            cards: Vec::from([
+                Card {
+                    attack: 1000.0,
+                    main_attr: PuyoAttr::Red,
+                    sub_attr: Some(PuyoAttr::Blue),
+                    main_sub_ratio: 0.5,
+                    leader_multiplier: 2.0,
+                    skill_multiplier: 1.5,
+                    recovery: 0.0,
+                },
+                Card {
+                    attack: 2000.0,
+                    main_attr: PuyoAttr::Green,
+                    sub_attr: None,
+                    main_sub_ratio: 0.0,
+                    leader_multiplier: 1.0,
+                    skill_multiplier: 1.0,
+                    recovery: 0.0,
+                },
+                Card {
+                    attack: 500.0,
+                    main_attr: PuyoAttr::Blue,
+                    sub_attr: Some(PuyoAttr::Yellow),
+                    main_sub_ratio: 1.0,
+                    leader_multiplier: 1.0,
+                    skill_multiplier: 1.0,
+                    recovery: 0.0,
+                },
+            ]),
+            combination_multiplier: 1.2,
+            damage_mode: DamageMode::Float,
+        };
+
+        // Act
+        let actual = calc_team_damage(&team, &environment(), &chains);
+
+        // Assert
+        assert_eq!(actual.card_damages.len(), 3);
+        assert_relative_eq!(actual.card_damages[0].main_damage, 5400.0);
+        assert_relative_eq!(actual.card_damages[0].sub_damage, 3600.0);
+        assert_relative_eq!(actual.card_damages[1].main_damage, 0.0);
+        assert_relative_eq!(actual.card_damages[1].sub_damage, 0.0);
+        assert_relative_eq!(actual.card_damages[2].main_damage, 1200.0);
+        assert_relative_eq!(actual.card_damages[2].sub_damage, 0.0);
+        assert_relative_eq!(actual.total_damage, 10200.0);
+    }
+
+    #[test]
+    fn test_calc_team_damage_exact() {
+        // Arrange
+        let chains: Vec<Chain> = Vec::from([Chain {
+            chain_num: 1,
+            simultaneous_num: 8,
+            boost_count: 0,
+            puyo_tsukai_count: 8,
+            attributes: HashMap::from([
+                (
+                    PuyoAttr::Red,
+                    AttributeChain {
+                        strength: 1.6,
+                        popped_count: 4,
+                        separated_blocks_num: 1,
+                    },
+                ),
+                (
+                    PuyoAttr::Blue,
+                    AttributeChain {
+                        strength: 1.6,
+                        popped_count: 4,
+                        separated_blocks_num: 1,
+                    },
+                ),
+            ]),
+            popped_chance_num: 0,
+            is_all_cleared: false,
+            targeted_special_strengths: HashMap::new(),
+        }]);
+        let team = Team {
+            cards: Vec::from([Card {
+                attack: 1234.0,
+                main_attr: PuyoAttr::Red,
+                sub_attr: Some(PuyoAttr::Blue),
+                main_sub_ratio: 1.0 / 3.0,
+                leader_multiplier: 1.5,
+                skill_multiplier: 1.0,
+                recovery: 0.0,
+            }]),
+            combination_multiplier: 1.0,
+            damage_mode: DamageMode::Exact,
+        };
+
+        // Act
+        let actual = calc_team_damage(&team, &environment(), &chains);
+
+        // Assert
+        // 攻撃力 1234 × 1.5 = 1851, floor(1851 × 1.6) = 2961, 副属性 floor(2961 / 3) = 987
+        // (浮動小数点のままだと 2961.6 と 987.2 になる)
+        assert_eq!(
+            actual.card_damages,
+            Vec::from([CardDamage {
+                main_damage: 2961.0,
+                sub_damage: 987.0,
+            }])
+        );
+        assert_eq!(actual.total_damage, 3948.0);
+    }
+
+    #[test]
+    fn test_calc_team_damage_against() {
+        // Arrange
+        let chains: Vec<Chain> = Vec::from([Chain {
+            chain_num: 1,
+            simultaneous_num: 4,
+            boost_count: 0,
+            puyo_tsukai_count: 5,
+            attributes: HashMap::from([
+                (
+                    PuyoAttr::Red,
+                    AttributeChain {
+                        strength: 1.0,
+                        popped_count: 4,
+                        separated_blocks_num: 1,
+                    },
+                ),
+                (
+                    PuyoAttr::Prism,
+                    AttributeChain {
+                        strength: 3.0,
+                        popped_count: 1,
+                        separated_blocks_num: 0,
+                    },
+                ),
+            ]),
+            popped_chance_num: 0,
+            is_all_cleared: false,
+            targeted_special_strengths: HashMap::new(),
+        }]);
+        let team = |damage_mode: DamageMode| Team {
+            cards: Vec::from([Card {
+                attack: 1000.0,
+                main_attr: PuyoAttr::Red,
+                sub_attr: None,
+                main_sub_ratio: 0.0,
+                leader_multiplier: 1.0,
+                skill_multiplier: 1.0,
+                recovery: 0.0,
+            }]),
+            combination_multiplier: 1.0,
+            damage_mode,
+        };
+        let enemy = Enemy {
+            attr: PuyoAttr::Green,
+            hp: None,
+            damage_reduction: 0.0,
+            damage_cap: None,
+        };
+
+        // Act & Assert
+        // 赤は緑に強いので赤の部分だけ2倍になり、プリズムの部分は変わらない: 1000 × (1.0 × 2 + 3.0)
+        for damage_mode in [DamageMode::Float, DamageMode::Exact] {
+            let actual =
+                calc_team_damage_against(&team(damage_mode), &environment(), &chains, &enemy);
+            assert_eq!(actual.total_damage, 5000.0);
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let team = || Team {
+            cards: Vec::from([Card {
+                attack: 1000.0,
+                main_attr: PuyoAttr::Red,
+                sub_attr: None,
+                main_sub_ratio: 0.0,
+                leader_multiplier: 1.0,
+                skill_multiplier: 1.0,
+                recovery: 0.0,
+            }]),
+            combination_multiplier: 1.0,
+            damage_mode: DamageMode::Exact,
+        };
+        assert!(team().validate().is_ok());
+
+        let mut invalid = team();
+        invalid.combination_multiplier = f64::NAN;
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = team();
+        invalid.cards[0].attack = -1.0;
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = team();
+        invalid.cards[0].skill_multiplier = f64::INFINITY;
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = team();
+        invalid.cards = vec![invalid.cards[0].clone(); 6];
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = team();
+        invalid.cards[0].main_attr = PuyoAttr::Heart;
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = team();
+        invalid.cards[0].sub_attr = Some(PuyoAttr::Prism);
+        assert!(invalid.validate().is_err());
+
+        // 整数で計算するときは倍率を有理数として扱えなければならない
+        let mut invalid = team();
+        invalid.cards[0].leader_multiplier = 1e-10;
+        assert!(invalid.validate().is_err());
+        invalid.damage_mode = DamageMode::Float;
+        assert!(invalid.validate().is_ok());
+
+        let mut valid = team();
+        valid.cards[0].sub_attr = Some(PuyoAttr::Blue);
+        valid.cards = vec![valid.cards[0].clone(); 5];
+        assert!(valid.validate().is_ok());
+    }
+}
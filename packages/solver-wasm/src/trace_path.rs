@@ -0,0 +1,187 @@
+//! なぞりの検証と、なぞる順番 (一筆書きの経路) を求める。
+//!
+//! 探索はなぞる座標を集合として列挙するが、実際には直前の座標に隣接する座標へ順に指を動かしてなぞる。
+//! 隣接は PuyoCoord::adjacent_coords と同じく斜めも含む8方向とする。
+
+use crate::{puyo::Field, puyo_coord::PuyoCoord, puyo_type::is_traceable_type};
+
+/// 集合内で隣接している座標の数を数える。
+fn count_neighbors(coord: &PuyoCoord, field_bits: u64) -> usize {
+    coord
+        .adjacent_coords()
+        .iter()
+        .filter(|c| field_bits & (1 << c.index()) != 0)
+        .count()
+}
+
+/// path の末尾から、まだ通っていない座標を深さ優先でたどって全て通る経路を探す。
+fn extend_path(path: &mut Vec<PuyoCoord>, visited_bits: u64, field_bits: u64) -> bool {
+    if visited_bits == field_bits {
+        return true;
+    }
+    let last = *path.last().unwrap();
+    for next in last.adjacent_coords() {
+        let bit = 1 << next.index();
+        if field_bits & bit == 0 || visited_bits & bit != 0 {
+            continue;
+        }
+        path.push(next);
+        if extend_path(path, visited_bits | bit, field_bits) {
+            return true;
+        }
+        path.pop();
+    }
+    false
+}
+
+/// 座標の集合が隣接関係でつながっているかどうか。空集合はつながっているものとする。
+pub fn is_connected(coords: &[PuyoCoord]) -> bool {
+    let Some(first) = coords.first() else {
+        return true;
+    };
+    let field_bits = coords.iter().fold(0_u64, |acc, c| acc | (1 << c.index()));
+    let mut visited_bits: u64 = 1 << first.index();
+    let mut stack = Vec::from([*first]);
+    while let Some(coord) = stack.pop() {
+        for next in coord.adjacent_coords() {
+            let bit = 1 << next.index();
+            if field_bits & bit != 0 && visited_bits & bit == 0 {
+                visited_bits |= bit;
+                stack.push(next);
+            }
+        }
+    }
+    visited_bits == field_bits
+}
+
+/// なぞりが盤面に対して正しいか検証する。
+/// 盤面内の重複の無い座標で、全てなぞれるぷよがあり、隣接関係でつながっていて、
+/// なぞり数が1以上かつ max_trace_num 以下でなければならない。
+pub fn validate_trace(
+    field: &Field,
+    trace_coords: &[PuyoCoord],
+    max_trace_num: u32,
+) -> Result<(), String> {
+    if trace_coords.is_empty() {
+        return Err("trace must not be empty".to_string());
+    }
+    if trace_coords.len() > max_trace_num as usize {
+        return Err(format!("trace must not exceed {max_trace_num} puyos"));
+    }
+    let mut field_bits: u64 = 0;
+    for coord in trace_coords {
+        if !PuyoCoord::is_valid_xy(coord.x, coord.y) {
+            return Err(format!("({}, {}) is out of the field", coord.x, coord.y));
+        }
+        let bit = 1 << coord.index();
+        if field_bits & bit != 0 {
+            return Err(format!("({}, {}) is traced twice", coord.x, coord.y));
+        }
+        field_bits |= bit;
+        match field[coord.y as usize][coord.x as usize] {
+            Some(p) if is_traceable_type(p.puyo_type) => {}
+            _ => return Err(format!("({}, {}) has no traceable puyo", coord.x, coord.y)),
+        }
+    }
+    if !is_connected(trace_coords) {
+        return Err("trace must be connected".to_string());
+    }
+    Ok(())
+}
+
+/// 座標の集合を全て1回ずつ通る、隣接する座標をたどる経路 (なぞる順番) を求める。
+/// 経路が無ければ None を返す。
+/// 始点は coords の順に試し、隣接座標は adjacent_coords の順にたどるので、結果は常に同じになる。
+pub fn find_drawing_order(coords: &[PuyoCoord]) -> Option<Vec<PuyoCoord>> {
+    if coords.is_empty() {
+        return Some(Vec::new());
+    }
+    let field_bits = coords.iter().fold(0_u64, |acc, c| acc | (1 << c.index()));
+
+    // 隣接が1つしか無い座標は経路の端にしかなれない。
+    // 3つ以上あれば経路は無く、あればそこから始めれば十分。
+    let ends: Vec<PuyoCoord> = coords
+        .iter()
+        .filter(|c| count_neighbors(c, field_bits) <= 1)
+        .cloned()
+        .collect();
+    if ends.len() > 2
+        || (coords.len() > 1 && ends.iter().any(|c| count_neighbors(c, field_bits) == 0))
+    {
+        return None;
+    }
+    let starts = if ends.is_empty() { coords } else { &ends[..] };
+
+    for start in starts {
+        let mut path = Vec::from([*start]);
+        if extend_path(&mut path, 1 << start.index(), field_bits) {
+            return Some(path);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coords(xys: &[(u8, u8)]) -> Vec<PuyoCoord> {
+        xys.iter()
+            .map(|(x, y)| PuyoCoord { x: *x, y: *y })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_drawing_order_of_line() {
+        // 端から始まる
+        assert_eq!(
+            find_drawing_order(&coords(&[(1, 0), (0, 0), (2, 0)])),
+            Some(coords(&[(0, 0), (1, 0), (2, 0)]))
+        );
+        // 斜めにもつながる
+        assert_eq!(
+            find_drawing_order(&coords(&[(0, 0), (1, 1), (2, 2)])),
+            Some(coords(&[(0, 0), (1, 1), (2, 2)]))
+        );
+        assert_eq!(find_drawing_order(&[]), Some(Vec::new()));
+        assert_eq!(
+            find_drawing_order(&coords(&[(3, 3)])),
+            Some(coords(&[(3, 3)]))
+        );
+    }
+
+    #[test]
+    fn test_find_drawing_order_of_star() {
+        // 中心から3方向に伸びた形は一筆で書けない
+        assert_eq!(
+            find_drawing_order(&coords(&[
+                (2, 2),
+                (0, 2),
+                (1, 2),
+                (3, 2),
+                (4, 2),
+                (2, 3),
+                (2, 4)
+            ])),
+            None
+        );
+        // 中心に隣接が4つあっても、端同士が隣接していれば書ける
+        let order = find_drawing_order(&coords(&[(1, 1), (0, 0), (1, 0), (2, 0), (1, 2)])).unwrap();
+        assert_eq!(order.len(), 5);
+        for pair in order.windows(2) {
+            assert!(pair[0].adjacent_coords().contains(&pair[1]));
+        }
+    }
+
+    #[test]
+    fn test_is_connected() {
+        assert!(is_connected(&[]));
+        assert!(is_connected(&coords(&[(0, 0), (1, 1), (3, 1), (2, 2)])));
+        assert!(!is_connected(&coords(&[(0, 0), (1, 1), (3, 1)])));
+    }
+
+    #[test]
+    fn test_find_drawing_order_of_disconnected() {
+        assert_eq!(find_drawing_order(&coords(&[(0, 0), (2, 0)])), None);
+    }
+}